@@ -0,0 +1,461 @@
+use alloc::{vec, vec::Vec};
+
+use super::{
+    addressing::{PhysAddr, KERNEL_BASE},
+    paging::PAGE_SIZE,
+};
+use crate::{logging, mm::ALLOCATOR, sync::Once};
+
+/// System description tables found by `init`
+static TABLES: Once<Vec<Table>> = Once::new();
+
+const RSDP_SIGNATURE: &[u8; 8] = b"RSD PTR ";
+/// Real mode segment of the Extended BIOS Data Area, stored in the BIOS Data Area
+const EBDA_SEGMENT: u64 = 0x40E;
+/// Read-only BIOS area, the other place where the RSDP can be
+const BIOS_AREA: (u64, u64) = (0xE0000, 0x100000);
+const HEADER_SIZE: usize = 36;
+/// Size of the RSDP since ACPI 2.0, which added the XSDT address
+const RSDP_V2_SIZE: usize = 36;
+/// Larger lengths in a table header are taken as firmware bugs
+const MAX_TABLE_SIZE: usize = 1024 * 1024;
+
+// MADT entry types
+const MADT_LOCAL_APIC: u8 = 0;
+const MADT_IO_APIC: u8 = 1;
+const MADT_INTERRUPT_OVERRIDE: u8 = 2;
+const MADT_LOCAL_APIC_ADDRESS: u8 = 5;
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+/// Copies physical memory, which can span several pages, into a buffer
+fn read_physical(addr: u64, buffer: &mut [u8]) {
+    let mut done = 0;
+    while done < buffer.len() {
+        let phys = addr + done as u64;
+        let size = ((PAGE_SIZE - phys % PAGE_SIZE) as usize).min(buffer.len() - done);
+        let virt = ALLOCATOR
+            .lock()
+            .page_allocator
+            .as_mut()
+            .unwrap()
+            .map_physical(PhysAddr::new(phys), 0);
+
+        unsafe {
+            core::ptr::copy_nonoverlapping(virt.as_ptr::<u8>(), buffer[done..].as_mut_ptr(), size)
+        };
+        done += size;
+    }
+}
+
+/// The bytes of a valid ACPI structure add up to 0
+fn checksum_valid(data: &[u8]) -> bool {
+    data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)) == 0
+}
+
+/// A system description table copied from the firmware memory
+pub struct Table {
+    data: Vec<u8>,
+}
+
+impl Table {
+    /// Copies the table at a physical address, None if its length or checksum is wrong
+    fn load(addr: u64) -> Option<Table> {
+        let mut header = [0; HEADER_SIZE];
+        read_physical(addr, &mut header);
+
+        let length = read_u32(&header, 4) as usize;
+        if !(HEADER_SIZE..=MAX_TABLE_SIZE).contains(&length) {
+            warn!(
+                "ACPI table {} at {:#x} has an invalid length of {} bytes",
+                core::str::from_utf8(&header[..4]).unwrap_or("?"),
+                addr,
+                length
+            );
+            return None;
+        }
+        let mut data = vec![0; length];
+        read_physical(addr, &mut data);
+
+        if !checksum_valid(&data) {
+            warn!(
+                "ACPI table {} at {:#x} has a wrong checksum",
+                core::str::from_utf8(&data[..4]).unwrap_or("?"),
+                addr
+            );
+            return None;
+        }
+        Some(Table { data })
+    }
+
+    pub fn signature(&self) -> &[u8] {
+        &self.data[..4]
+    }
+
+    pub fn revision(&self) -> u8 {
+        self.data[8]
+    }
+
+    /// Contents following the common header
+    pub fn body(&self) -> &[u8] {
+        &self.data[HEADER_SIZE..]
+    }
+}
+
+/// Checks the signature and checksums of a possible RSDP. ACPI 1.0 ones are 20 bytes,
+/// later revisions have a length and a checksum covering the whole structure. Only its
+/// first 36 bytes are defined, so only they are checked whatever the length says
+fn rsdp_valid(addr: u64) -> bool {
+    let rsdp = unsafe { core::slice::from_raw_parts((KERNEL_BASE + addr) as *const u8, 20) };
+    if !rsdp.starts_with(RSDP_SIGNATURE) || !checksum_valid(rsdp) {
+        return false;
+    }
+    if rsdp[15] < 2 {
+        return true;
+    }
+
+    let length = unsafe { *((KERNEL_BASE + addr + 20) as *const u32) } as usize;
+    length >= RSDP_V2_SIZE
+        && checksum_valid(unsafe {
+            core::slice::from_raw_parts((KERNEL_BASE + addr) as *const u8, RSDP_V2_SIZE)
+        })
+}
+
+/// Looks for the Root System Description Pointer in the first KiB of the EBDA,
+/// then in the BIOS area. Both are in the low memory mapped with the kernel
+fn find_rsdp() -> Option<&'static [u8]> {
+    let ebda = unsafe { *((KERNEL_BASE + EBDA_SEGMENT) as *const u16) as u64 } << 4;
+    let areas = [(ebda, ebda + 1024), BIOS_AREA];
+
+    let addr = areas
+        .into_iter()
+        .filter(|&(start, _)| start != 0)
+        .flat_map(|(start, end)| (start..end).step_by(16))
+        .find(|&addr| rsdp_valid(addr))?;
+
+    let size = if unsafe { *((KERNEL_BASE + addr + 15) as *const u8) } < 2 {
+        20
+    } else {
+        RSDP_V2_SIZE
+    };
+    Some(unsafe { core::slice::from_raw_parts((KERNEL_BASE + addr) as *const u8, size) })
+}
+
+/// Finds the ACPI tables listed by the XSDT, or the RSDT on ACPI 1.0 systems.
+/// Tables with a wrong checksum are left out. Needs the kernel page allocator
+pub fn init() {
+    let Some(rsdp) = find_rsdp() else {
+        warn!("No ACPI tables found");
+        return;
+    };
+
+    // The XSDT has 64-bit table addresses, the RSDT 32-bit ones
+    let xsdt_address = if rsdp.len() >= RSDP_V2_SIZE {
+        read_u64(rsdp, 24)
+    } else {
+        0
+    };
+    let (root, entry_size) = match xsdt_address {
+        0 => (Table::load(read_u32(rsdp, 16) as u64), 4),
+        xsdt => (Table::load(xsdt), 8),
+    };
+    let Some(root) = root else {
+        warn!("Invalid ACPI root table");
+        return;
+    };
+
+    let addresses: Vec<u64> = root
+        .body()
+        .chunks_exact(entry_size)
+        .map(|entry| match entry_size {
+            4 => read_u32(entry, 0) as u64,
+            _ => read_u64(entry, 0),
+        })
+        .collect();
+    let tables: Vec<Table> = addresses.into_iter().filter_map(Table::load).collect();
+
+    for table in &tables {
+        debug!(
+            "ACPI table {}",
+            core::str::from_utf8(table.signature()).unwrap_or("?")
+        );
+    }
+    TABLES.set(tables);
+}
+
+/// Returns the first table with the given signature
+pub fn find_table(signature: &[u8; 4]) -> Option<&'static Table> {
+    TABLES
+        .get()?
+        .iter()
+        .find(|table| table.signature() == signature)
+}
+
+/// A processor and its local APIC
+#[derive(Debug, Clone, Copy)]
+pub struct LocalApicEntry {
+    pub processor_id: u8,
+    pub apic_id: u8,
+    /// The processor can be used
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct IoApicEntry {
+    pub id: u8,
+    pub address: u64,
+    /// First global system interrupt handled by this I/O APIC
+    pub gsi_base: u32,
+}
+
+/// An ISA IRQ connected to a different global system interrupt than its number,
+/// or with a different polarity or trigger mode than ISA ones
+#[derive(Debug, Clone, Copy)]
+pub struct InterruptOverride {
+    pub irq: u8,
+    pub gsi: u32,
+    /// MPS INTI flags: bits 0-1 are the polarity, bits 2-3 the trigger mode
+    pub flags: u16,
+}
+
+#[derive(Debug)]
+pub struct Madt {
+    pub local_apic_address: u64,
+    pub local_apics: Vec<LocalApicEntry>,
+    pub io_apics: Vec<IoApicEntry>,
+    pub overrides: Vec<InterruptOverride>,
+}
+
+/// Multiple APIC Description Table, listing the interrupt controllers
+pub fn madt() -> Option<Madt> {
+    let body = find_table(b"APIC")?.body();
+
+    let mut madt = Madt {
+        local_apic_address: read_u32(body, 0) as u64,
+        local_apics: Vec::new(),
+        io_apics: Vec::new(),
+        overrides: Vec::new(),
+    };
+
+    // Variable length entries follow the address and flags
+    let mut offset = 8;
+    while offset + 2 <= body.len() {
+        let (kind, length) = (body[offset], body[offset + 1] as usize);
+        if length < 2 || offset + length > body.len() {
+            break;
+        }
+        let entry = &body[offset..offset + length];
+
+        match kind {
+            MADT_LOCAL_APIC if length >= 8 => madt.local_apics.push(LocalApicEntry {
+                processor_id: entry[2],
+                apic_id: entry[3],
+                enabled: read_u32(entry, 4) & 1 != 0,
+            }),
+            MADT_IO_APIC if length >= 12 => madt.io_apics.push(IoApicEntry {
+                id: entry[2],
+                address: read_u32(entry, 4) as u64,
+                gsi_base: read_u32(entry, 8),
+            }),
+            MADT_INTERRUPT_OVERRIDE if length >= 10 => madt.overrides.push(InterruptOverride {
+                irq: entry[3],
+                gsi: read_u32(entry, 4),
+                flags: read_u16(entry, 8),
+            }),
+            MADT_LOCAL_APIC_ADDRESS if length >= 12 => madt.local_apic_address = read_u64(entry, 4),
+            _ => {}
+        }
+        offset += length;
+    }
+
+    Some(madt)
+}
+
+/// Location of a register block in a firmware table
+#[derive(Debug, Clone, Copy)]
+pub struct GenericAddress {
+    /// 0 for memory, 1 for I/O ports, 2 for the PCI configuration space
+    pub address_space: u8,
+    pub bit_width: u8,
+    pub bit_offset: u8,
+    pub access_size: u8,
+    pub address: u64,
+}
+
+impl GenericAddress {
+    pub const SYSTEM_MEMORY: u8 = 0;
+    pub const SYSTEM_IO: u8 = 1;
+
+    fn read(data: &[u8], offset: usize) -> GenericAddress {
+        GenericAddress {
+            address_space: data[offset],
+            bit_width: data[offset + 1],
+            bit_offset: data[offset + 2],
+            access_size: data[offset + 3],
+            address: read_u64(data, offset + 4),
+        }
+    }
+}
+
+/// Fixed ACPI Description Table, with the power management registers.
+/// Fields added by later ACPI revisions are None in shorter tables
+#[derive(Debug, Clone, Copy)]
+pub struct Fadt {
+    pub dsdt: u64,
+    pub sci_interrupt: u16,
+    /// Port the ACPI enable and disable values are written to, 0 if ACPI is always enabled
+    pub smi_command: u32,
+    pub acpi_enable: u8,
+    pub acpi_disable: u8,
+    pub pm1a_control: u32,
+    pub pm1b_control: u32,
+    pub pm_timer: u32,
+    /// Index of the century in the CMOS RTC, 0 if there is none
+    pub century: u8,
+    /// IA-PC boot architecture flags, bit 1 is set when there is an 8042 keyboard controller
+    pub boot_flags: u16,
+    pub flags: u32,
+    pub reset_register: Option<GenericAddress>,
+    pub reset_value: u8,
+}
+
+impl Fadt {
+    /// Bit of `flags` set when the reset register is supported
+    pub const RESET_REG_SUP: u32 = 1 << 10;
+    pub const BOOT_8042: u16 = 1 << 1;
+}
+
+/// Body offsets of the FADT fields
+const FADT_DSDT: usize = 4;
+const FADT_SCI_INTERRUPT: usize = 10;
+const FADT_SMI_COMMAND: usize = 12;
+const FADT_ACPI_ENABLE: usize = 16;
+const FADT_ACPI_DISABLE: usize = 17;
+const FADT_PM1A_CONTROL: usize = 28;
+const FADT_PM1B_CONTROL: usize = 32;
+const FADT_PM_TIMER: usize = 40;
+const FADT_CENTURY: usize = 72;
+const FADT_BOOT_FLAGS: usize = 73;
+const FADT_FLAGS: usize = 76;
+const FADT_RESET_REGISTER: usize = 80;
+const FADT_RESET_VALUE: usize = 92;
+const FADT_X_DSDT: usize = 104;
+
+pub fn fadt() -> Option<Fadt> {
+    let body = find_table(b"FACP")?.body();
+    if body.len() < FADT_CENTURY {
+        return None;
+    }
+
+    let has = |offset: usize, size: usize| body.len() >= offset + size;
+    let x_dsdt = if has(FADT_X_DSDT, 8) {
+        read_u64(body, FADT_X_DSDT)
+    } else {
+        0
+    };
+
+    Some(Fadt {
+        dsdt: match x_dsdt {
+            0 => read_u32(body, FADT_DSDT) as u64,
+            x_dsdt => x_dsdt,
+        },
+        sci_interrupt: read_u16(body, FADT_SCI_INTERRUPT),
+        smi_command: read_u32(body, FADT_SMI_COMMAND),
+        acpi_enable: body[FADT_ACPI_ENABLE],
+        acpi_disable: body[FADT_ACPI_DISABLE],
+        pm1a_control: read_u32(body, FADT_PM1A_CONTROL),
+        pm1b_control: read_u32(body, FADT_PM1B_CONTROL),
+        pm_timer: read_u32(body, FADT_PM_TIMER),
+        century: if has(FADT_CENTURY, 1) {
+            body[FADT_CENTURY]
+        } else {
+            0
+        },
+        boot_flags: if has(FADT_BOOT_FLAGS, 2) {
+            read_u16(body, FADT_BOOT_FLAGS)
+        } else {
+            0
+        },
+        flags: if has(FADT_FLAGS, 4) {
+            read_u32(body, FADT_FLAGS)
+        } else {
+            0
+        },
+        reset_register: has(FADT_RESET_VALUE, 1)
+            .then(|| GenericAddress::read(body, FADT_RESET_REGISTER)),
+        reset_value: if has(FADT_RESET_VALUE, 1) {
+            body[FADT_RESET_VALUE]
+        } else {
+            0
+        },
+    })
+}
+
+/// Differentiated System Description Table, the AML code of the platform.
+/// It is not listed by the root table but by the FADT
+pub fn dsdt() -> Option<Table> {
+    Table::load(fadt()?.dsdt)
+}
+
+/// High Precision Event Timer Description Table
+#[derive(Debug, Clone, Copy)]
+pub struct Hpet {
+    /// Hardware revision, number of comparators and vendor of the timer block
+    pub block_id: u32,
+    pub address: GenericAddress,
+    pub number: u8,
+    /// Minimum clock ticks of a periodic comparator
+    pub minimum_tick: u16,
+}
+
+pub fn hpet() -> Option<Hpet> {
+    let body = find_table(b"HPET")?.body();
+    if body.len() < 20 {
+        return None;
+    }
+
+    Some(Hpet {
+        block_id: read_u32(body, 0),
+        address: GenericAddress::read(body, 4),
+        number: body[16],
+        minimum_tick: read_u16(body, 17),
+    })
+}
+
+/// Memory mapped configuration space (ECAM) of a range of PCI buses
+#[derive(Debug, Clone, Copy)]
+pub struct McfgEntry {
+    /// Address of the configuration space of bus 0, even if `start_bus` is not 0
+    pub base_address: u64,
+    pub segment: u16,
+    pub start_bus: u8,
+    pub end_bus: u8,
+}
+
+/// PCI Express memory mapped configuration table, one entry per range of buses
+pub fn mcfg() -> Option<Vec<McfgEntry>> {
+    let body = find_table(b"MCFG")?.body();
+
+    // Entries follow 8 reserved bytes
+    Some(
+        body.get(8..)?
+            .chunks_exact(16)
+            .map(|entry| McfgEntry {
+                base_address: read_u64(entry, 0),
+                segment: read_u16(entry, 8),
+                start_bus: entry[10],
+                end_bus: entry[11],
+            })
+            .collect(),
+    )
+}
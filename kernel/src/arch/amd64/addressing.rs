@@ -26,7 +26,7 @@ impl VirtAddr {
 
         let table_indexes = [self.p4_index(), self.p3_index(), self.p2_index()];
         //let mut frame = level_4_table_frame;
-        let mut frame = unsafe { PhysAddr::new(KERNEL_CR3) };
+        let mut frame = PhysAddr::new(*KERNEL_CR3);
 
         // traverse the multi-level page table
         for index in table_indexes {
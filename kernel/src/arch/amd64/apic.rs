@@ -0,0 +1,330 @@
+use alloc::vec::Vec;
+use core::ptr::{read_volatile, write_volatile};
+
+use super::{
+    acpi,
+    addressing::PhysAddr,
+    interrupts,
+    paging::PageTableFlags::{NO_CACHE, WRITE_THROUGH},
+    pic::{PICS, PIC_1_OFFSET},
+};
+use crate::{
+    logging,
+    mm::ALLOCATOR,
+    sync::{IrqSpinMutex, Once},
+};
+
+/// Vector of the spurious interrupts of the local APIC, which need no EOI
+pub const SPURIOUS_VECTOR: u8 = 0xFF;
+
+// Local APIC registers
+const LAPIC_ID: u64 = 0x20;
+const LAPIC_TASK_PRIORITY: u64 = 0x80;
+const LAPIC_EOI: u64 = 0xB0;
+const LAPIC_SPURIOUS: u64 = 0xF0;
+const SPURIOUS_APIC_ENABLE: u32 = 1 << 8;
+const LAPIC_ICR_LOW: u64 = 0x300;
+const LAPIC_ICR_HIGH: u64 = 0x310;
+const LAPIC_TIMER: u64 = 0x320;
+const LAPIC_TIMER_INITIAL_COUNT: u64 = 0x380;
+const LAPIC_TIMER_CURRENT_COUNT: u64 = 0x390;
+const LAPIC_TIMER_DIVIDE: u64 = 0x3E0;
+
+// Timer modes of the local vector table entry, the divider only applies to the one-shot mode
+pub const TIMER_ONE_SHOT: u32 = 0b00 << 17;
+pub const TIMER_TSC_DEADLINE: u32 = 0b10 << 17;
+const TIMER_DIVIDE_BY_16: u32 = 0b0011;
+
+// Interrupt command register bits
+const ICR_INIT: u32 = 0b101 << 8;
+const ICR_STARTUP: u32 = 0b110 << 8;
+const ICR_DELIVERY_PENDING: u32 = 1 << 12;
+const ICR_ASSERT: u32 = 1 << 14;
+const ICR_ALL_BUT_SELF: u32 = 0b11 << 18;
+
+// I/O APIC registers, accessed through a select and a window register
+const IO_REGISTER_SELECT: u64 = 0x00;
+const IO_WINDOW: u64 = 0x10;
+const IO_APIC_VERSION: u32 = 0x01;
+const IO_REDIRECTION_TABLE: u32 = 0x10;
+
+// Redirection entry bits, the delivery mode is fixed and the destination a physical APIC ID
+const REDIRECTION_ACTIVE_LOW: u64 = 1 << 13;
+const REDIRECTION_LEVEL: u64 = 1 << 15;
+const REDIRECTION_MASKED: u64 = 1 << 16;
+
+/// ISA IRQs left free by the legacy devices, which the firmware routes PCI interrupts to
+const PCI_IRQ_LINES: [u8; 4] = [5, 9, 10, 11];
+
+/// Set when the APICs replace the 8259 PICs
+pub static APIC: Once<Apic> = Once::new();
+
+/// Registers of the local APIC of the processor
+pub struct LocalApic {
+    base: u64,
+}
+
+impl LocalApic {
+    unsafe fn read(&self, register: u64) -> u32 {
+        read_volatile((self.base + register) as *const u32)
+    }
+
+    unsafe fn write(&self, register: u64, value: u32) {
+        write_volatile((self.base + register) as *mut u32, value)
+    }
+
+    pub fn id(&self) -> u8 {
+        unsafe { (self.read(LAPIC_ID) >> 24) as u8 }
+    }
+
+    /// Accepts every interrupt priority and sets the spurious vector,
+    /// each processor enables its own local APIC
+    /// # Safety
+    /// The IDT must have a handler for the spurious vector before interrupts are enabled
+    pub unsafe fn enable(&self) {
+        self.write(LAPIC_TASK_PRIORITY, 0);
+        self.write(
+            LAPIC_SPURIOUS,
+            SPURIOUS_APIC_ENABLE | SPURIOUS_VECTOR as u32,
+        );
+    }
+
+    pub fn end_of_interrupt(&self) {
+        unsafe { self.write(LAPIC_EOI, 0) }
+    }
+
+    /// Sets the vector and the mode of the timer of this processor, which counts
+    /// at the bus frequency divided by 16 in the one-shot mode
+    /// # Safety
+    /// The IDT must have a handler for the vector
+    pub unsafe fn setup_timer(&self, vector: u8, mode: u32) {
+        self.write(LAPIC_TIMER_DIVIDE, TIMER_DIVIDE_BY_16);
+        self.write(LAPIC_TIMER, mode | vector as u32);
+    }
+
+    /// Starts counting down from `count` in the one-shot mode, the timer interrupts at 0.
+    /// A count of 0 stops it
+    pub fn start_timer(&self, count: u32) {
+        unsafe { self.write(LAPIC_TIMER_INITIAL_COUNT, count) }
+    }
+
+    pub fn timer_count(&self) -> u32 {
+        unsafe { self.read(LAPIC_TIMER_CURRENT_COUNT) }
+    }
+
+    /// Sends an inter-processor interrupt and waits for the APIC to accept it
+    unsafe fn send_ipi(&self, apic_id: u8, command: u32) {
+        self.write(LAPIC_ICR_HIGH, (apic_id as u32) << 24);
+        self.write(LAPIC_ICR_LOW, command);
+        while self.read(LAPIC_ICR_LOW) & ICR_DELIVERY_PENDING != 0 {
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Resets a processor, which then waits for a startup IPI
+    /// # Safety
+    /// Whatever the processor was running is lost, it must not hold locks or be needed
+    pub unsafe fn send_init(&self, apic_id: u8) {
+        self.send_ipi(apic_id, ICR_INIT | ICR_ASSERT);
+    }
+
+    /// Starts a processor waiting after an INIT in real mode at the address `page << 12`
+    /// # Safety
+    /// The page must hold startup code for the processor to run
+    pub unsafe fn send_startup(&self, apic_id: u8, page: u8) {
+        self.send_ipi(apic_id, ICR_STARTUP | ICR_ASSERT | page as u32);
+    }
+
+    /// Interrupts every other processor with a vector
+    /// # Safety
+    /// The IDT must have a handler for the vector
+    pub unsafe fn send_to_others(&self, vector: u8) {
+        self.send_ipi(0, ICR_ALL_BUT_SELF | ICR_ASSERT | vector as u32);
+    }
+}
+
+struct IoApic {
+    base: u64,
+    gsi_base: u32,
+    /// Number of redirection entries
+    inputs: u32,
+}
+
+impl IoApic {
+    unsafe fn read(&mut self, register: u32) -> u32 {
+        write_volatile((self.base + IO_REGISTER_SELECT) as *mut u32, register);
+        read_volatile((self.base + IO_WINDOW) as *const u32)
+    }
+
+    unsafe fn write(&mut self, register: u32, value: u32) {
+        write_volatile((self.base + IO_REGISTER_SELECT) as *mut u32, register);
+        write_volatile((self.base + IO_WINDOW) as *mut u32, value);
+    }
+
+    fn handles(&self, gsi: u32) -> bool {
+        (self.gsi_base..self.gsi_base + self.inputs).contains(&gsi)
+    }
+
+    unsafe fn redirection(&mut self, gsi: u32) -> u64 {
+        let register = IO_REDIRECTION_TABLE + (gsi - self.gsi_base) * 2;
+        self.read(register) as u64 | (self.read(register + 1) as u64) << 32
+    }
+
+    unsafe fn set_redirection(&mut self, gsi: u32, entry: u64) {
+        let register = IO_REDIRECTION_TABLE + (gsi - self.gsi_base) * 2;
+        // Write the masked low half first, so a half written entry never fires
+        self.write(register, (entry as u32) | REDIRECTION_MASKED as u32);
+        self.write(register + 1, (entry >> 32) as u32);
+        self.write(register, entry as u32);
+    }
+}
+
+pub struct Apic {
+    pub local: LocalApic,
+    io_apics: IrqSpinMutex<Vec<IoApic>>,
+    /// Global system interrupt and redirection flags of each ISA IRQ
+    isa_irqs: [(u32, u64); 16],
+}
+
+impl Apic {
+    /// Updates the redirection entry of an ISA IRQ
+    fn update(&self, irq: u8, f: impl FnOnce(u64) -> u64) {
+        let (gsi, _) = self.isa_irqs[irq as usize];
+        let mut io_apics = self.io_apics.lock();
+        if let Some(io_apic) = io_apics.iter_mut().find(|io_apic| io_apic.handles(gsi)) {
+            unsafe {
+                let entry = io_apic.redirection(gsi);
+                io_apic.set_redirection(gsi, f(entry));
+            }
+        }
+    }
+
+    pub fn mask(&self, irq: u8) {
+        self.update(irq, |entry| entry | REDIRECTION_MASKED);
+    }
+
+    pub fn unmask(&self, irq: u8) {
+        self.update(irq, |entry| entry & !REDIRECTION_MASKED);
+    }
+}
+
+/// Maps a page of registers, uncached
+fn map_registers(addr: u64) -> u64 {
+    ALLOCATOR
+        .lock()
+        .page_allocator
+        .as_mut()
+        .unwrap()
+        .map_physical(PhysAddr::new(addr), NO_CACHE | WRITE_THROUGH)
+        .as_u64()
+}
+
+/// Converts the MPS INTI flags of an interrupt override to redirection entry bits
+fn redirection_flags(flags: u16) -> u64 {
+    let mut bits = 0;
+    if flags & 0b11 == 0b11 {
+        bits |= REDIRECTION_ACTIVE_LOW;
+    }
+    if (flags >> 2) & 0b11 == 0b11 {
+        bits |= REDIRECTION_LEVEL;
+    }
+    bits
+}
+
+/// Switches the interrupts to the local and I/O APICs listed in the ACPI MADT.
+/// ISA IRQs keep the vectors the PICs gave them and the lines unmasked in the
+/// PICs stay unmasked, then the PICs are masked. Without a MADT the PICs stay in use.
+/// Needs the kernel page allocator
+pub fn init() {
+    let Some(madt) = acpi::madt() else {
+        info!("No MADT, using the 8259 PICs");
+        return;
+    };
+    if madt.io_apics.is_empty() {
+        info!("No I/O APIC, using the 8259 PICs");
+        return;
+    }
+
+    let local = LocalApic {
+        base: map_registers(madt.local_apic_address),
+    };
+    let io_apics: Vec<IoApic> = madt
+        .io_apics
+        .iter()
+        .map(|entry| {
+            let mut io_apic = IoApic {
+                base: map_registers(entry.address),
+                gsi_base: entry.gsi_base,
+                inputs: 0,
+            };
+            // Bits 16-23 of the version register are the last redirection entry
+            io_apic.inputs = unsafe { (io_apic.read(IO_APIC_VERSION) >> 16 & 0xFF) + 1 };
+            info!(
+                "I/O APIC {} at {:#x}: GSIs {}-{}",
+                entry.id,
+                entry.address,
+                io_apic.gsi_base,
+                io_apic.gsi_base + io_apic.inputs - 1
+            );
+            io_apic
+        })
+        .collect();
+
+    // ISA IRQs are edge triggered and active high, the PCI lines routed to them level
+    // triggered and active low, unless overridden
+    let mut isa_irqs: [(u32, u64); 16] = core::array::from_fn(|irq| {
+        if PCI_IRQ_LINES.contains(&(irq as u8)) {
+            (irq as u32, REDIRECTION_LEVEL | REDIRECTION_ACTIVE_LOW)
+        } else {
+            (irq as u32, 0)
+        }
+    });
+    for o in madt.overrides.iter().filter(|o| o.irq < 16) {
+        isa_irqs[o.irq as usize] = (o.gsi, redirection_flags(o.flags));
+    }
+
+    let apic = Apic {
+        local,
+        io_apics: IrqSpinMutex::new(io_apics),
+        isa_irqs,
+    };
+
+    // The APIC must be in use before the next interrupt, its handler acknowledges it
+    interrupts::free(move || unsafe {
+        let mut pics = PICS.lock();
+        let masks = pics.read_masks();
+        let masks = masks[0] as u16 | (masks[1] as u16) << 8;
+        pics.disable();
+        drop(pics);
+
+        apic.local.enable();
+        let destination = (apic.local.id() as u64) << 56;
+
+        let mut io_apics = apic.io_apics.lock();
+        // IRQ 2 is the cascade of the PICs, it is never raised
+        for irq in (0..16).filter(|&irq| irq != 2) {
+            let (gsi, flags) = apic.isa_irqs[irq];
+            let Some(io_apic) = io_apics.iter_mut().find(|io_apic| io_apic.handles(gsi)) else {
+                continue;
+            };
+            let masked = if masks & (1 << irq) != 0 {
+                REDIRECTION_MASKED
+            } else {
+                0
+            };
+            io_apic.set_redirection(
+                gsi,
+                destination | masked | flags | (PIC_1_OFFSET as u64 + irq as u64),
+            );
+        }
+        drop(io_apics);
+
+        APIC.set(apic);
+    });
+
+    info!(
+        "Local APIC {} at {:#x}, 8259 PICs masked",
+        APIC.local.id(),
+        madt.local_apic_address
+    );
+}
@@ -0,0 +1,256 @@
+use core::{
+    arch::x86_64::{__cpuid, _rdtsc},
+    ptr::{addr_of, read_volatile, write_volatile},
+    sync::atomic::{AtomicI64, Ordering},
+};
+
+use super::{
+    acpi,
+    addressing::PhysAddr,
+    interrupts,
+    io::{inb, outb},
+    paging::PageTableFlags::{NO_CACHE, WRITE_THROUGH},
+    pic::Timer,
+    tick,
+};
+use crate::{logging, mm::ALLOCATOR, sync::Once};
+
+pub const NANOS_PER_SEC: u64 = 1_000_000_000;
+const NANOS_PER_MILLI: u64 = 1_000_000;
+
+// HPET registers
+const HPET_CAPABILITIES: u64 = 0x00;
+const HPET_CONFIGURATION: u64 = 0x10;
+const HPET_MAIN_COUNTER: u64 = 0xF0;
+const HPET_COUNTER_64BIT: u64 = 1 << 13;
+const HPET_ENABLE: u64 = 1 << 0;
+const FEMTOS_PER_SEC: u64 = 1_000_000_000_000_000;
+
+// PIT channel 2, whose gate and output are in the keyboard controller port B
+const PIT_CHANNEL_2_PORT: u16 = 0x42;
+const PIT_COMMAND_PORT: u16 = 0x43;
+/// Channel 2, low then high byte, mode 0
+const PIT_CHANNEL_2_ONE_SHOT: u8 = 0b1011_0000;
+const PORT_B: u16 = 0x61;
+const PORT_B_GATE_2: u8 = 1 << 0;
+const PORT_B_SPEAKER: u8 = 1 << 1;
+const PORT_B_OUT_2: u8 = 1 << 5;
+const CALIBRATION_MS: u64 = 50;
+
+/// Monotonic clock, set by `init`. Before it the PIT uptime is used
+static CLOCK: Once<Clock> = Once::new();
+
+/// Difference between the realtime clock and the monotonic clock, in nanoseconds
+static REALTIME_OFFSET: AtomicI64 = AtomicI64::new(0);
+
+#[derive(Clone, Copy, PartialEq)]
+enum Source {
+    /// Time stamp counter running at a constant rate in every power state
+    Tsc,
+    /// Main counter of the High Precision Event Timer
+    Hpet { base: u64 },
+}
+
+struct Clock {
+    source: Source,
+    frequency: u64,
+    /// Counter value and time when the clock was started
+    start_count: u64,
+    start_ns: u64,
+    /// `ns = (ticks * mult) >> 32`, to avoid a division on every read
+    mult: u128,
+}
+
+impl Clock {
+    fn new(source: Source, frequency: u64) -> Clock {
+        Clock {
+            source,
+            frequency,
+            start_count: read_counter(source),
+            start_ns: pit_ms() * NANOS_PER_MILLI,
+            mult: ((NANOS_PER_SEC as u128) << 32) / frequency as u128,
+        }
+    }
+
+    fn now(&self) -> u64 {
+        let ticks = read_counter(self.source).wrapping_sub(self.start_count);
+        self.start_ns + ((ticks as u128 * self.mult) >> 32) as u64
+    }
+}
+
+fn read_counter(source: Source) -> u64 {
+    match source {
+        Source::Tsc => unsafe { _rdtsc() },
+        Source::Hpet { base } => unsafe { read_volatile((base + HPET_MAIN_COUNTER) as *const u64) },
+    }
+}
+
+/// Whether the TSC keeps the same rate in every P-state and C-state
+fn invariant_tsc() -> bool {
+    __cpuid(0x8000_0000).eax >= 0x8000_0007 && __cpuid(0x8000_0007).edx & (1 << 8) != 0
+}
+
+/// Maps and starts the HPET main counter, returns its base address and frequency
+fn init_hpet() -> Option<(u64, u64)> {
+    let hpet = acpi::hpet()?;
+    if hpet.address.address_space != acpi::GenericAddress::SYSTEM_MEMORY {
+        return None;
+    }
+
+    let base = ALLOCATOR
+        .lock()
+        .page_allocator
+        .as_mut()
+        .unwrap()
+        .map_physical(
+            PhysAddr::new(hpet.address.address),
+            NO_CACHE | WRITE_THROUGH,
+        )
+        .as_u64();
+
+    unsafe {
+        let capabilities = read_volatile((base + HPET_CAPABILITIES) as *const u64);
+        // The 32-bit counters wrap in a few minutes
+        if capabilities & HPET_COUNTER_64BIT == 0 {
+            warn!("HPET counter is only 32 bits wide, not using it");
+            return None;
+        }
+        let period_fs = capabilities >> 32;
+        if period_fs == 0 {
+            return None;
+        }
+
+        let configuration = (base + HPET_CONFIGURATION) as *mut u64;
+        write_volatile(configuration, read_volatile(configuration) | HPET_ENABLE);
+
+        Some((base, FEMTOS_PER_SEC / period_fs))
+    }
+}
+
+/// Counts the ticks of the TSC and the HPET during `CALIBRATION_MS`, measured with
+/// the PIT channel 2 in one-shot mode. Interrupts are disabled meanwhile
+fn calibrate(hpet: Option<u64>) -> (u64, Option<u64>) {
+    let count = Timer::FREQUENCY as u64 * CALIBRATION_MS / 1000;
+
+    interrupts::free(|| unsafe {
+        // Gate low and speaker off, then mode 0 (interrupt on terminal count)
+        let port_b = inb(PORT_B) & !(PORT_B_GATE_2 | PORT_B_SPEAKER);
+        outb(PORT_B, port_b);
+        outb(PIT_COMMAND_PORT, PIT_CHANNEL_2_ONE_SHOT);
+        outb(PIT_CHANNEL_2_PORT, count as u8);
+        outb(PIT_CHANNEL_2_PORT, (count >> 8) as u8);
+
+        // Counting starts when the gate rises, the output goes high at zero
+        let tsc_start = _rdtsc();
+        let hpet_start = hpet.map(|base| read_counter(Source::Hpet { base }));
+        outb(PORT_B, port_b | PORT_B_GATE_2);
+        while inb(PORT_B) & PORT_B_OUT_2 == 0 {
+            core::hint::spin_loop();
+        }
+        let tsc_end = _rdtsc();
+        let hpet_end = hpet.map(|base| read_counter(Source::Hpet { base }));
+        outb(PORT_B, port_b);
+
+        let per_second = |ticks: u64| ticks * 1000 / CALIBRATION_MS;
+        (
+            per_second(tsc_end - tsc_start),
+            hpet_start
+                .zip(hpet_end)
+                .map(|(start, end)| per_second(end - start)),
+        )
+    })
+}
+
+/// Picks the monotonic clocksource: the invariant TSC, then the HPET. Their rates are
+/// measured against the PIT, without either one the PIT millisecond ticks are used.
+/// Needs the ACPI tables and the kernel page allocator
+pub fn init() {
+    let hpet = init_hpet();
+    let (tsc_frequency, hpet_measured) = calibrate(hpet.map(|(base, _)| base));
+
+    if let (Some((_, frequency)), Some(measured)) = (hpet, hpet_measured) {
+        // The HPET reports its own period, the PIT only checks it
+        if measured.abs_diff(frequency) > frequency / 100 {
+            warn!(
+                "HPET reports {} Hz but runs at {} Hz against the PIT",
+                frequency, measured
+            );
+        }
+    }
+
+    let clock = if invariant_tsc() {
+        Clock::new(Source::Tsc, tsc_frequency)
+    } else if let Some((base, frequency)) = hpet {
+        Clock::new(Source::Hpet { base }, frequency)
+    } else {
+        info!("No invariant TSC or HPET, using the PIT as clocksource");
+        return;
+    };
+
+    info!(
+        "Clocksource {} at {}.{:03} MHz",
+        match clock.source {
+            Source::Tsc => "TSC",
+            Source::Hpet { .. } => "HPET",
+        },
+        clock.frequency / 1_000_000,
+        clock.frequency / 1000 % 1000
+    );
+    CLOCK.set(clock);
+}
+
+/// Milliseconds counted by the PIT interrupts
+fn pit_ms() -> u64 {
+    unsafe { read_volatile(addr_of!(Timer::UPTIME)) }
+}
+
+/// Nanoseconds since boot, never going backwards
+pub fn monotonic_ns() -> u64 {
+    match CLOCK.get() {
+        Some(clock) => clock.now(),
+        None => pit_ms() * NANOS_PER_MILLI,
+    }
+}
+
+/// Whether a clocksource finer than the PIT is in use
+pub fn is_precise() -> bool {
+    CLOCK.get().is_some()
+}
+
+/// TSC value at a monotonic time, when the TSC is the clocksource
+pub fn tsc_at(ns: u64) -> Option<u64> {
+    let clock = CLOCK.get().filter(|clock| clock.source == Source::Tsc)?;
+    let ns = ns.saturating_sub(clock.start_ns);
+    let ticks = ns as u128 * clock.frequency as u128 / NANOS_PER_SEC as u128;
+    Some(clock.start_count + ticks as u64)
+}
+
+/// Nanoseconds since 1970-01-01 00:00:00 UTC, set from the CMOS clock at boot.
+/// It jumps when the time is set
+pub fn realtime_ns() -> i64 {
+    monotonic_ns() as i64 + REALTIME_OFFSET.load(Ordering::Relaxed)
+}
+
+pub fn set_realtime_ns(ns: i64) {
+    REALTIME_OFFSET.store(ns - monotonic_ns() as i64, Ordering::Relaxed);
+}
+
+/// Waits for a number of nanoseconds, halting the processor. Interrupts must be enabled
+pub fn sleep_ns(ns: u64) {
+    let deadline = monotonic_ns().saturating_add(ns);
+    loop {
+        interrupts::disable();
+        let now = monotonic_ns();
+        if now >= deadline {
+            interrupts::enable();
+            break;
+        }
+        // The PIT ticks every millisecond, the last one is spent spinning
+        if tick::is_tickless() || deadline - now > NANOS_PER_MILLI {
+            tick::halt_until(Some(deadline));
+        } else {
+            interrupts::enable();
+            core::hint::spin_loop();
+        }
+    }
+}
@@ -1,27 +1,86 @@
 use core::{arch::asm, mem::size_of};
 
+use crate::sync::Lazy;
+
 pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
+/// Entries of the GDT of `start.S`, the TSS descriptor takes the last two
+const GDT_ENTRIES: usize = 7;
+const TSS_INDEX: u16 = 5;
+const DOUBLE_FAULT_STACK_SIZE: usize = 4096 * 3;
 
 // They are defined in `start.S`, will reuse them for the time being
 extern "C" {
     static mut GDT: u64;
 }
 
-static mut TSS: TaskStateSegment = TaskStateSegment::new();
+/// Built on first use, the GDT keeps its address
+static TSS: Lazy<TaskStateSegment> = Lazy::new(|| {
+    let mut tss = TaskStateSegment::new();
+    tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = {
+        const STACK_SIZE: u64 = 4096 * 3;
+        static mut STACK: [u8; STACK_SIZE as usize] = [0; STACK_SIZE as usize];
+
+        let stack_start = unsafe { &STACK as *const _ as u64 };
+        stack_start + STACK_SIZE
+    };
+    tss
+});
 
 pub fn init_tss() {
     unsafe {
-        TSS.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = {
-            const STACK_SIZE: u64 = 4096 * 3;
-            static mut STACK: [u8; STACK_SIZE as usize] = [0; STACK_SIZE as usize];
+        GlobalDescriptorTable::replace_tss(TSS_INDEX as u64, &TSS);
+        GlobalDescriptorTable::load();
+        GlobalDescriptorTable::load_tss(TSS_INDEX);
+    }
+}
 
-            let stack_start = &STACK as *const _ as u64;
-            stack_start + STACK_SIZE
-        };
+/// GDT and TSS of an application processor. Each processor needs its own TSS,
+/// which is marked busy when loaded, and its own double fault stack
+pub struct CpuTables {
+    gdt: [u64; GDT_ENTRIES],
+    tss: TaskStateSegment,
+    double_fault_stack: [u8; DOUBLE_FAULT_STACK_SIZE],
+}
 
-        GlobalDescriptorTable::replace_tss(5, &TSS);
-        GlobalDescriptorTable::load();
-        GlobalDescriptorTable::load_tss(5);
+impl CpuTables {
+    pub const fn new() -> CpuTables {
+        CpuTables {
+            gdt: [0; GDT_ENTRIES],
+            tss: TaskStateSegment::new(),
+            double_fault_stack: [0; DOUBLE_FAULT_STACK_SIZE],
+        }
+    }
+}
+
+impl Default for CpuTables {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Loads a copy of the boot GDT, with the TSS of the processor.
+/// The selectors stay the same, so the segment registers don't need to be reloaded
+pub fn init_cpu(tables: &'static mut CpuTables) {
+    unsafe {
+        let boot_gdt = core::ptr::addr_of!(GDT);
+        for (i, entry) in tables.gdt[..TSS_INDEX as usize].iter_mut().enumerate() {
+            *entry = *boot_gdt.add(i);
+        }
+
+        let stack_end = tables.double_fault_stack.as_ptr() as u64 + DOUBLE_FAULT_STACK_SIZE as u64;
+        tables.tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = stack_end;
+
+        let tss = &*(&tables.tss as *const TaskStateSegment);
+        let (low, high) = tss_segment(tss);
+        tables.gdt[TSS_INDEX as usize] = low;
+        tables.gdt[TSS_INDEX as usize + 1] = high;
+
+        let pointer = DescriptorTablePointer {
+            limit: (size_of::<[u64; GDT_ENTRIES]>() - 1) as u16,
+            base: tables.gdt.as_ptr() as u64,
+        };
+        asm!("lgdt [{}]", in(reg) &pointer, options(readonly, nostack, preserves_flags));
+        GlobalDescriptorTable::load_tss(TSS_INDEX);
     }
 }
 
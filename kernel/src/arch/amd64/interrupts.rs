@@ -1,14 +1,105 @@
 #![allow(non_snake_case)]
 use crate::logging;
-use core::{arch::asm, mem::size_of};
+use core::{
+    arch::asm,
+    mem::size_of,
+    sync::atomic::{AtomicU64, Ordering},
+};
 
 use super::{
     addressing::VirtAddr,
+    msi::msi_handler,
     registers::{rflags_values, Rflags},
 };
+use crate::sync::IrqSpinMutex;
 
 static mut IDT: InterruptDescriptorTable = InterruptDescriptorTable::new();
 
+/// Number of times each of the 16 PIC lines interrupted
+pub static IRQ_COUNTS: [AtomicU64; 16] = [const { AtomicU64::new(0) }; 16];
+
+fn count_irq(irq: u8) {
+    IRQ_COUNTS[irq as usize].fetch_add(1, Ordering::Relaxed);
+}
+
+/// Most handlers sharing one IRQ line
+const MAX_SHARED_HANDLERS: usize = 4;
+
+/// Called with the IRQ number. Handlers of a shared line are all called, each one
+/// checks whether its device interrupted
+pub type IrqHandler = fn(u8);
+
+/// Handlers of each IRQ line, in the order they were registered
+static IRQ_HANDLERS: IrqSpinMutex<[[Option<IrqHandler>; MAX_SHARED_HANDLERS]; 16]> =
+    IrqSpinMutex::new([[None; MAX_SHARED_HANDLERS]; 16]);
+
+/// Number of IRQ lines of the PICs, also handled by the I/O APIC
+pub const IRQ_LINES: u8 = 16;
+
+/// Adds a handler to an IRQ line (0-15) and unmasks it. The interrupt is acknowledged
+/// after the handlers ran. Returns false for a line which does not exist (like 0xFF,
+/// a PCI device without an interrupt line) or has no room for another handler
+pub fn register_irq(irq: u8, handler: IrqHandler) -> bool {
+    if irq >= IRQ_LINES {
+        return false;
+    }
+    let registered = match IRQ_HANDLERS.lock()[irq as usize]
+        .iter_mut()
+        .find(|slot| slot.is_none())
+    {
+        Some(slot) => {
+            *slot = Some(handler);
+            true
+        }
+        None => false,
+    };
+
+    if registered {
+        unmask_irq(irq);
+    } else {
+        warn!("IRQ {} already has {} handlers", irq, MAX_SHARED_HANDLERS);
+    }
+    registered
+}
+
+/// Acknowledges an IRQ to the interrupt controller in use, the local APIC or the PICs
+pub fn end_of_interrupt(irq: u8) {
+    match super::apic::APIC.get() {
+        Some(apic) => apic.local.end_of_interrupt(),
+        None => unsafe {
+            super::pic::PICS
+                .lock()
+                .notify_end_of_interrupt(super::pic::PIC_1_OFFSET + irq)
+        },
+    }
+}
+
+/// Lets an IRQ line (0-15) interrupt, in the I/O APIC or the PICs.
+/// Returns false if the line does not exist
+pub fn unmask_irq(irq: u8) -> bool {
+    if irq >= IRQ_LINES {
+        return false;
+    }
+    match super::apic::APIC.get() {
+        Some(apic) => apic.unmask(irq),
+        None => unsafe { super::pic::PICS.lock().unmask(irq) },
+    }
+    true
+}
+
+/// Stops an IRQ line (0-15) from interrupting, its handlers stay registered.
+/// Returns false if the line does not exist
+pub fn mask_irq(irq: u8) -> bool {
+    if irq >= IRQ_LINES {
+        return false;
+    }
+    match super::apic::APIC.get() {
+        Some(apic) => apic.mask(irq),
+        None => unsafe { super::pic::PICS.lock().mask(irq) },
+    }
+    true
+}
+
 extern "C" {
     fn syscall_asm();
 }
@@ -25,6 +116,15 @@ pub struct Registers {
     pub r9: u64,
 }
 
+/// Points vectors to the instances of a handler generic over the vector
+macro_rules! set_vector_handlers {
+    ($handler:ident: $($vector:literal),*) => {
+        $(
+            IDT.interrupts[$vector - 32].set_handler_fn($handler::<$vector> as u64);
+        )*
+    };
+}
+
 #[allow(clippy::fn_to_numeric_cast)]
 pub fn init_idt() {
     unsafe {
@@ -32,15 +132,29 @@ pub fn init_idt() {
         IDT.double_fault.set_handler_fn(double_fault_handler as u64);
         IDT.double_fault.options.set_IST(1);
         IDT.page_fault.set_handler_fn(page_fault_handler as u64);
-        IDT.interrupts[InterruptIndex::Timer.IRQ_index()]
-            .set_handler_fn(timer_interrupt_handler as u64);
+        // IRQ 0-15, from the PICs or the I/O APIC
+        set_vector_handlers!(
+            irq_handler: 32, 33, 34, 35, 36, 37, 38, 39, 40, 41, 42, 43, 44, 45, 46, 47
+        );
+
         IDT.interrupts[InterruptIndex::Syscall.IRQ_index()].set_handler_fn(syscall_asm as u64);
         IDT.interrupts[InterruptIndex::Syscall.IRQ_index()]
             .options
             .disable_interrupts(false);
 
-        IDT.interrupts[InterruptIndex::Keyboard.IRQ_index()]
-            .set_handler_fn(keyboard_interrupt_handler as u64);
+        set_vector_handlers!(
+            msi_handler:
+            0x40, 0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49, 0x4A, 0x4B, 0x4C, 0x4D,
+            0x4E, 0x4F, 0x50, 0x51, 0x52, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5A, 0x5B,
+            0x5C, 0x5D, 0x5E, 0x5F
+        );
+
+        IDT.interrupts[super::apic::SPURIOUS_VECTOR as usize - 32]
+            .set_handler_fn(spurious_interrupt_handler as u64);
+        IDT.interrupts[super::smp::TLB_SHOOTDOWN_VECTOR as usize - 32]
+            .set_handler_fn(tlb_shootdown_handler as u64);
+        IDT.interrupts[super::tick::TIMER_VECTOR as usize - 32]
+            .set_handler_fn(local_timer_handler as u64);
 
         IDT.overflow.set_handler_fn(overflow_handler as u64);
         IDT.invalid_tss.set_handler_fn(invalidtss_handler as u64);
@@ -61,103 +175,104 @@ pub fn init_idt() {
     }
 }
 
-extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    unsafe {
-        let scancode: u8 = crate::arch::io::inb(0x60);
-        crate::drivers::keyboard::KeyboardState::update(scancode);
-
-        crate::arch::pic::PICS
-            .lock()
-            .notify_end_of_interrupt(InterruptIndex::Keyboard.as_u8());
+/// Entry of the vectors of IRQ 0-15: counts the interrupt, calls the handlers
+/// registered for the line and acknowledges it
+extern "x86-interrupt" fn irq_handler<const VECTOR: u8>(_stack_frame: InterruptStackFrame) {
+    let irq = VECTOR - super::pic::PIC_1_OFFSET;
+    count_irq(irq);
+    // Copied, so the handlers run without the lock
+    let handlers = IRQ_HANDLERS.lock()[irq as usize];
+    for handler in handlers.into_iter().flatten() {
+        handler(irq);
     }
+    end_of_interrupt(irq);
 }
 
-extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    use super::pic::Timer::COUNT_DOWN;
-    use super::pic::Timer::UPTIME;
-    unsafe {
-        let volatile = &mut COUNT_DOWN as *mut u64;
-        let count = core::ptr::read_volatile(volatile);
-        if count > 0 {
-            core::ptr::write_volatile(volatile, count - 1);
-        }
+/// Loads the IDT built by `init_idt` on an application processor, all the processors share it
+pub fn load_idt() {
+    unsafe { IDT.load() }
+}
 
-        let volatile = &mut UPTIME as *mut u64;
-        let count = core::ptr::read_volatile(volatile);
-        core::ptr::write_volatile(volatile, count + 1);
+/// Local APIC timer of the processor, it only has to wake the processor from `tick::halt_until`
+extern "x86-interrupt" fn local_timer_handler(_stack_frame: InterruptStackFrame) {
+    super::apic::APIC.local.end_of_interrupt();
+}
 
-        crate::arch::pic::PICS
-            .lock()
-            .notify_end_of_interrupt(InterruptIndex::Timer.as_u8());
-    }
+/// Sent by another processor which changed the kernel page tables
+extern "x86-interrupt" fn tlb_shootdown_handler(_stack_frame: InterruptStackFrame) {
+    super::smp::handle_tlb_shootdown();
+    super::apic::APIC.local.end_of_interrupt();
 }
 
+/// Raised by the local APIC when an interrupt goes away before it is delivered,
+/// it must not be acknowledged
+extern "x86-interrupt" fn spurious_interrupt_handler(_stack_frame: InterruptStackFrame) {}
+
 extern "x86-interrupt" fn double_fault_handler(
     stack_frame: InterruptStackFrame,
     error_code: u64,
 ) -> ! {
-    log!(
+    error!(
         "EXCEPTION: DOUBLE FAULT error code: {}\n{:#?}",
-        error_code,
-        stack_frame
+        error_code, stack_frame
     );
 
     panic!();
 }
 
 extern "x86-interrupt" fn breakpoint_handler(stack_frame: InterruptStackFrame) {
-    log!("EXCEPTION: BREAKPOINT\n{:#?}", stack_frame);
+    debug!("EXCEPTION: BREAKPOINT\n{:#?}", stack_frame);
 }
 
 extern "x86-interrupt" fn page_fault_handler(stack_frame: InterruptStackFrame, error_code: u64) {
-    log!("EXCEPTION: PAGE FAULT");
-    log!(
+    error!("EXCEPTION: PAGE FAULT");
+    error!(
         "Accessed Address: 0x{:x}",
         crate::arch::registers::Cr2::read()
     );
-    log!("Error Code: {}", error_code);
-    log!("{:#?}", stack_frame);
+    error!("Error Code: {}", error_code);
+    error!("{:#?}", stack_frame);
 
     crate::hlt_loop()
 }
 
 extern "x86-interrupt" fn overflow_handler(stack_frame: InterruptStackFrame) {
-    log!("EXCEPTION: OVERFLOW\n{:#?}", stack_frame);
+    error!("EXCEPTION: OVERFLOW\n{:#?}", stack_frame);
     crate::hlt_loop()
 }
 
 extern "x86-interrupt" fn invalidtss_handler(stack_frame: InterruptStackFrame) {
-    log!("EXCEPTION: Invalid TSS\n{:#?}", stack_frame);
+    error!("EXCEPTION: Invalid TSS\n{:#?}", stack_frame);
     crate::hlt_loop()
 }
 
 extern "x86-interrupt" fn invalidopcode_handler(stack_frame: InterruptStackFrame) {
-    log!("EXCEPTION: Invalid Opcode\n{:#?}", stack_frame);
+    error!("EXCEPTION: Invalid Opcode\n{:#?}", stack_frame);
     crate::hlt_loop()
 }
 
 extern "x86-interrupt" fn segmentnotpresent_handler(stack_frame: InterruptStackFrame) {
-    log!("EXCEPTION: Segment not present\n{:#?}", stack_frame);
+    error!("EXCEPTION: Segment not present\n{:#?}", stack_frame);
     crate::hlt_loop()
 }
 
 extern "x86-interrupt" fn stacksegment_handler(stack_frame: InterruptStackFrame) {
-    log!("EXCEPTION: Stack segment fault\n{:#?}", stack_frame);
+    error!("EXCEPTION: Stack segment fault\n{:#?}", stack_frame);
     crate::hlt_loop()
 }
 
 extern "x86-interrupt" fn generalprotection_handler(stack_frame: InterruptStackFrame) {
-    log!("EXCEPTION: General protection fault\n{:#?}", stack_frame);
+    error!("EXCEPTION: General protection fault\n{:#?}", stack_frame);
     crate::hlt_loop()
 }
 
 extern "x86-interrupt" fn machinecheck_handler(stack_frame: InterruptStackFrame) {
-    log!("EXCEPTION: Machine check fault\n{:#?}", stack_frame);
+    error!("EXCEPTION: Machine check fault\n{:#?}", stack_frame);
     crate::hlt_loop()
 }
 
 extern "x86-interrupt" fn alignmentcheck_handler(stack_frame: InterruptStackFrame) {
-    log!("EXCEPTION: Alignment check fault\n{:#?}", stack_frame);
+    error!("EXCEPTION: Alignment check fault\n{:#?}", stack_frame);
     crate::hlt_loop()
 }
 
@@ -398,7 +513,6 @@ impl InterruptDescriptorTable {
 #[repr(u8)]
 pub enum InterruptIndex {
     Timer = super::pic::PIC_1_OFFSET,
-    Keyboard,
     Syscall = 0x80,
 }
 
@@ -480,3 +594,15 @@ pub fn hlt() {
         asm!("hlt", options(nomem, nostack, preserves_flags));
     }
 }
+
+/// Enables interrupts and halts until the next one arrives.
+///
+/// `sti` only takes effect after the following instruction, so an interrupt
+/// cannot arrive between the two and be missed by a caller that checked a
+/// condition with interrupts disabled.
+#[inline]
+pub fn enable_and_hlt() {
+    unsafe {
+        asm!("sti; hlt", options(nomem, nostack));
+    }
+}
@@ -20,7 +20,7 @@ pub unsafe fn outw(port: u16, val: u16) {
 /// Read a word (16-bits) from the specified port
 pub unsafe fn inw(port: u16) -> u16 {
     let ret: u16;
-    ::core::arch::asm!("in eax, dx", out("eax") ret, in("dx") port, options(preserves_flags, nomem, nostack));
+    ::core::arch::asm!("in ax, dx", out("ax") ret, in("dx") port, options(preserves_flags, nomem, nostack));
     ret
 }
 
@@ -1,5 +1,5 @@
 // x86 port IO
-mod io;
+pub mod io;
 
 pub mod serial;
 
@@ -14,3 +14,21 @@ pub mod paging;
 pub mod addressing;
 
 pub mod pic;
+
+pub mod acpi;
+
+pub mod apic;
+
+pub mod power;
+
+pub mod percpu;
+
+pub mod smp;
+
+pub mod clock;
+
+pub mod rtc;
+
+pub mod tick;
+
+pub mod msi;
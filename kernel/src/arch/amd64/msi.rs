@@ -0,0 +1,94 @@
+use alloc::{boxed::Box, vec::Vec};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use super::{
+    apic::APIC,
+    interrupts::{self, InterruptStackFrame},
+};
+use crate::sync::RwSpinLock;
+
+/// First vector handed out to message signaled interrupts, above the legacy IRQs
+pub const FIRST_VECTOR: u8 = 0x40;
+/// Number of vectors for message signaled interrupts, up to 0x5F
+pub const VECTOR_COUNT: usize = 32;
+
+/// Messages are memory writes to this range, which the local APICs claim
+const MESSAGE_ADDRESS_BASE: u64 = 0xFEE0_0000;
+const MESSAGE_DESTINATION_SHIFT: u64 = 12;
+
+type Handler = Box<dyn Fn() + Send + Sync>;
+/// Name of the device and handler of a vector, None while it is free
+type Slot = Option<(&'static str, Handler)>;
+
+static HANDLERS: RwSpinLock<[Slot; VECTOR_COUNT]> = RwSpinLock::new([const { None }; VECTOR_COUNT]);
+/// Number of interrupts received on each vector
+static COUNTS: [AtomicU64; VECTOR_COUNT] = [const { AtomicU64::new(0) }; VECTOR_COUNT];
+
+/// Takes a free vector and calls `handler` on each of its interrupts, which are
+/// acknowledged afterwards. A function or a closure can be given. None without a local
+/// APIC or when every vector is taken
+pub fn allocate_vector<F>(name: &'static str, handler: F) -> Option<u8>
+where
+    F: Fn() + Send + Sync + 'static,
+{
+    APIC.get()?;
+
+    // An interrupt taking the read lock on this processor would never get it
+    interrupts::free(|| {
+        let mut handlers = HANDLERS.write();
+        let index = handlers.iter().position(|slot| slot.is_none())?;
+        handlers[index] = Some((name, Box::new(handler)));
+        COUNTS[index].store(0, Ordering::Relaxed);
+        Some(FIRST_VECTOR + index as u8)
+    })
+}
+
+/// Releases a vector, the device must not send its message anymore.
+/// Returns false for a vector which is not one of the message signaled ones
+pub fn free_vector(vector: u8) -> bool {
+    if !(FIRST_VECTOR..FIRST_VECTOR + VECTOR_COUNT as u8).contains(&vector) {
+        return false;
+    }
+    let index = (vector - FIRST_VECTOR) as usize;
+    interrupts::free(|| HANDLERS.write()[index] = None);
+    true
+}
+
+/// Address the device writes its message to, delivered to the local APIC of the running processor
+pub fn message_address() -> u64 {
+    MESSAGE_ADDRESS_BASE | ((APIC.local.id() as u64) << MESSAGE_DESTINATION_SHIFT)
+}
+
+/// Data of the message: fixed delivery, edge triggered
+pub fn message_data(vector: u8) -> u32 {
+    vector as u32
+}
+
+/// Allocated vectors with their device name and interrupt count, for /proc/interrupts
+pub fn vectors() -> Vec<(u8, &'static str, u64)> {
+    HANDLERS
+        .read()
+        .iter()
+        .enumerate()
+        .filter_map(|(index, slot)| {
+            let (name, _) = slot.as_ref()?;
+            Some((
+                FIRST_VECTOR + index as u8,
+                *name,
+                COUNTS[index].load(Ordering::Relaxed),
+            ))
+        })
+        .collect()
+}
+
+/// Entry of the vectors `FIRST_VECTOR` to `FIRST_VECTOR + VECTOR_COUNT`
+pub(super) extern "x86-interrupt" fn msi_handler<const VECTOR: u8>(
+    _stack_frame: InterruptStackFrame,
+) {
+    let index = (VECTOR - FIRST_VECTOR) as usize;
+    COUNTS[index].fetch_add(1, Ordering::Relaxed);
+    if let Some((_, handler)) = &HANDLERS.read()[index] {
+        handler();
+    }
+    APIC.local.end_of_interrupt();
+}
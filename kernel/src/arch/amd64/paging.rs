@@ -2,6 +2,7 @@ use alloc::boxed::Box;
 
 use super::addressing::{PhysAddr, VirtAddr, KERNEL_BASE};
 use crate::multiboot::{MmapEntry, MultibootInfo};
+use crate::sync::{Once, SpinMutex};
 use crate::utils;
 use core::{
     fmt,
@@ -15,10 +16,11 @@ extern "C" {
     fn kernel_end();
 }
 
-// TODO: add mutex
-pub static mut GLOBAL_FRAME_ALLOCATOR: PageFrameAllocator = PageFrameAllocator::new();
+/// Set up by `init_pfa`
+pub static GLOBAL_FRAME_ALLOCATOR: Once<SpinMutex<PageFrameAllocator>> = Once::new();
 
-pub static mut KERNEL_CR3: u64 = 0;
+/// Physical address of the kernel PML4, set when the kernel page allocator is created
+pub static KERNEL_CR3: Once<u64> = Once::new();
 
 /// I use 2MB pages
 pub const PAGE_SIZE: u64 = 2 * 1024 * 1024;
@@ -47,9 +49,7 @@ impl PageAllocator {
 
         let virt = VirtAddr::new(physical_memory_offset + kernel_cr3);
 
-        unsafe {
-            KERNEL_CR3 = kernel_cr3;
-        }
+        KERNEL_CR3.call_once(|| kernel_cr3);
 
         PageAllocator {
             pml4: virt,
@@ -71,7 +71,7 @@ impl PageAllocator {
 
         // Link the last entry to the kernel memory space
         (*l4)[511] =
-            unsafe { (&*((KERNEL_CR3 + physical_memory_offset) as *const PageTable))[511] };
+            unsafe { (&*((*KERNEL_CR3 + physical_memory_offset) as *const PageTable))[511] };
 
         let l2_virt = VirtAddr::new(Box::<PageTable>::into_raw(l2) as u64);
         let l2_phys = l2_virt.translate_address(physical_memory_offset).unwrap();
@@ -199,11 +199,10 @@ impl PageAllocator {
         use PageTableFlags::*;
         // If the tables exist, insert the new entry at its index
         if present {
-            let frame = unsafe {
-                GLOBAL_FRAME_ALLOCATOR
-                    .alloc_next()
-                    .expect("Failed to allocate frame")
-            };
+            let frame = GLOBAL_FRAME_ALLOCATOR
+                .lock()
+                .alloc_next()
+                .expect("Failed to allocate frame");
             page_table_ptr[page_indexes[2]]
                 .set_addr(frame.start_address.as_u64(), PRESENT | WRITABLE | HUGE_PAGE);
 
@@ -239,6 +238,108 @@ impl PageAllocator {
         .unwrap()
     }
 
+    /// Maps the page containing a physical address which is not RAM given out by the
+    /// frame allocator, like memory mapped registers or firmware tables, and returns the
+    /// virtual address of `addr`. Pages are taken from the end of the kernel page table,
+    /// below the framebuffer, and a page that is already mapped is reused.
+    /// Extra `flags`, e.g. NO_CACHE for registers, are added to the entry
+    pub fn map_physical(&mut self, addr: PhysAddr, flags: u64) -> VirtAddr {
+        let (p4, p3) = (511, 510);
+        let mut page_table_ptr: &mut PageTable = unsafe { &mut *self.pml4.as_mut_ptr() };
+
+        for index in [p4, p3] {
+            page_table_ptr = unsafe {
+                &mut *VirtAddr::new(
+                    page_table_ptr[index].addr().as_u64() + self.physical_memory_offset,
+                )
+                .as_mut_ptr()
+            };
+        }
+
+        use PageTableFlags::*;
+        let frame = Frame::containing_address(addr);
+        let offset = addr.as_u64() - frame.start_address.as_u64();
+
+        let mut free = None;
+        for p2 in (0..511).rev() {
+            let entry = &page_table_ptr[p2];
+            if entry.is_unused() {
+                free.get_or_insert(p2);
+                continue;
+            }
+            if entry.addr() == frame.start_address && entry.flags() & HUGE_PAGE != 0 {
+                return VirtAddr::new(VirtAddr::from_table_indexes(p4, p3, p2).as_u64() + offset);
+            }
+            // The heap grows up from the start of the table
+            if free.is_some() {
+                break;
+            }
+        }
+
+        let p2 = free.expect("No free page for a physical mapping");
+        page_table_ptr[p2].set_addr(
+            frame.start_address.as_u64(),
+            PRESENT | WRITABLE | HUGE_PAGE | flags,
+        );
+
+        VirtAddr::new(VirtAddr::from_table_indexes(p4, p3, p2).as_u64() + offset)
+    }
+
+    /// Like `map_physical`, for a range which can span several pages, e.g. a large
+    /// device memory region. The pages are mapped at consecutive virtual addresses,
+    /// below the other physical mappings, or reused if the whole range is already mapped
+    pub fn map_physical_range(&mut self, addr: PhysAddr, size: u64, flags: u64) -> VirtAddr {
+        let first = Frame::containing_address(addr);
+        let last = Frame::containing_address(PhysAddr::new(addr.as_u64() + size.max(1) - 1));
+        let count =
+            ((last.start_address.as_u64() - first.start_address.as_u64()) / PAGE_SIZE + 1) as usize;
+        if count == 1 {
+            return self.map_physical(addr, flags);
+        }
+
+        let (p4, p3) = (511, 510);
+        let mut page_table_ptr: &mut PageTable = unsafe { &mut *self.pml4.as_mut_ptr() };
+
+        for index in [p4, p3] {
+            page_table_ptr = unsafe {
+                &mut *VirtAddr::new(
+                    page_table_ptr[index].addr().as_u64() + self.physical_memory_offset,
+                )
+                .as_mut_ptr()
+            };
+        }
+
+        use PageTableFlags::*;
+        let offset = addr.as_u64() - first.start_address.as_u64();
+        let frame_at = |i: usize| first.start_address.as_u64() + i as u64 * PAGE_SIZE;
+
+        // The physical mappings are packed at the end of the table, the heap grows up from the start
+        let mut lowest = 511;
+        while lowest > 0 && !page_table_ptr[lowest - 1].is_unused() {
+            lowest -= 1;
+        }
+
+        for p2 in lowest..511 - count + 1 {
+            let mapped = (0..count).all(|i| {
+                let entry = &page_table_ptr[p2 + i];
+                entry.addr().as_u64() == frame_at(i) && entry.flags() & HUGE_PAGE != 0
+            });
+            if mapped {
+                return VirtAddr::new(VirtAddr::from_table_indexes(p4, p3, p2).as_u64() + offset);
+            }
+        }
+
+        let start = lowest
+            .checked_sub(count)
+            .filter(|&start| (start..lowest).all(|p2| page_table_ptr[p2].is_unused()))
+            .expect("No free pages for a physical mapping");
+        for i in 0..count {
+            page_table_ptr[start + i].set_addr(frame_at(i), PRESENT | WRITABLE | HUGE_PAGE | flags);
+        }
+
+        VirtAddr::new(VirtAddr::from_table_indexes(p4, p3, start).as_u64() + offset)
+    }
+
     /// Frees Page starting at given address
     pub fn free_vaddr(&mut self, addr: VirtAddr) {
         let page_indexes = [addr.p4_index(), addr.p3_index(), addr.p2_index()];
@@ -265,8 +366,9 @@ impl PageAllocator {
         if page_table_ptr[page_indexes[2]].is_present() {
             let frame = Frame::from_start_address(page_table_ptr[page_indexes[2]].addr())
                 .expect("Frame not aligned");
-            unsafe { GLOBAL_FRAME_ALLOCATOR.free(frame) };
+            GLOBAL_FRAME_ALLOCATOR.lock().free(frame);
             page_table_ptr[page_indexes[2]].set_unused();
+            super::smp::flush_tlb(addr);
         }
     }
 }
@@ -280,6 +382,9 @@ pub struct PageFrameAllocator {
     total_pages: u64,
 }
 
+// The bitmap is only accessed behind the lock of the global allocator
+unsafe impl Send for PageFrameAllocator {}
+
 /// Initialize the Page Frame Allocator
 /// # Safety
 /// The Multiboot structure must have a valid Mmap pointer.
@@ -316,21 +421,11 @@ pub fn init_pfa(multiboot_info: &'static MultibootInfo) {
         pfa.set_bit(0);
         pfa.set_bit(1);
 
-        GLOBAL_FRAME_ALLOCATOR.bitmap = pfa.bitmap;
-        GLOBAL_FRAME_ALLOCATOR.multiboot_info = pfa.multiboot_info;
-        GLOBAL_FRAME_ALLOCATOR.total_pages = pfa.total_pages;
+        GLOBAL_FRAME_ALLOCATOR.set(SpinMutex::new(pfa));
     }
 }
 
 impl PageFrameAllocator {
-    pub const fn new() -> Self {
-        PageFrameAllocator {
-            bitmap: 0 as *mut u8,
-            multiboot_info: None,
-            total_pages: 0,
-        }
-    }
-
     /// Returns an iterator over all the avaiable fame start addresses
     unsafe fn frame_iterator(&self) -> impl Iterator<Item = Frame> {
         let multiboot_info = self.multiboot_info.unwrap();
@@ -387,6 +482,18 @@ impl PageFrameAllocator {
         unsafe { (*(self.bitmap.add(byte_index)) & 1 << (8 - bit_index - 1)) != 0 }
     }
 
+    /// Number of frames of usable memory
+    pub fn total_frames(&self) -> u64 {
+        self.total_pages
+    }
+
+    /// Number of frames currently allocated
+    pub fn used_frames(&self) -> u64 {
+        (0..self.total_pages as usize)
+            .filter(|&index| self.is_bit_set(index))
+            .count() as u64
+    }
+
     #[inline]
     pub fn get_bitmap_len(&self) -> usize {
         ((self.total_pages / 8) + 1) as usize
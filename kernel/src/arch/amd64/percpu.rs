@@ -0,0 +1,71 @@
+use alloc::{boxed::Box, collections::VecDeque};
+use core::{
+    arch::asm,
+    ptr::null_mut,
+    sync::atomic::{AtomicPtr, AtomicUsize, Ordering},
+};
+
+use super::registers::Msr;
+use crate::sync::IrqSpinMutex;
+
+/// Processors beyond this number are left halted
+pub const MAX_CPUS: usize = 16;
+
+static CPUS: [AtomicPtr<PerCpu>; MAX_CPUS] = [const { AtomicPtr::new(null_mut()) }; MAX_CPUS];
+static CPU_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// State of a processor, its GS base points to it
+#[repr(C)]
+pub struct PerCpu {
+    /// Address of the structure itself, so `gs:0` finds it
+    this: *const PerCpu,
+    /// Index of the processor, the bootstrap processor is 0
+    pub index: usize,
+    pub apic_id: u8,
+    /// Ids of the tasks placed on this processor, the running one at the back
+    pub run_queue: IrqSpinMutex<VecDeque<u64>>,
+}
+
+// Only the processor owning it changes its fields, besides the locked run queue
+unsafe impl Sync for PerCpu {}
+unsafe impl Send for PerCpu {}
+
+/// Creates the state of the running processor and points its GS base to it.
+/// The GS selector must not be loaded afterwards, that would reset the base
+pub fn init(index: usize, apic_id: u8) -> &'static PerCpu {
+    assert!(index < MAX_CPUS);
+
+    let cpu = Box::leak(Box::new(PerCpu {
+        this: core::ptr::null(),
+        index,
+        apic_id,
+        run_queue: IrqSpinMutex::new(VecDeque::new()),
+    }));
+    cpu.this = cpu;
+
+    unsafe { Msr::write(Msr::GS_BASE, cpu as *const PerCpu as u64) };
+    CPUS[index].store(cpu, Ordering::Release);
+    CPU_COUNT.fetch_max(index + 1, Ordering::AcqRel);
+    cpu
+}
+
+/// State of the running processor, only valid after its `init`
+pub fn current() -> &'static PerCpu {
+    let cpu: *const PerCpu;
+    unsafe {
+        asm!("mov {}, gs:0", out(reg) cpu, options(nostack, readonly, preserves_flags));
+        &*cpu
+    }
+}
+
+/// Number of processors started
+pub fn cpu_count() -> usize {
+    CPU_COUNT.load(Ordering::Acquire)
+}
+
+/// The processors started, in index order
+pub fn cpus() -> impl Iterator<Item = &'static PerCpu> {
+    CPUS[..cpu_count()]
+        .iter()
+        .filter_map(|cpu| unsafe { cpu.load(Ordering::Acquire).as_ref() })
+}
@@ -1,4 +1,4 @@
-use crate::sync::SpinMutex;
+use crate::sync::IrqSpinMutex;
 
 use super::io::{inb, outb};
 
@@ -11,8 +11,9 @@ const CMD_END_OF_INTERRUPT: u8 = 0x20;
 // The mode in which we want to run our PICs.
 const MODE_8086: u8 = 0x01;
 
-pub static PICS: SpinMutex<ChainedPics> =
-    SpinMutex::new(unsafe { ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET) });
+/// Taken by every interrupt handler to acknowledge its interrupt
+pub static PICS: IrqSpinMutex<ChainedPics> =
+    IrqSpinMutex::new(unsafe { ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET) });
 
 /// An individual PIC chip.
 struct Pic {
@@ -137,6 +138,34 @@ impl ChainedPics {
         self.write_masks(u8::MAX, u8::MAX)
     }
 
+    /// Unmasks the given IRQ line (0-15).
+    /// Lines on the secondary PIC also need the cascade line (IRQ 2) unmasked
+    /// # Safety
+    /// The PICs must be initialized, and the line must have a handler installed
+    pub unsafe fn unmask(&mut self, irq: u8) {
+        let [mut mask1, mut mask2] = self.read_masks();
+        if irq < 8 {
+            mask1 &= !(1 << irq);
+        } else {
+            mask2 &= !(1 << (irq - 8));
+            mask1 &= !(1 << 2);
+        }
+        self.write_masks(mask1, mask2);
+    }
+
+    /// Masks the given IRQ line (0-15).
+    /// # Safety
+    /// The PICs must be initialized
+    pub unsafe fn mask(&mut self, irq: u8) {
+        let [mut mask1, mut mask2] = self.read_masks();
+        if irq < 8 {
+            mask1 |= 1 << irq;
+        } else {
+            mask2 |= 1 << (irq - 8);
+        }
+        self.write_masks(mask1, mask2);
+    }
+
     /// Do we handle this interrupt?
     pub fn handles_interrupt(&self, interrupt_id: u8) -> bool {
         self.pics.iter().any(|p| p.handles_interrupt(interrupt_id))
@@ -156,9 +185,10 @@ impl ChainedPics {
 }
 
 pub mod Timer {
+    use core::ptr::{addr_of_mut, read_volatile, write_volatile};
+
     use crate::arch::io::outb;
 
-    pub static mut COUNT_DOWN: u64 = 0;
     pub static mut UPTIME: u64 = 0;
 
     pub const FREQUENCY: u32 = 1193180;
@@ -177,18 +207,25 @@ pub mod Timer {
             outb(CHANNEL_0_PORT, (divisor & 0xFF) as u8);
             outb(CHANNEL_0_PORT, ((divisor >> 8) & 0xFF) as u8);
         }
+        crate::arch::interrupts::register_irq(0, tick);
     }
 
-    /// Sleep for a number of milliseconds
-    pub fn sleep(millis: u64) {
+    /// IRQ 0 handler, counts the milliseconds until `tick::init` masks it
+    fn tick(_irq: u8) {
         unsafe {
-            let volatile = &mut COUNT_DOWN as *mut u64;
+            let volatile = addr_of_mut!(UPTIME);
+            let count = read_volatile(volatile);
+            write_volatile(volatile, count + 1);
+        }
+    }
 
-            core::ptr::write_volatile(volatile, millis);
+    /// Milliseconds since boot
+    pub fn uptime() -> u64 {
+        crate::arch::clock::monotonic_ns() / 1_000_000
+    }
 
-            while core::ptr::read_volatile(volatile) > 0 {
-                crate::arch::interrupts::hlt();
-            }
-        }
+    /// Sleep for a number of milliseconds
+    pub fn sleep(millis: u64) {
+        crate::arch::clock::sleep_ns(millis * 1_000_000);
     }
 }
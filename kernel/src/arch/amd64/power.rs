@@ -0,0 +1,166 @@
+use core::arch::asm;
+
+use super::{
+    acpi::{self, Fadt, GenericAddress},
+    addressing::PhysAddr,
+    interrupts::{self, DescriptorTablePointer},
+    io::{inb, inw, outb, outw},
+    paging::PageTableFlags::NO_CACHE,
+    pic::Timer,
+};
+use crate::{logging, mm::ALLOCATOR};
+
+const KEYBOARD_CONTROLLER: u16 = 0x64;
+const STATUS_INPUT_FULL: u8 = 1 << 1;
+/// Pulses the CPU reset line
+const COMMAND_RESET: u8 = 0xFE;
+
+// PM1 control register bits
+const SCI_EN: u16 = 1;
+const SLP_TYP_SHIFT: u16 = 10;
+const SLP_EN: u16 = 1 << 13;
+
+/// Ports and values powering off emulators without ACPI: QEMU, Bochs and older QEMU, VirtualBox
+const EMULATOR_SHUTDOWN: [(u16, u16); 3] = [(0x604, 0x2000), (0xB004, 0x2000), (0x4004, 0x3400)];
+
+// AML opcodes of the \_S5_ object
+const AML_NAME_OP: u8 = 0x08;
+const AML_ROOT_PREFIX: u8 = b'\\';
+const AML_PACKAGE_OP: u8 = 0x12;
+const AML_BYTE_PREFIX: u8 = 0x0A;
+
+/// Restarts the machine with the ACPI reset register, then the keyboard controller.
+/// As a last resort the CPU triple faults, which also resets it
+pub fn reboot() -> ! {
+    info!("Rebooting");
+    interrupts::disable();
+
+    if let Some(fadt) = acpi::fadt() {
+        if fadt.flags & Fadt::RESET_REG_SUP != 0 {
+            if let Some(register) = fadt.reset_register {
+                write_register(register, fadt.reset_value);
+            }
+        }
+    }
+
+    unsafe {
+        // Wait for the controller to accept a command
+        for _ in 0..0x10000 {
+            if inb(KEYBOARD_CONTROLLER) & STATUS_INPUT_FULL == 0 {
+                break;
+            }
+        }
+        outb(KEYBOARD_CONTROLLER, COMMAND_RESET);
+
+        let empty = DescriptorTablePointer { limit: 0, base: 0 };
+        asm!("lidt [{}]; int3", in(reg) &empty, options(nostack));
+    }
+
+    crate::hlt_loop()
+}
+
+/// Powers the machine off by entering the ACPI S5 sleep state, or through the
+/// ports of the emulators when there is no ACPI or it fails
+pub fn shutdown() -> ! {
+    info!("Powering off");
+
+    if let (Some(fadt), Some((sleep_a, sleep_b))) = (acpi::fadt(), s5_sleep_types()) {
+        enable_acpi(&fadt);
+        interrupts::disable();
+        unsafe {
+            outw(fadt.pm1a_control as u16, sleep_a << SLP_TYP_SHIFT | SLP_EN);
+            if fadt.pm1b_control != 0 {
+                outw(fadt.pm1b_control as u16, sleep_b << SLP_TYP_SHIFT | SLP_EN);
+            }
+        }
+    }
+
+    interrupts::disable();
+    for (port, value) in EMULATOR_SHUTDOWN {
+        unsafe { outw(port, value) };
+    }
+
+    warn!("Power off failed, halting");
+    crate::hlt_loop()
+}
+
+/// Stops the CPU without powering off
+pub fn halt() -> ! {
+    info!("System halted");
+    interrupts::disable();
+    crate::hlt_loop()
+}
+
+fn write_register(register: GenericAddress, value: u8) {
+    match register.address_space {
+        GenericAddress::SYSTEM_IO => unsafe { outb(register.address as u16, value) },
+        GenericAddress::SYSTEM_MEMORY => {
+            let virt = ALLOCATOR
+                .lock()
+                .page_allocator
+                .as_mut()
+                .unwrap()
+                .map_physical(PhysAddr::new(register.address), NO_CACHE);
+            unsafe { core::ptr::write_volatile(virt.as_mut_ptr::<u8>(), value) };
+        }
+        _ => {}
+    }
+}
+
+/// Switches from legacy to ACPI mode if the firmware has not done it yet,
+/// the PM1 registers are ignored until then
+fn enable_acpi(fadt: &Fadt) {
+    let enabled = || unsafe { inw(fadt.pm1a_control as u16) } & SCI_EN != 0;
+    if enabled() || fadt.smi_command == 0 || fadt.acpi_enable == 0 {
+        return;
+    }
+
+    unsafe { outb(fadt.smi_command as u16, fadt.acpi_enable) };
+    for _ in 0..300 {
+        if enabled() {
+            return;
+        }
+        Timer::sleep(1);
+    }
+    warn!("Failed to enable ACPI mode");
+}
+
+/// Finds the SLP_TYPa and SLP_TYPb values of the S5 (soft off) state in the
+/// `\_S5_` package of the DSDT, which is defined as
+/// `NameOp [\] _S5_ PackageOp PkgLength NumElements SLP_TYPa SLP_TYPb ...`
+fn s5_sleep_types() -> Option<(u16, u16)> {
+    let dsdt = acpi::dsdt()?;
+    let aml = dsdt.body();
+
+    let start = aml.windows(4).position(|name| name == b"_S5_")?;
+    let named = match start {
+        0 => false,
+        1 => aml[0] == AML_NAME_OP,
+        _ => {
+            aml[start - 1] == AML_NAME_OP
+                || (aml[start - 1] == AML_ROOT_PREFIX && aml[start - 2] == AML_NAME_OP)
+        }
+    };
+    if !named || aml.get(start + 4) != Some(&AML_PACKAGE_OP) {
+        return None;
+    }
+
+    // The top 2 bits of the first PkgLength byte are the number of bytes following it
+    let mut i = start + 5;
+    i += (*aml.get(i)? >> 6) as usize + 1;
+    // NumElements
+    i += 1;
+
+    // Small integers are a ZeroOp or OneOp byte, the others follow a BytePrefix
+    let mut integer = || {
+        if *aml.get(i)? == AML_BYTE_PREFIX {
+            i += 1;
+        }
+        let value = *aml.get(i)?;
+        i += 1;
+        Some(value as u16)
+    };
+    let sleep_a = integer()?;
+    let sleep_b = integer()?;
+    Some((sleep_a, sleep_b))
+}
@@ -49,6 +49,39 @@ impl Cr3 {
     }
 }
 
+/// Model specific registers, read with `rdmsr` and written with `wrmsr`
+pub struct Msr;
+
+impl Msr {
+    /// Base address of the GS segment
+    pub const GS_BASE: u32 = 0xC000_0101;
+    /// TSC value at which the local APIC timer fires in the TSC-deadline mode, 0 disarms it
+    pub const TSC_DEADLINE: u32 = 0x6E0;
+
+    /// Reads a model specific register.
+    ///
+    /// ## Safety
+    ///
+    /// The register must exist on the processor, reading one which does not faults.
+    #[inline]
+    pub unsafe fn read(msr: u32) -> u64 {
+        let (low, high): (u32, u32);
+        asm!("rdmsr", in("ecx") msr, out("eax") low, out("edx") high, options(nomem, nostack, preserves_flags));
+        ((high as u64) << 32) | low as u64
+    }
+
+    /// Writes a model specific register.
+    ///
+    /// ## Safety
+    ///
+    /// The register must exist on the processor, and the value must not break what the kernel
+    /// relies on, e.g. the GS base of `percpu`.
+    #[inline]
+    pub unsafe fn write(msr: u32, value: u64) {
+        asm!("wrmsr", in("ecx") msr, in("eax") value as u32, in("edx") (value >> 32) as u32, options(nostack, preserves_flags));
+    }
+}
+
 pub struct Rflags;
 
 impl Rflags {
@@ -0,0 +1,253 @@
+use core::ops::RangeInclusive;
+
+use super::{
+    acpi, interrupts,
+    io::{inb, outb},
+};
+use crate::{arch::clock, logging};
+
+const CMOS_ADDRESS: u16 = 0x70;
+const CMOS_DATA: u16 = 0x71;
+/// Keeps NMIs disabled while a register is selected
+const NMI_DISABLE: u8 = 1 << 7;
+
+// CMOS registers
+const SECONDS: u8 = 0x00;
+const MINUTES: u8 = 0x02;
+const HOURS: u8 = 0x04;
+const DAY: u8 = 0x07;
+const MONTH: u8 = 0x08;
+const YEAR: u8 = 0x09;
+const STATUS_A: u8 = 0x0A;
+const STATUS_B: u8 = 0x0B;
+
+const STATUS_A_UPDATE_IN_PROGRESS: u8 = 1 << 7;
+const STATUS_B_SET: u8 = 1 << 7;
+const STATUS_B_24_HOUR: u8 = 1 << 1;
+const STATUS_B_BINARY: u8 = 1 << 2;
+/// Set in the hours register for PM times in the 12 hour mode
+const HOURS_PM: u8 = 1 << 7;
+
+pub const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// A calendar date and time, in UTC
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DateTime {
+    pub year: i64,
+    /// 1 to 12
+    pub month: u8,
+    /// 1 to 31
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl DateTime {
+    /// Seconds since 1970-01-01 00:00:00 UTC
+    pub fn to_unix(&self) -> i64 {
+        days_from_civil(self.year, self.month as i64, self.day as i64) * SECONDS_PER_DAY
+            + self.hour as i64 * 3600
+            + self.minute as i64 * 60
+            + self.second as i64
+    }
+
+    pub fn from_unix(seconds: i64) -> DateTime {
+        let (year, month, day) = civil_from_days(seconds.div_euclid(SECONDS_PER_DAY));
+        let time = seconds.rem_euclid(SECONDS_PER_DAY);
+        DateTime {
+            year,
+            month: month as u8,
+            day: day as u8,
+            hour: (time / 3600) as u8,
+            minute: (time / 60 % 60) as u8,
+            second: (time % 60) as u8,
+        }
+    }
+}
+
+/// Days since 1970-01-01 of a date of the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    // Years start in March, so the leap day is the last one
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Inverse of `days_from_civil`
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+unsafe fn read_register(register: u8) -> u8 {
+    outb(CMOS_ADDRESS, NMI_DISABLE | register);
+    inb(CMOS_DATA)
+}
+
+unsafe fn write_register(register: u8, value: u8) {
+    outb(CMOS_ADDRESS, NMI_DISABLE | register);
+    outb(CMOS_DATA, value);
+}
+
+fn from_bcd(value: u8) -> u8 {
+    (value >> 4) * 10 + (value & 0x0F)
+}
+
+fn to_bcd(value: u8) -> u8 {
+    ((value / 10) << 4) | (value % 10)
+}
+
+/// Year of a two digit year without a century, in the range `write` accepts
+fn first_year(year: i64) -> i64 {
+    if year >= 70 {
+        1900 + year
+    } else {
+        2000 + year
+    }
+}
+
+/// Years the clock can hold: two digits from 1970 without a century register,
+/// otherwise as many centuries as the century register holds in its format
+fn years(century: u8, binary: bool) -> RangeInclusive<i64> {
+    match (century, binary) {
+        (0, _) => 1970..=2099,
+        (_, true) => 1970..=u8::MAX as i64 * 100 + 99,
+        (_, false) => 1970..=9999,
+    }
+}
+
+/// Index of the CMOS century register given by the FADT, 0 when there is none
+fn century_register() -> u8 {
+    acpi::fadt().map_or(0, |fadt| fadt.century)
+}
+
+/// Raw registers, in the order seconds, minutes, hours, day, month, year, century
+unsafe fn read_raw(century: u8) -> [u8; 7] {
+    while read_register(STATUS_A) & STATUS_A_UPDATE_IN_PROGRESS != 0 {
+        core::hint::spin_loop();
+    }
+    [
+        read_register(SECONDS),
+        read_register(MINUTES),
+        read_register(HOURS),
+        read_register(DAY),
+        read_register(MONTH),
+        read_register(YEAR),
+        if century != 0 {
+            read_register(century)
+        } else {
+            0
+        },
+    ]
+}
+
+/// Reads the date of the CMOS real time clock, which is kept in UTC. The registers are read
+/// until two reads agree, so an update of the clock in the middle is not seen
+pub fn read() -> DateTime {
+    let century = century_register();
+
+    let (raw, status_b) = interrupts::free(|| unsafe {
+        let mut raw = read_raw(century);
+        loop {
+            let again = read_raw(century);
+            if again == raw {
+                break;
+            }
+            raw = again;
+        }
+        (raw, read_register(STATUS_B))
+    });
+
+    let binary = status_b & STATUS_B_BINARY != 0;
+    let decode = |value: u8| if binary { value } else { from_bcd(value) };
+
+    // The PM bit is not part of the BCD value
+    let pm = status_b & STATUS_B_24_HOUR == 0 && raw[2] & HOURS_PM != 0;
+    let mut hour = decode(raw[2] & !HOURS_PM);
+    if status_b & STATUS_B_24_HOUR == 0 {
+        hour %= 12;
+        if pm {
+            hour += 12;
+        }
+    }
+
+    let year = decode(raw[5]) as i64;
+    let year = if century != 0 {
+        decode(raw[6]) as i64 * 100 + year
+    } else {
+        // Without a century register, two digit years are taken in 1970 to 2099
+        first_year(year)
+    };
+
+    DateTime {
+        year,
+        month: decode(raw[4]),
+        day: decode(raw[3]),
+        hour,
+        minute: decode(raw[1]),
+        second: decode(raw[0]),
+    }
+}
+
+/// Sets the CMOS real time clock, in the format it already uses.
+/// Returns false without changing it if it can not hold the year
+pub fn write(date: &DateTime) -> bool {
+    let century = century_register();
+
+    interrupts::free(|| unsafe {
+        let status_b = read_register(STATUS_B);
+        let binary = status_b & STATUS_B_BINARY != 0;
+        if !years(century, binary).contains(&date.year) {
+            return false;
+        }
+        let encode = |value: u8| if binary { value } else { to_bcd(value) };
+
+        let hour = if status_b & STATUS_B_24_HOUR != 0 {
+            encode(date.hour)
+        } else {
+            // 12 AM and 12 PM, not 0
+            let pm = if date.hour >= 12 { HOURS_PM } else { 0 };
+            match date.hour % 12 {
+                0 => encode(12) | pm,
+                hour => encode(hour) | pm,
+            }
+        };
+
+        // Stop the updates while the registers change
+        write_register(STATUS_B, status_b | STATUS_B_SET);
+        write_register(SECONDS, encode(date.second));
+        write_register(MINUTES, encode(date.minute));
+        write_register(HOURS, hour);
+        write_register(DAY, encode(date.day));
+        write_register(MONTH, encode(date.month));
+        write_register(YEAR, encode((date.year % 100) as u8));
+        if century != 0 {
+            write_register(century, encode((date.year / 100) as u8));
+        }
+        write_register(STATUS_B, status_b);
+        true
+    })
+}
+
+/// Sets the realtime clock from the CMOS clock
+pub fn init() {
+    let date = read();
+    clock::set_realtime_ns(date.to_unix() * clock::NANOS_PER_SEC as i64);
+    info!(
+        "RTC: {}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        date.year, date.month, date.day, date.hour, date.minute, date.second
+    );
+}
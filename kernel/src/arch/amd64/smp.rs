@@ -0,0 +1,221 @@
+use alloc::{boxed::Box, vec};
+use core::{
+    arch::asm,
+    ptr::addr_of,
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+};
+
+use super::{
+    acpi,
+    addressing::{VirtAddr, KERNEL_BASE},
+    apic::APIC,
+    gdt::{self, CpuTables},
+    interrupts,
+    paging::{PageTable, KERNEL_CR3},
+    percpu::{self, MAX_CPUS},
+    pic::Timer,
+    tick,
+};
+use crate::{logging, sync::SpinMutex};
+
+/// Vector of the IPI asking the other processors to flush a page from their TLB
+pub const TLB_SHOOTDOWN_VECTOR: u8 = 0xFD;
+
+/// Physical address the trampoline is copied to, below 1 MiB and page aligned
+/// so a startup IPI can point to it. Must match `AP_TRAMPOLINE` in `start.S`
+const AP_TRAMPOLINE: u64 = 0x8000;
+const AP_STACK_SIZE: usize = 64 * 1024;
+
+// Symbols from `start.S`, only their addresses are used
+extern "C" {
+    static ap_trampoline: u8;
+    static ap_trampoline_end: u8;
+    static ap_cr3: u8;
+    static ap_stack: u8;
+    static ap_entry: u8;
+    static ap_cpu: u8;
+    static ap_ready: u8;
+    static low_pdpt: u8;
+}
+
+/// Set by an application processor once it is running, so the next one can be started
+static AP_ONLINE: AtomicBool = AtomicBool::new(false);
+
+/// Only one shootdown at a time, the address and the number of processors
+/// which did not flush it yet are shared with the handlers
+static SHOOTDOWN: SpinMutex<()> = SpinMutex::new(());
+static SHOOTDOWN_ADDRESS: AtomicU64 = AtomicU64::new(0);
+static SHOOTDOWN_PENDING: AtomicUsize = AtomicUsize::new(0);
+
+/// Address of a trampoline symbol in the copy
+fn trampoline_address(symbol: *const u8) -> *mut u64 {
+    let offset = symbol as u64 - addr_of!(ap_trampoline) as u64;
+    (KERNEL_BASE + AP_TRAMPOLINE + offset) as *mut u64
+}
+
+/// Sets up the per-CPU state of the bootstrap processor and starts the application
+/// processors listed in the ACPI MADT, one at a time. They need the APICs
+pub fn init() {
+    let Some(apic) = APIC.get() else {
+        percpu::init(0, 0);
+        info!("No local APIC, only the bootstrap processor runs");
+        return;
+    };
+    let bsp_id = apic.local.id();
+    percpu::init(0, bsp_id);
+
+    let Some(madt) = acpi::madt() else {
+        return;
+    };
+
+    unsafe {
+        let size = addr_of!(ap_trampoline_end) as u64 - addr_of!(ap_trampoline) as u64;
+        core::ptr::copy_nonoverlapping(
+            addr_of!(ap_trampoline),
+            (KERNEL_BASE + AP_TRAMPOLINE) as *mut u8,
+            size as usize,
+        );
+    }
+
+    // Identity map the low memory again while the processors switch to long mode
+    let pml4 = unsafe { &mut *((*KERNEL_CR3 + KERNEL_BASE) as *mut PageTable) };
+    use super::paging::PageTableFlags::*;
+    pml4[0].set_addr(addr_of!(low_pdpt) as u64 - KERNEL_BASE, PRESENT | WRITABLE);
+
+    let mut index = 1;
+    for cpu in madt.local_apics.iter().filter(|cpu| cpu.enabled) {
+        if cpu.apic_id == bsp_id {
+            continue;
+        }
+        if index == MAX_CPUS {
+            warn!("Only {} processors are supported", MAX_CPUS);
+            break;
+        }
+        if start_application_processor(index, cpu.apic_id) {
+            index += 1;
+        } else {
+            error!("CPU with APIC ID {} did not start", cpu.apic_id);
+        }
+    }
+
+    pml4[0].set_unused();
+    flush_all();
+
+    info!("{} processors online", percpu::cpu_count());
+}
+
+/// Waits up to `ms` milliseconds for a flag to be set
+fn wait_for(flag: impl Fn() -> bool, ms: u64) -> bool {
+    for _ in 0..ms {
+        if flag() {
+            return true;
+        }
+        Timer::sleep(1);
+    }
+    flag()
+}
+
+/// INIT-SIPI-SIPI sequence, waits up to 100 ms for the processor to read the trampoline
+/// variables, after which it owns `index`, and 100 ms more for it to run `ap_main`
+fn start_application_processor(index: usize, apic_id: u8) -> bool {
+    let stack = Box::leak(vec![0u8; AP_STACK_SIZE].into_boxed_slice());
+    let ready = unsafe { &*(trampoline_address(addr_of!(ap_ready)) as *const AtomicU64) };
+
+    unsafe {
+        *trampoline_address(addr_of!(ap_cr3)) = *KERNEL_CR3;
+        *trampoline_address(addr_of!(ap_stack)) = stack.as_ptr() as u64 + AP_STACK_SIZE as u64;
+        *trampoline_address(addr_of!(ap_entry)) = ap_main as *const () as u64;
+        *trampoline_address(addr_of!(ap_cpu)) = index as u64;
+    }
+    ready.store(0, Ordering::Release);
+    AP_ONLINE.store(false, Ordering::Release);
+
+    let local = &APIC.local;
+    let page = (AP_TRAMPOLINE >> 12) as u8;
+    unsafe {
+        local.send_init(apic_id);
+        Timer::sleep(10);
+        // The second startup IPI is only needed if the first one was missed
+        for _ in 0..2 {
+            local.send_startup(apic_id, page);
+            Timer::sleep(1);
+            if ready.load(Ordering::Acquire) != 0 {
+                break;
+            }
+        }
+    }
+
+    if !wait_for(|| ready.load(Ordering::Acquire) != 0, 100) {
+        // Park it, a late start would read the variables of the next processor
+        unsafe { local.send_init(apic_id) };
+        return false;
+    }
+    if !wait_for(|| AP_ONLINE.load(Ordering::Acquire), 100) {
+        warn!("CPU {} is slow to come online", index);
+    }
+    true
+}
+
+/// Entry point of the application processors, called by the trampoline with the
+/// kernel page tables and the stack prepared by the bootstrap processor
+extern "C" fn ap_main(index: u64) -> ! {
+    let tables = Box::leak(Box::new(CpuTables::new()));
+    gdt::init_cpu(tables);
+    interrupts::load_idt();
+
+    let local = &APIC.local;
+    unsafe { local.enable() };
+    if tick::is_tickless() {
+        tick::init_cpu();
+    }
+    let cpu = percpu::init(index as usize, local.id());
+
+    info!("CPU {} online (APIC ID {})", cpu.index, cpu.apic_id);
+    AP_ONLINE.store(true, Ordering::Release);
+
+    // Tasks only run on the bootstrap processor for now, so the run queue of the
+    // AP stays empty. Nothing arms the timer, so only IPIs wake the processor
+    loop {
+        interrupts::enable_and_hlt();
+    }
+}
+
+fn invlpg(addr: VirtAddr) {
+    unsafe { asm!("invlpg [{}]", in(reg) addr.as_u64(), options(nostack, preserves_flags)) }
+}
+
+/// Flushes the whole TLB of the running processor, except global pages
+fn flush_all() {
+    unsafe {
+        asm!("mov {0}, cr3", "mov cr3, {0}", out(reg) _, options(nostack, preserves_flags));
+    }
+}
+
+/// Removes a page whose mapping changed from the TLB of every processor.
+/// Waits for the others to flush it, they must have interrupts enabled
+pub fn flush_tlb(addr: VirtAddr) {
+    invlpg(addr);
+
+    let others = percpu::cpus().count().saturating_sub(1);
+    let Some(apic) = APIC.get() else {
+        return;
+    };
+    if others == 0 {
+        return;
+    }
+
+    let _guard = SHOOTDOWN.lock();
+    SHOOTDOWN_ADDRESS.store(addr.as_u64(), Ordering::Release);
+    SHOOTDOWN_PENDING.store(others, Ordering::Release);
+    unsafe { apic.local.send_to_others(TLB_SHOOTDOWN_VECTOR) };
+
+    while SHOOTDOWN_PENDING.load(Ordering::Acquire) != 0 {
+        core::hint::spin_loop();
+    }
+}
+
+/// Called by the shootdown IPI handler
+pub fn handle_tlb_shootdown() {
+    invlpg(VirtAddr::new(SHOOTDOWN_ADDRESS.load(Ordering::Acquire)));
+    SHOOTDOWN_PENDING.fetch_sub(1, Ordering::AcqRel);
+}
@@ -0,0 +1,118 @@
+use core::{
+    arch::x86_64::__cpuid,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+
+use super::{
+    apic::{self, APIC},
+    clock::{self, NANOS_PER_SEC},
+    interrupts,
+    registers::Msr,
+};
+use crate::logging;
+
+/// Vector of the local APIC timer, which only wakes the processor
+pub const TIMER_VECTOR: u8 = 0xFC;
+
+const CALIBRATION_NS: u64 = 10_000_000;
+
+/// Set once the local APIC timers replace the periodic PIT interrupts
+static TICKLESS: AtomicBool = AtomicBool::new(false);
+/// The timers fire at a TSC value instead of counting down
+static TSC_DEADLINE: AtomicBool = AtomicBool::new(false);
+/// Ticks per second of the timers in the one-shot mode
+static FREQUENCY: AtomicU64 = AtomicU64::new(0);
+
+/// Measures the rate of the local APIC timer with the monotonic clock
+fn calibrate() -> u64 {
+    let local = &APIC.local;
+    interrupts::free(|| unsafe {
+        local.setup_timer(TIMER_VECTOR, apic::TIMER_ONE_SHOT);
+        let start = clock::monotonic_ns();
+        local.start_timer(u32::MAX);
+        while clock::monotonic_ns() - start < CALIBRATION_NS {
+            core::hint::spin_loop();
+        }
+        let ticks = u32::MAX - local.timer_count();
+        let elapsed = clock::monotonic_ns() - start;
+        local.start_timer(0);
+        ticks as u64 * NANOS_PER_SEC / elapsed
+    })
+}
+
+/// Stops the periodic PIT interrupts and wakes the processors from the local APIC
+/// timers, only when a deadline is reached. The TSC-deadline mode is used when the
+/// TSC is the clocksource. Needs the APICs and a clocksource, otherwise the PIT keeps
+/// interrupting every millisecond
+pub fn init() {
+    if APIC.get().is_none() {
+        info!("No local APIC, the PIT keeps ticking");
+        return;
+    }
+    if !clock::is_precise() {
+        info!("No clocksource, the PIT keeps ticking");
+        return;
+    }
+
+    let tsc_deadline = __cpuid(1).ecx & (1 << 24) != 0 && clock::tsc_at(0).is_some();
+    if tsc_deadline {
+        TSC_DEADLINE.store(true, Ordering::Relaxed);
+        info!("Tickless timer in TSC-deadline mode");
+    } else {
+        let frequency = calibrate();
+        FREQUENCY.store(frequency, Ordering::Relaxed);
+        info!(
+            "Tickless timer in one-shot mode at {}.{:03} MHz",
+            frequency / 1_000_000,
+            frequency / 1000 % 1000
+        );
+    }
+
+    init_cpu();
+    TICKLESS.store(true, Ordering::Release);
+    // The uptime now comes from the clocksource
+    interrupts::mask_irq(0);
+}
+
+/// Sets up the timer of the running processor, each application processor calls it
+pub fn init_cpu() {
+    let mode = if TSC_DEADLINE.load(Ordering::Relaxed) {
+        apic::TIMER_TSC_DEADLINE
+    } else {
+        apic::TIMER_ONE_SHOT
+    };
+    unsafe { APIC.local.setup_timer(TIMER_VECTOR, mode) };
+}
+
+pub fn is_tickless() -> bool {
+    TICKLESS.load(Ordering::Acquire)
+}
+
+/// Programs the timer of the running processor to fire at a monotonic time.
+/// A deadline in the past fires at once
+fn arm(deadline_ns: u64) {
+    if TSC_DEADLINE.load(Ordering::Relaxed) {
+        if let Some(tsc) = clock::tsc_at(deadline_ns) {
+            unsafe { Msr::write(Msr::TSC_DEADLINE, tsc.max(1)) };
+        }
+    } else {
+        let ns = deadline_ns.saturating_sub(clock::monotonic_ns());
+        let ticks = ns as u128 * FREQUENCY.load(Ordering::Relaxed) as u128 / NANOS_PER_SEC as u128;
+        // A longer wait fires early, the caller halts again
+        APIC.local
+            .start_timer(ticks.clamp(1, u32::MAX as u128) as u32);
+    }
+}
+
+/// Halts the processor until the next interrupt, which comes at the latest at the
+/// monotonic time `deadline_ns` if there is one. Without a deadline only other
+/// interrupts wake it.
+///
+/// Interrupts must be disabled, so the caller can check what it waits for first:
+/// they are enabled by the halt and no wakeup is missed
+pub fn halt_until(deadline_ns: Option<u64>) {
+    if let Some(deadline) = deadline_ns.filter(|_| is_tickless()) {
+        arm(deadline);
+    }
+    interrupts::enable_and_hlt();
+}
@@ -0,0 +1,396 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use alloc::{boxed::Box, string::String};
+
+use crate::arch::io::{inb, inw, outb, outw};
+use crate::arch::pic::Timer;
+use crate::logging;
+use crate::task::WaitQueue;
+
+use super::blockdev::BlockDevice;
+
+pub const SECTOR_SIZE: usize = 512;
+
+/// Milliseconds to wait for a drive before giving up
+const TIMEOUT_MS: u64 = 1000;
+
+// Offsets from the I/O base port
+const REG_DATA: u16 = 0;
+const REG_ERROR: u16 = 1;
+const REG_SECCOUNT: u16 = 2;
+const REG_LBA0: u16 = 3;
+const REG_LBA1: u16 = 4;
+const REG_LBA2: u16 = 5;
+const REG_DRIVE: u16 = 6;
+const REG_STATUS: u16 = 7;
+const REG_COMMAND: u16 = 7;
+
+// Status register bits
+const STATUS_ERR: u8 = 1;
+const STATUS_DRQ: u8 = 1 << 3;
+const STATUS_DF: u8 = 1 << 5;
+const STATUS_BSY: u8 = 1 << 7;
+
+// Commands
+const CMD_READ_PIO: u8 = 0x20;
+const CMD_READ_PIO_EXT: u8 = 0x24;
+const CMD_WRITE_PIO: u8 = 0x30;
+const CMD_WRITE_PIO_EXT: u8 = 0x34;
+const CMD_CACHE_FLUSH: u8 = 0xE7;
+const CMD_CACHE_FLUSH_EXT: u8 = 0xEA;
+const CMD_IDENTIFY: u8 = 0xEC;
+
+/// Set by the IRQ handler of each channel, cleared before issuing a command
+static IRQ_RECEIVED: [AtomicBool; 2] = [AtomicBool::new(false), AtomicBool::new(false)];
+/// Tasks waiting for the IRQ of each channel
+static IRQ_WAITERS: [WaitQueue; 2] = [WaitQueue::new(), WaitQueue::new()];
+
+/// An IDE channel, each one can have a master and a slave drive
+#[derive(Debug, Clone, Copy)]
+struct Channel {
+    index: usize,
+    io_base: u16,
+    control_base: u16,
+    irq: u8,
+}
+
+const CHANNELS: [Channel; 2] = [
+    Channel {
+        index: 0,
+        io_base: 0x1F0,
+        control_base: 0x3F6,
+        irq: 14,
+    },
+    Channel {
+        index: 1,
+        io_base: 0x170,
+        control_base: 0x376,
+        irq: 15,
+    },
+];
+
+impl Channel {
+    unsafe fn status(&self) -> u8 {
+        inb(self.io_base + REG_STATUS)
+    }
+
+    /// Reading the alternate status register 4 times gives the drive the 400ns
+    /// it needs to update its status after a drive select or a command
+    unsafe fn delay(&self) {
+        for _ in 0..4 {
+            inb(self.control_base);
+        }
+    }
+
+    /// Selects the drive and sets up the LBA, sector count and drive registers for a command.
+    /// A count of 0 means the maximum (256 sectors for LBA28, 65536 for LBA48)
+    unsafe fn setup(&self, slave: bool, lba48: bool, lba: u64, count: u16) {
+        let slave_bit = (slave as u8) << 4;
+
+        if lba48 {
+            outb(self.io_base + REG_DRIVE, 0x40 | slave_bit);
+            self.delay();
+            // High bytes go first
+            outb(self.io_base + REG_SECCOUNT, (count >> 8) as u8);
+            outb(self.io_base + REG_LBA0, (lba >> 24) as u8);
+            outb(self.io_base + REG_LBA1, (lba >> 32) as u8);
+            outb(self.io_base + REG_LBA2, (lba >> 40) as u8);
+        } else {
+            outb(
+                self.io_base + REG_DRIVE,
+                0xE0 | slave_bit | ((lba >> 24) & 0x0F) as u8,
+            );
+            self.delay();
+        }
+        outb(self.io_base + REG_SECCOUNT, count as u8);
+        outb(self.io_base + REG_LBA0, lba as u8);
+        outb(self.io_base + REG_LBA1, (lba >> 8) as u8);
+        outb(self.io_base + REG_LBA2, (lba >> 16) as u8);
+    }
+
+    /// Sends a command, the IRQ flag is cleared first so only the
+    /// interrupt caused by this command is waited for
+    unsafe fn command(&self, command: u8) {
+        IRQ_RECEIVED[self.index].store(false, Ordering::Release);
+        outb(self.io_base + REG_COMMAND, command);
+        self.delay();
+    }
+
+    /// Busy waits until the drive is not busy and checks for errors
+    unsafe fn poll(&self) -> Option<u8> {
+        let deadline = Timer::uptime() + TIMEOUT_MS;
+        loop {
+            let status = self.status();
+            if status & STATUS_BSY == 0 {
+                if status & (STATUS_ERR | STATUS_DF) != 0 {
+                    error!(
+                        "ATA error: status 0x{:x}, error 0x{:x}",
+                        status,
+                        inb(self.io_base + REG_ERROR)
+                    );
+                    return None;
+                }
+                return Some(status);
+            }
+            if Timer::uptime() > deadline {
+                return None;
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Sleeps until the drive raises its IRQ, then checks the status.
+    /// If interrupts are disabled the status is polled instead
+    unsafe fn wait_irq(&self) -> Option<u8> {
+        if crate::arch::interrupts::are_enabled() {
+            let received = IRQ_WAITERS[self.index].wait_while_timeout(
+                || !IRQ_RECEIVED[self.index].swap(false, Ordering::Acquire),
+                Some(TIMEOUT_MS),
+            );
+            if !received {
+                error!("ATA timeout on channel {}", self.index);
+                return None;
+            }
+        }
+        self.poll()
+    }
+
+    /// Busy waits until the drive is ready to transfer data
+    unsafe fn wait_drq(&self) -> Option<()> {
+        let deadline = Timer::uptime() + TIMEOUT_MS;
+        while self.poll()? & STATUS_DRQ == 0 {
+            if Timer::uptime() > deadline {
+                return None;
+            }
+        }
+        Some(())
+    }
+
+    unsafe fn read_sector(&self, buf: &mut [u8]) {
+        for chunk in buf[..SECTOR_SIZE].chunks_exact_mut(2) {
+            chunk.copy_from_slice(&inw(self.io_base + REG_DATA).to_le_bytes());
+        }
+    }
+
+    unsafe fn write_sector(&self, buf: &[u8]) {
+        for chunk in buf[..SECTOR_SIZE].chunks_exact(2) {
+            outw(
+                self.io_base + REG_DATA,
+                u16::from_le_bytes([chunk[0], chunk[1]]),
+            );
+        }
+    }
+}
+
+/// A hard disk attached to an IDE channel, accessed with PIO transfers
+#[derive(Debug)]
+pub struct AtaDrive {
+    channel: Channel,
+    slave: bool,
+    lba48: bool,
+    sectors: u64,
+    pub model: String,
+}
+
+impl AtaDrive {
+    /// Sends IDENTIFY to the drive, returns None if no ATA drive is present
+    unsafe fn identify(channel: Channel, slave: bool) -> Option<Self> {
+        outb(channel.io_base + REG_DRIVE, 0xA0 | ((slave as u8) << 4));
+        channel.delay();
+        outb(channel.io_base + REG_SECCOUNT, 0);
+        outb(channel.io_base + REG_LBA0, 0);
+        outb(channel.io_base + REG_LBA1, 0);
+        outb(channel.io_base + REG_LBA2, 0);
+        channel.command(CMD_IDENTIFY);
+
+        // No drive
+        if channel.status() == 0 {
+            return None;
+        }
+
+        let deadline = Timer::uptime() + TIMEOUT_MS;
+        while channel.status() & STATUS_BSY != 0 {
+            if Timer::uptime() > deadline {
+                return None;
+            }
+        }
+        // ATAPI and SATA devices set these, they are not supported
+        if inb(channel.io_base + REG_LBA1) != 0 || inb(channel.io_base + REG_LBA2) != 0 {
+            return None;
+        }
+        channel.wait_drq()?;
+
+        let mut identify = [0_u16; 256];
+        for word in identify.iter_mut() {
+            *word = inw(channel.io_base + REG_DATA);
+        }
+
+        let lba48 = identify[83] & (1 << 10) != 0;
+        let sectors = if lba48 {
+            identify[100] as u64
+                | (identify[101] as u64) << 16
+                | (identify[102] as u64) << 32
+                | (identify[103] as u64) << 48
+        } else {
+            identify[60] as u64 | (identify[61] as u64) << 16
+        };
+
+        // The model string has the bytes of each word swapped
+        let model = identify[27..47]
+            .iter()
+            .flat_map(|w| w.to_be_bytes())
+            .map(|b| b as char)
+            .collect::<String>()
+            .trim_end()
+            .into();
+
+        Some(AtaDrive {
+            channel,
+            slave,
+            lba48,
+            sectors,
+            model,
+        })
+    }
+
+    /// Largest number of sectors transferred by one command
+    fn max_transfer(&self) -> usize {
+        if self.lba48 {
+            65536
+        } else {
+            256
+        }
+    }
+
+    /// Reads the sectors filling `buf` with a single command
+    unsafe fn read_chunk(&mut self, lba: u64, buf: &mut [u8]) -> Option<()> {
+        let count = buf.len() / SECTOR_SIZE;
+        self.channel
+            .setup(self.slave, self.lba48, lba, count as u16);
+        self.channel.command(if self.lba48 {
+            CMD_READ_PIO_EXT
+        } else {
+            CMD_READ_PIO
+        });
+
+        // The drive interrupts once for every sector that is ready
+        for sector in buf.chunks_exact_mut(SECTOR_SIZE) {
+            self.channel.wait_irq()?;
+            self.channel.read_sector(sector);
+        }
+        Some(())
+    }
+
+    /// Writes the sectors in `buf` with a single command and flushes the drive cache
+    unsafe fn write_chunk(&mut self, lba: u64, buf: &[u8]) -> Option<()> {
+        let count = buf.len() / SECTOR_SIZE;
+        self.channel
+            .setup(self.slave, self.lba48, lba, count as u16);
+        self.channel.command(if self.lba48 {
+            CMD_WRITE_PIO_EXT
+        } else {
+            CMD_WRITE_PIO
+        });
+
+        // The first sector is sent as soon as the drive asks for data,
+        // the rest after the interrupt signaling the previous one was written
+        self.channel.wait_drq()?;
+        for sector in buf.chunks_exact(SECTOR_SIZE) {
+            self.channel.write_sector(sector);
+            self.channel.wait_irq()?;
+        }
+
+        self.channel.command(if self.lba48 {
+            CMD_CACHE_FLUSH_EXT
+        } else {
+            CMD_CACHE_FLUSH
+        });
+        self.channel.wait_irq()?;
+        Some(())
+    }
+
+    /// Checks that the transfer fits on the disk and the addressing mode
+    fn check_range(&self, lba: u64, buf_len: usize) -> bool {
+        let count = (buf_len / SECTOR_SIZE) as u64;
+        let limit = if self.lba48 { 1 << 48 } else { 1 << 28 };
+
+        buf_len.is_multiple_of(SECTOR_SIZE) && lba + count <= self.sectors.min(limit)
+    }
+}
+
+impl BlockDevice for AtaDrive {
+    fn sector_size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    fn sector_count(&self) -> u64 {
+        self.sectors
+    }
+
+    fn read_sectors(&mut self, lba: u64, buf: &mut [u8]) -> Option<usize> {
+        if !self.check_range(lba, buf.len()) {
+            return None;
+        }
+        let chunk_size = self.max_transfer() * SECTOR_SIZE;
+        for (i, chunk) in buf.chunks_mut(chunk_size).enumerate() {
+            let chunk_lba = lba + (i * self.max_transfer()) as u64;
+            unsafe { self.read_chunk(chunk_lba, chunk)? };
+        }
+        Some(buf.len())
+    }
+
+    fn write_sectors(&mut self, lba: u64, buf: &[u8]) -> Option<usize> {
+        if !self.check_range(lba, buf.len()) {
+            return None;
+        }
+        let chunk_size = self.max_transfer() * SECTOR_SIZE;
+        for (i, chunk) in buf.chunks(chunk_size).enumerate() {
+            let chunk_lba = lba + (i * self.max_transfer()) as u64;
+            unsafe { self.write_chunk(chunk_lba, chunk)? };
+        }
+        Some(buf.len())
+    }
+}
+
+/// Handler of IRQ 14 and 15, one per channel.
+/// Reading the status register acknowledges the interrupt on the drive
+fn handle_irq(irq: u8) {
+    let Some(channel) = CHANNELS.iter().find(|channel| channel.irq == irq) else {
+        return;
+    };
+    unsafe { inb(channel.io_base + REG_STATUS) };
+    IRQ_RECEIVED[channel.index].store(true, Ordering::Release);
+    IRQ_WAITERS[channel.index].wake_all();
+}
+
+/// Detects the drives on both IDE channels and registers them in /dev
+/// as hda (primary master), hdb (primary slave), hdc and hdd
+pub fn init() {
+    let names = ["hda", "hdb", "hdc", "hdd"];
+
+    for channel in CHANNELS {
+        unsafe {
+            // A floating bus reads as 0xFF, there is nothing attached
+            if channel.status() == 0xFF {
+                continue;
+            }
+            // Clear nIEN so the drives raise interrupts
+            outb(channel.control_base, 0);
+            crate::arch::interrupts::register_irq(channel.irq, handle_irq);
+        }
+
+        for slave in [false, true] {
+            if let Some(drive) = unsafe { AtaDrive::identify(channel, slave) } {
+                let name = names[channel.index * 2 + slave as usize];
+                log!(
+                    "{}: {} ({} sectors, LBA{})",
+                    name,
+                    drive.model,
+                    drive.sectors,
+                    if drive.lba48 { 48 } else { 28 }
+                );
+                super::blockdev::register_disk(name, Box::new(drive));
+            }
+        }
+    }
+}
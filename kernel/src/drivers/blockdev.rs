@@ -0,0 +1,194 @@
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+
+use crate::logging;
+use crate::sync::{Mutex, RwSpinLock};
+
+use super::buffer_cache::BufferCache;
+
+/// Sectors kept in the buffer cache of each disk
+const CACHE_SECTORS: usize = 512;
+
+/// Cached disks, kept to write them back on `sync_all`
+static DISKS: RwSpinLock<Vec<Arc<Mutex<BufferCache>>>> = RwSpinLock::new(Vec::new());
+/// Every registered disk and partition by device name
+static DEVICES: RwSpinLock<Vec<(String, Partition)>> = RwSpinLock::new(Vec::new());
+
+/// A device addressed in fixed size sectors
+pub trait BlockDevice {
+    /// Size of a sector in bytes
+    fn sector_size(&self) -> usize;
+
+    /// Number of sectors on the device
+    fn sector_count(&self) -> u64;
+
+    /// Reads `buf.len() / sector_size()` sectors starting at `lba`.
+    /// Returns the number of bytes read
+    fn read_sectors(&mut self, lba: u64, buf: &mut [u8]) -> Option<usize>;
+
+    /// Writes `buf.len() / sector_size()` sectors starting at `lba`.
+    /// Returns the number of bytes written
+    fn write_sectors(&mut self, lba: u64, buf: &[u8]) -> Option<usize>;
+
+    /// Size of the device in bytes
+    fn size(&self) -> usize {
+        self.sector_count() as usize * self.sector_size()
+    }
+
+    /// Reads bytes at any offset, going through whole sectors
+    fn read_bytes(&mut self, offset: usize, buf: &mut [u8]) -> Option<usize> {
+        if offset >= self.size() {
+            return None;
+        }
+        let sector_size = self.sector_size();
+        let size = buf.len().min(self.size() - offset);
+
+        let first = offset / sector_size;
+        let last = (offset + size).div_ceil(sector_size);
+        let mut sectors = vec![0; (last - first) * sector_size];
+        self.read_sectors(first as u64, &mut sectors)?;
+
+        let start = offset % sector_size;
+        buf[..size].copy_from_slice(&sectors[start..start + size]);
+        Some(size)
+    }
+
+    /// Writes bytes at any offset. Sectors only partially covered by `buf`
+    /// are read first so their remaining contents are kept
+    fn write_bytes(&mut self, offset: usize, buf: &[u8]) -> Option<usize> {
+        if offset >= self.size() {
+            return None;
+        }
+        let sector_size = self.sector_size();
+        let size = buf.len().min(self.size() - offset);
+
+        let first = offset / sector_size;
+        let last = (offset + size).div_ceil(sector_size);
+        let mut sectors = vec![0; (last - first) * sector_size];
+
+        let start = offset % sector_size;
+        if start != 0 {
+            self.read_sectors(first as u64, &mut sectors[..sector_size])?;
+        }
+        if !(offset + size).is_multiple_of(sector_size) {
+            let tail = sectors.len() - sector_size;
+            self.read_sectors(last as u64 - 1, &mut sectors[tail..])?;
+        }
+
+        sectors[start..start + size].copy_from_slice(&buf[..size]);
+        self.write_sectors(first as u64, &sectors)?;
+        Some(size)
+    }
+}
+
+/// A range of sectors of a cached disk. The whole disk is the range covering
+/// all of its sectors, so disks and partitions are accessed the same way
+#[derive(Clone)]
+pub struct Partition {
+    /// Shared by the disk and its partitions. A sleeping mutex, as it is held
+    /// across device I/O that waits for interrupts, and waiters get it in turn
+    disk: Arc<Mutex<BufferCache>>,
+    start: u64,
+    sectors: u64,
+}
+
+impl Partition {
+    fn whole(disk: Arc<Mutex<BufferCache>>) -> Self {
+        let sectors = disk.lock().sector_count();
+        Partition {
+            disk,
+            start: 0,
+            sectors,
+        }
+    }
+
+    /// Writes the modified sectors of the underlying disk back
+    pub fn sync(&self) -> Option<()> {
+        self.disk.lock().sync()
+    }
+}
+
+impl BlockDevice for Partition {
+    fn sector_size(&self) -> usize {
+        self.disk.lock().sector_size()
+    }
+
+    fn sector_count(&self) -> u64 {
+        self.sectors
+    }
+
+    fn read_sectors(&mut self, lba: u64, buf: &mut [u8]) -> Option<usize> {
+        if lba + (buf.len() / self.sector_size()) as u64 > self.sectors {
+            return None;
+        }
+        self.disk.lock().read_sectors(self.start + lba, buf)
+    }
+
+    fn write_sectors(&mut self, lba: u64, buf: &[u8]) -> Option<usize> {
+        if lba + (buf.len() / self.sector_size()) as u64 > self.sectors {
+            return None;
+        }
+        self.disk.lock().write_sectors(self.start + lba, buf)
+    }
+}
+
+/// Adds a disk found by a driver: puts it behind a buffer cache, reads its
+/// partition table and exposes the disk and each partition in /dev
+pub fn register_disk(name: &str, device: Box<dyn BlockDevice>) {
+    let disk = Arc::new(Mutex::new(BufferCache::new(device, CACHE_SECTORS)));
+    DISKS.write().push(disk.clone());
+
+    let mut whole = Partition::whole(disk.clone());
+    let partitions = super::partition::parse(&mut whole);
+    register(name.to_string(), whole);
+
+    for entry in partitions {
+        let part_name = alloc::format!("{}{}", name, entry.number);
+        log!(
+            "{}: sectors {}-{}",
+            part_name,
+            entry.start,
+            entry.start + entry.sectors - 1
+        );
+        register(
+            part_name,
+            Partition {
+                disk: disk.clone(),
+                start: entry.start,
+                sectors: entry.sectors,
+            },
+        );
+    }
+}
+
+fn register(name: String, partition: Partition) {
+    super::devfs::register_blockdev(&name, partition.clone());
+    DEVICES.write().push((name, partition));
+}
+
+/// Returns the disk or partition with the given name, e.g. "hda1"
+pub fn open(name: &str) -> Option<Partition> {
+    DEVICES
+        .read()
+        .iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, partition)| partition.clone())
+}
+
+/// Writes the modified sectors of every disk back
+pub fn sync_all() -> Option<()> {
+    // The list is copied so its spinlock isn't held while sleeping on a disk
+    let disks = DISKS.read().clone();
+    let mut result = Some(());
+    for disk in disks.iter() {
+        if disk.lock().sync().is_none() {
+            result = None;
+        }
+    }
+    result
+}
@@ -0,0 +1,184 @@
+use alloc::{boxed::Box, collections::BTreeMap, vec, vec::Vec};
+
+use super::blockdev::BlockDevice;
+
+/// Longest run of missing sectors read from the device with one request
+const MAX_READ_RUN: usize = 64;
+
+struct CacheEntry {
+    lba: u64,
+    data: Vec<u8>,
+    dirty: bool,
+    /// Value of the access counter at the last use, the smallest one is evicted first
+    last_used: u64,
+}
+
+/// Write-back LRU cache of the sectors of a disk.
+///
+/// Writes only go to the device when their sector is evicted or on `sync`.
+pub struct BufferCache {
+    device: Box<dyn BlockDevice>,
+    capacity: usize,
+    entries: Vec<CacheEntry>,
+    /// Index into `entries` of each cached sector
+    index: BTreeMap<u64, usize>,
+    counter: u64,
+}
+
+// Caches are only used behind the Mutex of their disk
+unsafe impl Send for BufferCache {}
+
+impl BufferCache {
+    /// Creates a cache holding at most `capacity` sectors of the device
+    pub fn new(device: Box<dyn BlockDevice>, capacity: usize) -> Self {
+        BufferCache {
+            device,
+            capacity,
+            entries: Vec::new(),
+            index: BTreeMap::new(),
+            counter: 0,
+        }
+    }
+
+    fn touch(&mut self, entry: usize) {
+        self.counter += 1;
+        self.entries[entry].last_used = self.counter;
+    }
+
+    /// Returns the entry where a new sector can be placed, evicting the least
+    /// recently used one if the cache is full
+    fn free_entry(&mut self) -> Option<usize> {
+        if self.entries.len() < self.capacity {
+            self.entries.push(CacheEntry {
+                lba: 0,
+                data: vec![0; self.device.sector_size()],
+                dirty: false,
+                last_used: 0,
+            });
+            return Some(self.entries.len() - 1);
+        }
+
+        let (victim, _) = self
+            .entries
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, e)| e.last_used)?;
+
+        let entry = &mut self.entries[victim];
+        if entry.dirty {
+            self.device.write_sectors(entry.lba, &entry.data)?;
+            entry.dirty = false;
+        }
+        self.index.remove(&entry.lba);
+        Some(victim)
+    }
+
+    /// Places the sector in the cache, replacing the cached contents if present
+    fn insert(&mut self, lba: u64, data: &[u8], dirty: bool) -> Option<()> {
+        let entry = match self.index.get(&lba) {
+            Some(&entry) => entry,
+            None => {
+                let entry = self.free_entry()?;
+                self.entries[entry].lba = lba;
+                self.index.insert(lba, entry);
+                entry
+            }
+        };
+
+        let cached = &mut self.entries[entry];
+        cached.data.copy_from_slice(data);
+        cached.dirty |= dirty;
+        self.touch(entry);
+        Some(())
+    }
+
+    /// Writes all modified sectors to the device, merging consecutive ones
+    pub fn sync(&mut self) -> Option<()> {
+        let sector_size = self.device.sector_size();
+        let dirty: Vec<(u64, usize)> = self
+            .index
+            .iter()
+            .filter(|(_, &entry)| self.entries[entry].dirty)
+            .map(|(&lba, &entry)| (lba, entry))
+            .collect();
+
+        let mut run: Vec<u8> = Vec::new();
+        let mut run_start = 0;
+        for (i, &(lba, entry)) in dirty.iter().enumerate() {
+            if run.is_empty() {
+                run_start = lba;
+            }
+            run.extend_from_slice(&self.entries[entry].data);
+
+            let run_ends = dirty.get(i + 1).is_none_or(|&(next, _)| next != lba + 1);
+            if run_ends {
+                self.device.write_sectors(run_start, &run)?;
+                for sector in 0..(run.len() / sector_size) as u64 {
+                    let entry = self.index[&(run_start + sector)];
+                    self.entries[entry].dirty = false;
+                }
+                run.clear();
+            }
+        }
+
+        Some(())
+    }
+}
+
+impl BlockDevice for BufferCache {
+    fn sector_size(&self) -> usize {
+        self.device.sector_size()
+    }
+
+    fn sector_count(&self) -> u64 {
+        self.device.sector_count()
+    }
+
+    fn read_sectors(&mut self, lba: u64, buf: &mut [u8]) -> Option<usize> {
+        let sector_size = self.sector_size();
+        let count = buf.len() / sector_size;
+
+        let mut i = 0;
+        while i < count {
+            let sector = lba + i as u64;
+            let dest = i * sector_size;
+
+            if let Some(&entry) = self.index.get(&sector) {
+                buf[dest..dest + sector_size].copy_from_slice(&self.entries[entry].data);
+                self.touch(entry);
+                i += 1;
+                continue;
+            }
+
+            // Read all the following missing sectors at once
+            let mut run = 1;
+            while i + run < count
+                && run < MAX_READ_RUN
+                && !self.index.contains_key(&(sector + run as u64))
+            {
+                run += 1;
+            }
+            let fetched = &mut buf[dest..dest + run * sector_size];
+            self.device.read_sectors(sector, fetched)?;
+
+            for (j, data) in fetched.chunks(sector_size).enumerate() {
+                self.insert(sector + j as u64, data, false)?;
+            }
+            i += run;
+        }
+
+        Some(count * sector_size)
+    }
+
+    fn write_sectors(&mut self, lba: u64, buf: &[u8]) -> Option<usize> {
+        let sector_size = self.sector_size();
+        if lba + (buf.len() / sector_size) as u64 > self.sector_count() {
+            return None;
+        }
+
+        for (i, data) in buf.chunks_exact(sector_size).enumerate() {
+            self.insert(lba + i as u64, data, true)?;
+        }
+        Some(buf.len() - buf.len() % sector_size)
+    }
+}
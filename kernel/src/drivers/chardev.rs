@@ -1,5 +1,41 @@
-pub trait CharDev {
+/// Devices are shared by every open file and used without a lock,
+/// their state has its own synchronization
+pub trait CharDev: Send + Sync {
     fn read(&self, size: usize, buf: &mut [u8]) -> Option<usize>;
 
-    fn write(&mut self, size: usize, buf: &[u8]) -> Option<usize>;
+    /// Reads at a file offset, for devices whose contents are not a stream
+    fn read_at(&self, _offset: usize, size: usize, buf: &mut [u8]) -> Option<usize> {
+        self.read(size, buf)
+    }
+
+    /// Offset a read at `offset` starts from, for devices which drop their oldest data
+    fn seek(&self, offset: usize) -> usize {
+        offset
+    }
+
+    fn write(&self, size: usize, buf: &[u8]) -> Option<usize>;
+}
+
+/// Major numbers of the character devices, same as Linux
+#[allow(non_snake_case)]
+pub mod Major {
+    /// null, zero, full, random, urandom and kmsg
+    pub const MEM: u32 = 1;
+    /// Serial ports, starting at minor 64
+    pub const TTY_SERIAL: u32 = 4;
+    pub const INPUT: u32 = 13;
+}
+
+/// Combines a major and minor number into a device number, with the
+/// layout Linux uses inside the kernel (20 bits for the minor number)
+pub const fn makedev(major: u32, minor: u32) -> u64 {
+    (major as u64) << 20 | (minor & 0xF_FFFF) as u64
+}
+
+pub const fn major(device: u64) -> u32 {
+    (device >> 20) as u32
+}
+
+pub const fn minor(device: u64) -> u32 {
+    (device & 0xF_FFFF) as u32
 }
@@ -0,0 +1,121 @@
+use core::fmt;
+
+use crate::sync::IrqSpinMutex;
+
+use super::{
+    font::{FIRST_CHAR, FONT},
+    framebuffer::{Rgb, FRAMEBUFFER},
+};
+
+/// Each row of the 8x8 font is drawn twice, so characters keep a usual aspect ratio
+const CELL_WIDTH: usize = 8;
+const CELL_HEIGHT: usize = 16;
+const TAB_WIDTH: usize = 8;
+
+pub static CONSOLE: IrqSpinMutex<Option<Console>> = IrqSpinMutex::new(None);
+
+/// Text console drawn on the framebuffer, scrolling up when the last line is full
+pub struct Console {
+    column: usize,
+    row: usize,
+    columns: usize,
+    rows: usize,
+    foreground: u32,
+    background: u32,
+}
+
+impl Console {
+    /// Creates the console on a cleared framebuffer, if there is one
+    pub fn init() {
+        let Some(fb) = FRAMEBUFFER.get() else {
+            return;
+        };
+        let background = Rgb::new(0, 0, 0).pack32();
+        let console = {
+            let mut fb = fb.lock();
+            fb.buffer.fill(background);
+            Console {
+                column: 0,
+                row: 0,
+                columns: fb.width / CELL_WIDTH,
+                rows: fb.height / CELL_HEIGHT,
+                foreground: Rgb::new(0xAA, 0xAA, 0xAA).pack32(),
+                background,
+            }
+        };
+
+        // The framebuffer is always locked after the console
+        *CONSOLE.lock() = Some(console);
+    }
+
+    pub fn set_color(&mut self, color: Rgb) {
+        self.foreground = color.pack32();
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    fn draw(&mut self, c: u8) {
+        let mut fb = FRAMEBUFFER.lock();
+        let glyph = &FONT[(c - FIRST_CHAR) as usize];
+        let x = self.column * CELL_WIDTH;
+        let y = self.row * CELL_HEIGHT;
+
+        for line in 0..CELL_HEIGHT {
+            let bits = glyph[line / 2];
+            let start = (y + line) * fb.width + x;
+            for (i, pixel) in fb.buffer[start..start + CELL_WIDTH].iter_mut().enumerate() {
+                *pixel = if bits & (1 << i) != 0 {
+                    self.foreground
+                } else {
+                    self.background
+                };
+            }
+        }
+    }
+
+    fn newline(&mut self) {
+        self.column = 0;
+        if self.row + 1 < self.rows {
+            self.row += 1;
+            return;
+        }
+
+        // Move every line up and clear the last one
+        let mut fb = FRAMEBUFFER.lock();
+        let line_pixels = fb.width * CELL_HEIGHT;
+        let text_pixels = line_pixels * self.rows;
+        fb.buffer.copy_within(line_pixels..text_pixels, 0);
+        fb.buffer[text_pixels - line_pixels..text_pixels].fill(self.background);
+    }
+
+    pub fn write_byte(&mut self, byte: u8) {
+        match byte {
+            b'\n' => self.newline(),
+            b'\r' => self.column = 0,
+            b'\t' => {
+                for _ in 0..TAB_WIDTH - self.column % TAB_WIDTH {
+                    self.write_byte(b' ');
+                }
+            }
+            _ => {
+                if self.column >= self.columns {
+                    self.newline();
+                }
+                let printable = (FIRST_CHAR..=b'~').contains(&byte);
+                self.draw(if printable { byte } else { b'?' });
+                self.column += 1;
+            }
+        }
+    }
+}
+
+impl fmt::Write for Console {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            self.write_byte(byte);
+        }
+        Ok(())
+    }
+}
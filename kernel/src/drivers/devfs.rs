@@ -1,16 +1,74 @@
-use crate::filesystem::{DirEnt, Type, VFS_Node};
+use crate::filesystem::{DirEnt, FsError, Type, VFS_Node};
+use crate::sync::{Once, SpinMutex};
 use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
 use alloc::string::ToString;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 
-use super::chardev::CharDev;
+use super::{
+    blockdev::{BlockDevice, Partition},
+    chardev::{makedev, CharDev, Major},
+    keyboard::Keyboard,
+    kmsg::Kmsg,
+    mem::{Full, Null, Zero},
+    random::Random,
+    serial::Serial,
+};
 
-static mut DEV_FS: Option<DevFilesystem> = None;
+static DEV_FS: Once<SpinMutex<DevFilesystem>> = Once::new();
 
 pub struct DevFilesystem {
     root: VFS_Node,
-    file_nodes: Vec<VFS_Node>,
-    devices: Vec<Box<dyn CharDev>>,
+    /// Boxed so the node addresses stay valid when devices are registered later
+    #[allow(clippy::vec_box)]
+    file_nodes: Vec<Box<VFS_Node>>,
+    /// Character devices by device number, which is also the inode of their node
+    devices: BTreeMap<u64, Arc<dyn CharDev>>,
+    /// Disks and partitions, each handle shares the buffer cache of its disk
+    block_devices: Vec<Partition>,
+}
+
+// The nodes are only used behind the lock of the filesystem. Reads and writes take
+// a handle of the device under the lock and release it before the transfer
+unsafe impl Send for DevFilesystem {}
+
+/// Creates a device node, without the directory operations
+fn device_node(name: &str, kind: Type, inode: usize, size: usize) -> VFS_Node {
+    let is_block = kind == Type::BlockDev;
+
+    VFS_Node {
+        name: name.to_string(),
+        kind: kind.clone(),
+        inode,
+        size,
+        links: 1,
+        uid: 0,
+        gid: 0,
+        permissions: kind.default_permissions(),
+        read: Some(if is_block {
+            devfs_block_read
+        } else {
+            devfs_read
+        }),
+        write: Some(if is_block {
+            devfs_block_write
+        } else {
+            devfs_write
+        }),
+        readdir: None,
+        finddir: None,
+        create: None,
+        truncate: None,
+        unlink: None,
+        readlink: None,
+        symlink: None,
+        link: None,
+        mount_point: None,
+        open: 0,
+        release: None,
+        seek: if is_block { None } else { Some(devfs_seek) },
+    }
 }
 
 pub fn initialize_devfs() -> *mut VFS_Node {
@@ -19,69 +77,111 @@ pub fn initialize_devfs() -> *mut VFS_Node {
         kind: Type::Dir,
         inode: 0,
         size: 0,
+        links: 1,
+        uid: 0,
+        gid: 0,
+        permissions: Type::Dir.default_permissions(),
         read: None,
         write: None,
         readdir: Some(devfs_readdir),
         finddir: Some(devfs_finddir),
+        create: None,
+        truncate: None,
+        unlink: None,
+        readlink: None,
+        symlink: None,
+        link: None,
         mount_point: None,
+        open: 0,
+        release: None,
+        seek: None,
     };
-    let serial = VFS_Node {
-        name: "serial".to_string(),
-        kind: Type::CharDev,
-        inode: 0,
-        size: 0,
-        read: Some(devfs_read),
-        write: Some(devfs_write),
-        readdir: None,
-        finddir: None,
-        mount_point: None,
-    };
-    let keyboard = VFS_Node {
-        name: "keyboard".to_string(),
-        kind: Type::CharDev,
-        inode: 1,
-        size: 0,
-        read: Some(devfs_read),
-        write: None,
-        readdir: None,
-        finddir: None,
-        mount_point: None,
-    };
-
-    let mut file_nodes = Vec::new();
-    file_nodes.push(serial);
-    file_nodes.push(keyboard);
-
-    let serial_dev = Box::new(super::serial::Serial);
-    let mut devices: Vec<Box<dyn CharDev>> = Vec::new();
-    devices.push(serial_dev);
-
-    let key_dev = Box::new(super::keyboard::Keyboard);
-    devices.push(key_dev);
 
     let dev_fs = DevFilesystem {
         root,
-        file_nodes,
-        devices,
+        file_nodes: Vec::new(),
+        devices: BTreeMap::new(),
+        block_devices: Vec::new(),
     };
 
-    unsafe {
-        DEV_FS = Some(dev_fs);
+    DEV_FS.set(SpinMutex::new(dev_fs));
+
+    let always_present: [(&str, u32, u32, Box<dyn CharDev>); 8] = [
+        ("serial", Major::TTY_SERIAL, 64, Box::new(Serial)),
+        ("keyboard", Major::INPUT, 64, Box::new(Keyboard)),
+        ("null", Major::MEM, 3, Box::new(Null)),
+        ("zero", Major::MEM, 5, Box::new(Zero)),
+        ("full", Major::MEM, 7, Box::new(Full)),
+        ("random", Major::MEM, 8, Box::new(Random)),
+        ("urandom", Major::MEM, 9, Box::new(Random)),
+        ("kmsg", Major::MEM, 11, Box::new(Kmsg)),
+    ];
+    for (name, major, minor, device) in always_present {
+        register_chardev(name, major, minor, device);
+    }
+
+    // Everyone can read the kernel log, only root can add messages to it
+    let mut fs = DEV_FS.lock();
+    if let Some(kmsg) = fs.file_nodes.iter_mut().find(|node| node.name == "kmsg") {
+        kmsg.permissions = 0o644;
     }
 
-    unsafe { &mut DEV_FS.as_mut().unwrap().root as *mut VFS_Node }
+    &mut fs.root as *mut VFS_Node
 }
 
-pub fn devfs_read(
-    node: &VFS_Node,
-    _offset: usize,
-    size: usize,
-    buffer: &mut [u8],
-) -> Option<usize> {
-    let fs = unsafe { &DEV_FS.as_ref().unwrap() };
-    let device = &fs.devices[node.inode];
+/// Adds a character device node named `name` to /dev, returns None if the name
+/// or the device number is already used
+pub fn register_chardev(
+    name: &str,
+    major: u32,
+    minor: u32,
+    device: Box<dyn CharDev>,
+) -> Option<()> {
+    let mut fs = DEV_FS.lock();
+
+    let number = makedev(major, minor);
+    if fs.devices.contains_key(&number) || fs.file_nodes.iter().any(|node| node.name == name) {
+        return None;
+    }
 
-    device.read(size, buffer)
+    fs.devices.insert(number, Arc::from(device));
+    fs.file_nodes.push(Box::new(device_node(
+        name,
+        Type::CharDev,
+        number as usize,
+        0,
+    )));
+    Some(())
+}
+
+/// Adds a block device node named `name` to /dev.
+/// The inode of block device nodes is their index in `block_devices`
+pub fn register_blockdev(name: &str, device: Partition) {
+    let mut fs = DEV_FS.lock();
+
+    let node = device_node(name, Type::BlockDev, fs.block_devices.len(), device.size());
+
+    fs.block_devices.push(device);
+    fs.file_nodes.push(Box::new(node));
+}
+
+/// The character device of a node, the lock is released before it is used
+fn chardev(node: &VFS_Node) -> Option<Arc<dyn CharDev>> {
+    DEV_FS.lock().devices.get(&(node.inode as u64)).cloned()
+}
+
+/// The disk or partition of a node. Transfers wait for the disk, so they do not
+/// hold the lock of the filesystem
+fn blockdev(node: &VFS_Node) -> Option<Partition> {
+    DEV_FS.lock().block_devices.get(node.inode).cloned()
+}
+
+pub fn devfs_read(node: &VFS_Node, offset: usize, size: usize, buffer: &mut [u8]) -> Option<usize> {
+    chardev(node)?.read_at(offset, size, buffer)
+}
+
+pub fn devfs_seek(node: &VFS_Node, offset: usize) -> usize {
+    chardev(node).map_or(offset, |device| device.seek(offset))
 }
 
 pub fn devfs_write(
@@ -89,11 +189,32 @@ pub fn devfs_write(
     _offset: usize,
     size: usize,
     buffer: &[u8],
+) -> Result<usize, FsError> {
+    chardev(node)
+        .ok_or(FsError::Failed)?
+        .write(size, buffer)
+        .ok_or(FsError::Failed)
+}
+
+pub fn devfs_block_read(
+    node: &VFS_Node,
+    offset: usize,
+    size: usize,
+    buffer: &mut [u8],
 ) -> Option<usize> {
-    let fs = unsafe { &mut DEV_FS.as_mut().unwrap() };
-    let device = &mut fs.devices[node.inode];
+    blockdev(node)?.read_bytes(offset, &mut buffer[..size])
+}
 
-    device.write(size, buffer)
+pub fn devfs_block_write(
+    node: &mut VFS_Node,
+    offset: usize,
+    size: usize,
+    buffer: &[u8],
+) -> Result<usize, FsError> {
+    blockdev(node)
+        .ok_or(FsError::Failed)?
+        .write_bytes(offset, &buffer[..size])
+        .ok_or(FsError::Failed)
 }
 
 pub fn devfs_readdir(node: &VFS_Node) -> Option<Vec<DirEnt>> {
@@ -101,12 +222,13 @@ pub fn devfs_readdir(node: &VFS_Node) -> Option<Vec<DirEnt>> {
         return None;
     }
     let mut ret = Vec::new();
-    let fs = unsafe { &DEV_FS.as_ref().unwrap() };
+    let fs = DEV_FS.lock();
 
     for node in &fs.file_nodes {
         ret.push(DirEnt {
             name: node.name.clone(),
             inode: node.inode,
+            kind: node.kind.clone(),
         });
     }
 
@@ -117,10 +239,10 @@ pub fn devfs_finddir(node: &VFS_Node, name: &str) -> Option<*mut VFS_Node> {
     if node.kind != Type::Dir {
         return None;
     }
-    let fs = unsafe { &mut DEV_FS.as_mut().unwrap() };
+    let mut fs = DEV_FS.lock();
 
     fs.file_nodes
         .iter_mut()
         .find(|node| node.name == name)
-        .map(|node| node as *mut VFS_Node)
+        .map(|node| &mut **node as *mut VFS_Node)
 }
@@ -0,0 +1,429 @@
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+use crate::filesystem::{DirEnt, FsError, Type, VFS_Node};
+use crate::logging;
+use crate::sync::{LockGuard, Mutex, SpinMutex};
+
+use super::blockdev::{BlockDevice, Partition};
+
+/// Mounted volumes, the index is stored in the upper half of the node inodes
+/// and the ext2 inode number in the lower half. Like FAT volumes, each is
+/// leaked and locked on its own
+static EXT2_VOLUMES: SpinMutex<Vec<&'static Mutex<Ext2Volume>>> = SpinMutex::new(Vec::new());
+
+const SUPERBLOCK_OFFSET: usize = 1024;
+const EXT2_MAGIC: u16 = 0xEF53;
+const ROOT_INODE: u32 = 2;
+
+// Incompatible features that don't change how the filesystem is read
+const INCOMPAT_FILETYPE: u32 = 0x0002;
+const INCOMPAT_FLEX_BG: u32 = 0x0200;
+const SUPPORTED_INCOMPAT: u32 = INCOMPAT_FILETYPE | INCOMPAT_FLEX_BG;
+
+// Inode file types, upper bits of the mode
+const S_IFMT: u16 = 0xF000;
+const S_IFREG: u16 = 0x8000;
+const S_IFDIR: u16 = 0x4000;
+const S_IFLNK: u16 = 0xA000;
+const S_IFCHR: u16 = 0x2000;
+const S_IFBLK: u16 = 0x6000;
+
+/// Block pointers in an inode: 12 direct, then single, double and triple indirect
+const DIRECT_BLOCKS: usize = 12;
+/// Symlinks shorter than this store the target in the block pointers
+const FAST_SYMLINK_MAX: usize = 60;
+/// Largest directory read, its entries are kept in the kernel heap
+const MAX_DIR_SIZE: u64 = 1024 * 1024;
+
+/// The parts of an inode the driver uses
+#[derive(Debug, Clone, Copy)]
+struct Inode {
+    mode: u16,
+    links: u16,
+    uid: u32,
+    gid: u32,
+    size: u64,
+    /// Size in 512 byte sectors, including extended attribute blocks
+    sectors: u32,
+    file_acl: u32,
+    block: [u32; 15],
+}
+
+impl Inode {
+    fn kind(&self) -> Type {
+        match self.mode & S_IFMT {
+            S_IFDIR => Type::Dir,
+            S_IFLNK => Type::Symlink,
+            S_IFCHR => Type::CharDev,
+            S_IFBLK => Type::BlockDev,
+            _ => Type::File,
+        }
+    }
+}
+
+pub struct Ext2Volume {
+    device: Partition,
+    block_size: usize,
+    inodes_per_group: u32,
+    inode_size: usize,
+    /// Block of the first block group descriptor
+    group_table: usize,
+    /// Nodes looked up so far by inode number, boxed so their addresses stay valid
+    nodes: BTreeMap<u32, Box<VFS_Node>>,
+}
+
+// The nodes are only used behind the lock of the volume, or through the
+// pointers handed to the VFS which keep their boxes alive
+unsafe impl Send for Ext2Volume {}
+
+fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(buf[offset..offset + 2].try_into().unwrap())
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+/// Mounts the ext2 volume on the device read-only, returns the root directory
+pub fn mount(mut device: Partition) -> Result<*mut VFS_Node, FsError> {
+    let mut superblock = [0; 1024];
+    device
+        .read_bytes(SUPERBLOCK_OFFSET, &mut superblock)
+        .ok_or(FsError::Invalid)?;
+    if read_u16(&superblock, 56) != EXT2_MAGIC {
+        return Err(FsError::Invalid);
+    }
+
+    let block_size = 1024usize
+        .checked_shl(read_u32(&superblock, 24))
+        .ok_or(FsError::Invalid)?;
+    let inodes_per_group = read_u32(&superblock, 40);
+    // Revision 0 has fixed size inodes and no feature flags
+    let revision = read_u32(&superblock, 76);
+    let (inode_size, incompat) = match revision {
+        0 => (128, 0),
+        _ => (
+            read_u16(&superblock, 88) as usize,
+            read_u32(&superblock, 96),
+        ),
+    };
+
+    if incompat & !SUPPORTED_INCOMPAT != 0 {
+        warn!(
+            "ext2: unsupported features {:#x} (journal recovery, extents, 64-bit...)",
+            incompat & !SUPPORTED_INCOMPAT
+        );
+        return Err(FsError::Invalid);
+    }
+    if block_size > 65536 || inodes_per_group == 0 || inode_size < 128 {
+        return Err(FsError::Invalid);
+    }
+
+    let first_data_block = read_u32(&superblock, 20) as usize;
+    let mut volume = Ext2Volume {
+        device,
+        block_size,
+        inodes_per_group,
+        inode_size,
+        group_table: first_data_block + 1,
+        nodes: BTreeMap::new(),
+    };
+
+    let root = volume.read_inode(ROOT_INODE).ok_or(FsError::Invalid)?;
+    if root.kind() != Type::Dir {
+        return Err(FsError::Invalid);
+    }
+
+    log!(
+        "Mounted ext2 volume, {} blocks of {} bytes, {} inodes",
+        read_u32(&superblock, 4),
+        block_size,
+        read_u32(&superblock, 0)
+    );
+
+    let volume: &'static Mutex<Ext2Volume> = Box::leak(Box::new(Mutex::new(volume)));
+    let index = {
+        let mut volumes = EXT2_VOLUMES.lock();
+        volumes.push(volume);
+        volumes.len() - 1
+    };
+
+    volume
+        .lock()
+        .node(index, ROOT_INODE, "ext2")
+        .ok_or(FsError::Invalid)
+}
+
+/// Returns the volume of a node and its ext2 inode number
+fn volume(node: &VFS_Node) -> (LockGuard<'static, Ext2Volume>, u32) {
+    let volume = EXT2_VOLUMES.lock()[node.inode >> 32];
+    (volume.lock(), node.inode as u32)
+}
+
+impl Ext2Volume {
+    fn read_inode(&mut self, number: u32) -> Option<Inode> {
+        if number == 0 {
+            return None;
+        }
+        let group = ((number - 1) / self.inodes_per_group) as usize;
+        let index = ((number - 1) % self.inodes_per_group) as usize;
+
+        // Block group descriptors are 32 bytes, the inode table block is at offset 8
+        let mut table = [0; 4];
+        self.device.read_bytes(
+            self.group_table * self.block_size + group * 32 + 8,
+            &mut table,
+        )?;
+        let inode_table = u32::from_le_bytes(table) as usize;
+
+        let mut raw = [0; 128];
+        self.device.read_bytes(
+            inode_table * self.block_size + index * self.inode_size,
+            &mut raw,
+        )?;
+
+        let mode = read_u16(&raw, 0);
+        let mut size = read_u32(&raw, 4) as u64;
+        // Revision 1 keeps the upper half of regular file sizes in the old i_dir_acl field
+        if mode & S_IFMT == S_IFREG {
+            size |= (read_u32(&raw, 108) as u64) << 32;
+        }
+
+        let mut block = [0; 15];
+        for (i, pointer) in block.iter_mut().enumerate() {
+            *pointer = read_u32(&raw, 40 + i * 4);
+        }
+
+        Some(Inode {
+            mode,
+            links: read_u16(&raw, 26),
+            // Linux keeps the upper halves of the owner in the osd2 field
+            uid: read_u16(&raw, 2) as u32 | (read_u16(&raw, 120) as u32) << 16,
+            gid: read_u16(&raw, 24) as u32 | (read_u16(&raw, 122) as u32) << 16,
+            size,
+            sectors: read_u32(&raw, 28),
+            file_acl: read_u32(&raw, 104),
+            block,
+        })
+    }
+
+    /// Reads the entry `index` of the pointer block `block`
+    fn read_pointer(&mut self, block: u32, index: usize) -> Option<u32> {
+        if block == 0 {
+            return Some(0);
+        }
+        let mut pointer = [0; 4];
+        self.device
+            .read_bytes(block as usize * self.block_size + index * 4, &mut pointer)?;
+        Some(u32::from_le_bytes(pointer))
+    }
+
+    /// Maps a block of the file to its block on disk, 0 for holes
+    fn block_of(&mut self, inode: &Inode, mut n: usize) -> Option<u32> {
+        if n < DIRECT_BLOCKS {
+            return Some(inode.block[n]);
+        }
+        n -= DIRECT_BLOCKS;
+
+        // Each level of indirection multiplies the reach by the pointers per block
+        let per_block = self.block_size / 4;
+        let mut reach = per_block;
+        for level in 0..3 {
+            if n < reach {
+                let mut block = inode.block[DIRECT_BLOCKS + level];
+                for depth in (0..=level).rev() {
+                    let span = per_block.pow(depth as u32);
+                    block = self.read_pointer(block, n / span)?;
+                    n %= span;
+                }
+                return Some(block);
+            }
+            n -= reach;
+            reach *= per_block;
+        }
+        None
+    }
+
+    fn read_data(&mut self, inode: &Inode, offset: usize, buffer: &mut [u8]) -> Option<()> {
+        let mut done = 0;
+        while done < buffer.len() {
+            let position = offset + done;
+            let in_block = position % self.block_size;
+            let len = (self.block_size - in_block).min(buffer.len() - done);
+            let part = &mut buffer[done..done + len];
+
+            match self.block_of(inode, position / self.block_size)? {
+                0 => part.fill(0),
+                block => {
+                    self.device
+                        .read_bytes(block as usize * self.block_size + in_block, part)?;
+                }
+            }
+            done += len;
+        }
+        Some(())
+    }
+
+    /// Entries of a directory as (name, inode number), without "." and ".."
+    fn read_dir(&mut self, inode: &Inode) -> Result<Vec<(String, u32)>, FsError> {
+        // Directories have no holes, a size past the allocated sectors is corrupt
+        if inode.size > inode.sectors as u64 * 512 || inode.size > MAX_DIR_SIZE {
+            return Err(FsError::Invalid);
+        }
+        let mut raw = vec![0; inode.size as usize];
+        self.read_data(inode, 0, &mut raw).ok_or(FsError::Failed)?;
+
+        let mut entries = Vec::new();
+        let mut offset = 0;
+        while offset + 8 <= raw.len() {
+            let number = read_u32(&raw, offset);
+            let rec_len = read_u16(&raw, offset + 4) as usize;
+            // The byte after the name length is the file type with the FILETYPE
+            // feature and the upper byte of the length otherwise, but names are
+            // at most 255 bytes anyway
+            let name_len = raw[offset + 6] as usize;
+            if rec_len < 8 || offset + 8 + name_len > raw.len() {
+                break;
+            }
+
+            let name = &raw[offset + 8..offset + 8 + name_len];
+            if number != 0 && name != b"." && name != b".." {
+                entries.push((String::from_utf8_lossy(name).to_string(), number));
+            }
+            offset += rec_len;
+        }
+
+        Ok(entries)
+    }
+
+    /// Returns the node of an inode, creating it on first use
+    fn node(&mut self, volume: usize, number: u32, name: &str) -> Option<*mut VFS_Node> {
+        if !self.nodes.contains_key(&number) {
+            let inode = self.read_inode(number)?;
+            self.nodes.insert(
+                number,
+                Box::new(new_node(
+                    name.to_string(),
+                    volume << 32 | number as usize,
+                    &inode,
+                )),
+            );
+        }
+
+        self.nodes
+            .get_mut(&number)
+            .map(|node| &mut **node as *mut VFS_Node)
+    }
+}
+
+/// Creates a VFS node with the operations matching its type.
+/// Device inodes are listed but can't be opened
+fn new_node(name: String, number: usize, inode: &Inode) -> VFS_Node {
+    let kind = inode.kind();
+    let (is_file, is_dir, is_link) = (kind == Type::File, kind == Type::Dir, kind == Type::Symlink);
+
+    VFS_Node {
+        name,
+        kind,
+        inode: number,
+        size: inode.size as usize,
+        links: inode.links as usize,
+        uid: inode.uid,
+        gid: inode.gid,
+        permissions: (inode.mode & !S_IFMT) as u64,
+        read: if is_file { Some(ext2_read) } else { None },
+        write: None,
+        readdir: if is_dir { Some(ext2_readdir) } else { None },
+        finddir: if is_dir { Some(ext2_finddir) } else { None },
+        create: None,
+        truncate: None,
+        unlink: None,
+        readlink: if is_link { Some(ext2_readlink) } else { None },
+        symlink: None,
+        link: None,
+        mount_point: None,
+        open: 0,
+        release: None,
+        seek: None,
+    }
+}
+
+pub fn ext2_read(node: &VFS_Node, offset: usize, size: usize, buffer: &mut [u8]) -> Option<usize> {
+    let (mut volume, number) = volume(node);
+    let inode = volume.read_inode(number)?;
+    if offset as u64 >= inode.size {
+        return None;
+    }
+    let size = size
+        .min((inode.size - offset as u64) as usize)
+        .min(buffer.len());
+
+    volume.read_data(&inode, offset, &mut buffer[..size])?;
+    Some(size)
+}
+
+pub fn ext2_readdir(node: &VFS_Node) -> Option<Vec<DirEnt>> {
+    let (mut volume, number) = volume(node);
+    let inode = volume.read_inode(number)?;
+
+    let ret = volume
+        .read_dir(&inode)
+        .ok()?
+        .iter()
+        .filter_map(|(name, child)| {
+            let child = unsafe { &*volume.node(node.inode >> 32, *child, name)? };
+            Some(DirEnt {
+                name: name.clone(),
+                inode: child.inode,
+                kind: child.kind.clone(),
+            })
+        })
+        .collect();
+
+    Some(ret)
+}
+
+pub fn ext2_finddir(node: &VFS_Node, name: &str) -> Option<*mut VFS_Node> {
+    let (mut volume, number) = volume(node);
+    let inode = volume.read_inode(number)?;
+
+    let (name, child) = volume
+        .read_dir(&inode)
+        .ok()?
+        .into_iter()
+        .find(|(entry, _)| entry == name)?;
+    volume.node(node.inode >> 32, child, &name)
+}
+
+pub fn ext2_readlink(node: &VFS_Node) -> Option<String> {
+    let (mut volume, number) = volume(node);
+    let inode = volume.read_inode(number)?;
+    let size = inode.size as usize;
+    // Targets fit in a single block
+    if size > volume.block_size {
+        return None;
+    }
+
+    // Fast symlinks have no data blocks, only an extended attribute block may be counted
+    let acl_sectors = if inode.file_acl != 0 {
+        volume.block_size as u32 / 512
+    } else {
+        0
+    };
+    let mut target = vec![0; size];
+    if size < FAST_SYMLINK_MAX && inode.sectors == acl_sectors {
+        for (i, byte) in target.iter_mut().enumerate() {
+            *byte = inode.block[i / 4].to_le_bytes()[i % 4];
+        }
+    } else {
+        volume.read_data(&inode, 0, &mut target)?;
+    }
+
+    String::from_utf8(target).ok()
+}
@@ -0,0 +1,978 @@
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+use crate::arch::{clock, rtc::DateTime};
+use crate::filesystem::{DirEnt, FsError, Inode, Type, VFS_Node};
+use crate::logging;
+use crate::sync::{LockGuard, Mutex, SpinMutex};
+
+use super::blockdev::{BlockDevice, Partition};
+
+/// Mounted volumes, the index is stored in the upper half of the node inodes.
+/// Volumes are never unmounted, each is leaked so it can be locked on its own,
+/// with a sleeping mutex as it is held across disk transfers
+static FAT_VOLUMES: SpinMutex<Vec<&'static Mutex<FatVolume>>> = SpinMutex::new(Vec::new());
+
+const DIR_ENTRY_SIZE: usize = 32;
+
+// Directory entry attributes
+const ATTR_READ_ONLY: u8 = 0x01;
+const ATTR_HIDDEN: u8 = 0x02;
+const ATTR_SYSTEM: u8 = 0x04;
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_ARCHIVE: u8 = 0x20;
+const ATTR_LONG_NAME: u8 = ATTR_READ_ONLY | ATTR_HIDDEN | ATTR_SYSTEM | ATTR_VOLUME_ID;
+
+/// First byte of a free entry, 0 also marks the end of the directory
+const ENTRY_DELETED: u8 = 0xE5;
+const ENTRY_END: u8 = 0x00;
+/// Set in the sequence number of the last long name entry of a file
+const LFN_LAST: u8 = 0x40;
+const LFN_CHARS: usize = 13;
+/// Byte offsets of the name characters in a long name entry
+const LFN_CHAR_OFFSETS: [usize; LFN_CHARS] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+
+// Flags in the reserved byte of short entries, set by Windows and Linux for names
+// that are 8.3 apart from being lower case
+const LOWERCASE_BASE: u8 = 0x08;
+const LOWERCASE_EXT: u8 = 0x10;
+
+const FSINFO_SIGNATURE: u32 = 0x41615252;
+
+/// Entry offset of files that were removed from their directory
+const DETACHED: usize = usize::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FatType {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+impl FatType {
+    fn bits(&self) -> usize {
+        match self {
+            FatType::Fat12 => 12,
+            FatType::Fat16 => 16,
+            FatType::Fat32 => 32,
+        }
+    }
+}
+
+/// Position of a file on the volume
+#[derive(Debug, Clone, Copy)]
+struct FatFile {
+    /// First cluster of the contents, 0 for empty files and the FAT12/16 root directory
+    cluster: u32,
+    /// First cluster of the directory holding the entry
+    parent: u32,
+    /// Byte offset of the short entry in the parent directory
+    entry: usize,
+}
+
+/// A file as found in a directory
+struct FatDirEntry {
+    name: String,
+    short_name: [u8; 11],
+    attr: u8,
+    cluster: u32,
+    size: u32,
+    /// Byte offset of the short entry
+    offset: usize,
+    /// Byte offset of the first long name entry, or of the short entry if there are none
+    first_slot: usize,
+}
+
+pub struct FatVolume {
+    device: Partition,
+    kind: FatType,
+    cluster_size: usize,
+    /// Byte offset of the first FAT
+    fat_start: usize,
+    /// Size of a FAT in bytes
+    fat_size: usize,
+    fat_count: usize,
+    /// Copy of the first FAT, changes are written through to every FAT on disk
+    fat: Vec<u8>,
+    /// Fixed size root directory of FAT12/16, in bytes
+    root_dir_start: usize,
+    root_dir_size: usize,
+    /// Byte offset of cluster 2
+    data_start: usize,
+    cluster_count: u32,
+    /// First cluster of the FAT32 root directory, 0 on FAT12/16
+    root_cluster: u32,
+    /// FAT32 sector with the free cluster count, invalidated on the first change
+    fsinfo_sector: Option<usize>,
+    /// Where the search for a free cluster starts
+    next_free: u32,
+    /// Indexed by the lower half of the inode number, the root is 0
+    files: Vec<FatFile>,
+    /// Boxed so the node addresses stay valid as files are looked up
+    #[allow(clippy::vec_box)]
+    nodes: Vec<Box<VFS_Node>>,
+    /// File index of each (parent directory cluster, entry offset)
+    by_entry: BTreeMap<(u32, usize), usize>,
+}
+
+// The nodes are only used behind the lock of the volume, or through the
+// pointers handed to the VFS which keep their boxes alive
+unsafe impl Send for FatVolume {}
+
+fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(buf[offset..offset + 2].try_into().unwrap())
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+/// Mounts the FAT volume on the device, returns the root directory
+pub fn mount(mut device: Partition) -> Result<*mut VFS_Node, FsError> {
+    let mut boot = vec![0; device.sector_size()];
+    device.read_sectors(0, &mut boot).ok_or(FsError::Invalid)?;
+    if read_u16(&boot, 510) != 0xAA55 {
+        return Err(FsError::Invalid);
+    }
+
+    let bytes_per_sector = read_u16(&boot, 11) as usize;
+    let sectors_per_cluster = boot[13] as usize;
+    let reserved_sectors = read_u16(&boot, 14) as usize;
+    let fat_count = boot[16] as usize;
+    let root_entries = read_u16(&boot, 17) as usize;
+    let total_sectors = match read_u16(&boot, 19) {
+        0 => read_u32(&boot, 32) as usize,
+        n => n as usize,
+    };
+    let fat_sectors = match read_u16(&boot, 22) {
+        0 => read_u32(&boot, 36) as usize,
+        n => n as usize,
+    };
+
+    if !bytes_per_sector.is_power_of_two()
+        || !(512..=4096).contains(&bytes_per_sector)
+        || !sectors_per_cluster.is_power_of_two()
+        || reserved_sectors == 0
+        || fat_count == 0
+        || fat_sectors == 0
+    {
+        return Err(FsError::Invalid);
+    }
+
+    let root_dir_sectors = (root_entries * DIR_ENTRY_SIZE).div_ceil(bytes_per_sector);
+    let data_sector = reserved_sectors + fat_count * fat_sectors + root_dir_sectors;
+    if data_sector >= total_sectors || total_sectors * bytes_per_sector > device.size() {
+        return Err(FsError::Invalid);
+    }
+
+    // The type only depends on the number of clusters
+    let cluster_count = ((total_sectors - data_sector) / sectors_per_cluster) as u32;
+    let kind = match cluster_count {
+        0..=4084 => FatType::Fat12,
+        4085..=65524 => FatType::Fat16,
+        _ => FatType::Fat32,
+    };
+    let (root_cluster, fsinfo_sector) = match kind {
+        FatType::Fat32 => (read_u32(&boot, 44), Some(read_u16(&boot, 48) as usize)),
+        _ => (0, None),
+    };
+
+    // The FAT needs an entry for every cluster, volumes with a smaller one are corrupt
+    let fat_size = fat_sectors * bytes_per_sector;
+    let fat_used = ((cluster_count as usize + 2) * kind.bits()).div_ceil(8);
+    if fat_size < fat_used {
+        return Err(FsError::Invalid);
+    }
+
+    // Only the part of the FAT covering existing clusters is kept, plus a zero
+    // byte as FAT12 entries are read two bytes at a time
+    let mut fat = vec![0; fat_used + 1];
+    device
+        .read_bytes(reserved_sectors * bytes_per_sector, &mut fat[..fat_used])
+        .ok_or(FsError::Invalid)?;
+
+    let mut volume = FatVolume {
+        device,
+        kind,
+        cluster_size: sectors_per_cluster * bytes_per_sector,
+        fat_start: reserved_sectors * bytes_per_sector,
+        fat_size,
+        fat_count,
+        fat,
+        root_dir_start: (reserved_sectors + fat_count * fat_sectors) * bytes_per_sector,
+        root_dir_size: root_entries * DIR_ENTRY_SIZE,
+        data_start: data_sector * bytes_per_sector,
+        cluster_count,
+        root_cluster,
+        fsinfo_sector: fsinfo_sector.map(|sector| sector * bytes_per_sector),
+        next_free: 2,
+        files: vec![FatFile {
+            cluster: root_cluster,
+            parent: 0,
+            entry: 0,
+        }],
+        nodes: Vec::new(),
+        by_entry: BTreeMap::new(),
+    };
+    if kind == FatType::Fat32 && !volume.valid_cluster(root_cluster) {
+        return Err(FsError::Invalid);
+    }
+
+    log!(
+        "Mounted FAT{} volume, {} clusters of {} bytes",
+        kind.bits(),
+        cluster_count,
+        volume.cluster_size
+    );
+
+    let mut volumes = FAT_VOLUMES.lock();
+    let index = volumes.len();
+    volume.nodes.push(Box::new(new_node(
+        "fat".to_string(),
+        Type::Dir,
+        index << 32,
+        0,
+    )));
+    let root = &mut *volume.nodes[0] as *mut VFS_Node;
+    volumes.push(Box::leak(Box::new(Mutex::new(volume))));
+
+    Ok(root)
+}
+
+/// Returns the volume of a node and the index of the node in it
+fn volume(node: &VFS_Node) -> (LockGuard<'static, FatVolume>, usize) {
+    let volume = FAT_VOLUMES.lock()[node.inode >> 32];
+    (volume.lock(), node.inode & 0xFFFF_FFFF)
+}
+
+/// Creates a VFS node with the operations matching its type
+fn new_node(name: String, kind: Type, inode: Inode, size: usize) -> VFS_Node {
+    let is_dir = kind == Type::Dir;
+
+    VFS_Node {
+        name,
+        kind: kind.clone(),
+        inode,
+        size,
+        links: 1,
+        uid: 0,
+        gid: 0,
+        permissions: kind.default_permissions(),
+        read: if is_dir { None } else { Some(fat_read) },
+        write: if is_dir { None } else { Some(fat_write) },
+        readdir: if is_dir { Some(fat_readdir) } else { None },
+        finddir: if is_dir { Some(fat_finddir) } else { None },
+        create: if is_dir { Some(fat_create) } else { None },
+        truncate: if is_dir { None } else { Some(fat_truncate) },
+        unlink: if is_dir { Some(fat_unlink) } else { None },
+        readlink: None,
+        symlink: None,
+        link: None,
+        mount_point: None,
+        open: 0,
+        release: None,
+        seek: None,
+    }
+}
+
+impl FatVolume {
+    fn valid_cluster(&self, cluster: u32) -> bool {
+        cluster >= 2 && cluster < self.cluster_count + 2
+    }
+
+    fn cluster_address(&self, cluster: u32) -> usize {
+        self.data_start + (cluster as usize - 2) * self.cluster_size
+    }
+
+    fn fat_entry(&self, cluster: u32) -> u32 {
+        let n = cluster as usize;
+        match self.kind {
+            FatType::Fat12 => {
+                let value = read_u16(&self.fat, n + n / 2);
+                if n % 2 == 1 {
+                    (value >> 4) as u32
+                } else {
+                    (value & 0xFFF) as u32
+                }
+            }
+            FatType::Fat16 => read_u16(&self.fat, n * 2) as u32,
+            FatType::Fat32 => read_u32(&self.fat, n * 4) & 0x0FFF_FFFF,
+        }
+    }
+
+    /// Changes an entry in memory and in every FAT on disk
+    fn set_fat_entry(&mut self, cluster: u32, value: u32) -> Option<()> {
+        let n = cluster as usize;
+        let (offset, len) = match self.kind {
+            FatType::Fat12 => {
+                let offset = n + n / 2;
+                let old = read_u16(&self.fat, offset);
+                // Two entries share the middle byte
+                let new = if n % 2 == 1 {
+                    (old & 0x000F) | ((value as u16) << 4)
+                } else {
+                    (old & 0xF000) | (value as u16 & 0xFFF)
+                };
+                self.fat[offset..offset + 2].copy_from_slice(&new.to_le_bytes());
+                (offset, 2)
+            }
+            FatType::Fat16 => {
+                self.fat[n * 2..n * 2 + 2].copy_from_slice(&(value as u16).to_le_bytes());
+                (n * 2, 2)
+            }
+            FatType::Fat32 => {
+                // The upper 4 bits are reserved and must be kept
+                let old = read_u32(&self.fat, n * 4);
+                let new = (old & 0xF000_0000) | (value & 0x0FFF_FFFF);
+                self.fat[n * 4..n * 4 + 4].copy_from_slice(&new.to_le_bytes());
+                (n * 4, 4)
+            }
+        };
+
+        for i in 0..self.fat_count {
+            let address = self.fat_start + i * self.fat_size + offset;
+            self.device
+                .write_bytes(address, &self.fat[offset..offset + len])?;
+        }
+
+        // The free cluster count of FAT32 is only a hint, mark it as unknown
+        // instead of keeping it up to date
+        if let Some(fsinfo) = self.fsinfo_sector.take() {
+            let mut signature = [0; 4];
+            self.device.read_bytes(fsinfo, &mut signature)?;
+            if u32::from_le_bytes(signature) == FSINFO_SIGNATURE {
+                self.device.write_bytes(fsinfo + 488, &[0xFF; 8])?;
+            }
+        }
+        Some(())
+    }
+
+    /// Returns the clusters of a chain in order. Any value outside the cluster
+    /// range (end of chain marker, free or bad cluster) ends it
+    fn chain(&self, first: u32) -> Vec<u32> {
+        let mut clusters = Vec::new();
+        let mut cluster = first;
+
+        // The length check stops on corrupted chains that loop
+        while self.valid_cluster(cluster) && clusters.len() < self.cluster_count as usize {
+            clusters.push(cluster);
+            cluster = self.fat_entry(cluster);
+        }
+        clusters
+    }
+
+    /// Finds a free cluster, zeroes it and appends it to the chain ending with `previous`
+    fn allocate_cluster(&mut self, previous: Option<u32>) -> Option<u32> {
+        let cluster = (0..self.cluster_count)
+            .map(|i| 2 + (self.next_free - 2 + i) % self.cluster_count)
+            .find(|&cluster| self.fat_entry(cluster) == 0)?;
+
+        self.set_fat_entry(cluster, 0x0FFF_FFFF)?;
+        if let Some(previous) = previous {
+            self.set_fat_entry(previous, cluster)?;
+        }
+        self.next_free = cluster + 1;
+        if self.next_free >= self.cluster_count + 2 {
+            self.next_free = 2;
+        }
+
+        let zeroes = vec![0; self.cluster_size];
+        self.device
+            .write_bytes(self.cluster_address(cluster), &zeroes)?;
+        Some(cluster)
+    }
+
+    fn free_chain(&mut self, first: u32) -> Option<()> {
+        for cluster in self.chain(first) {
+            self.set_fat_entry(cluster, 0)?;
+        }
+        Some(())
+    }
+
+    /// Makes the chain of a file long enough for `size` bytes, returns the chain
+    fn grow(&mut self, file: usize, size: usize) -> Option<Vec<u32>> {
+        let mut chain = self.chain(self.files[file].cluster);
+        while chain.len() * self.cluster_size < size {
+            let cluster = self.allocate_cluster(chain.last().copied())?;
+            if chain.is_empty() {
+                self.files[file].cluster = cluster;
+            }
+            chain.push(cluster);
+        }
+        Some(chain)
+    }
+
+    /// Copies bytes between `buffer` and the file contents, starting `offset` bytes into the chain
+    fn transfer(
+        &mut self,
+        chain: &[u32],
+        offset: usize,
+        buffer: &mut [u8],
+        write: bool,
+    ) -> Option<()> {
+        let mut done = 0;
+        while done < buffer.len() {
+            let position = offset + done;
+            let cluster = *chain.get(position / self.cluster_size)?;
+            let in_cluster = position % self.cluster_size;
+            let len = (self.cluster_size - in_cluster).min(buffer.len() - done);
+
+            let address = self.cluster_address(cluster) + in_cluster;
+            let part = &mut buffer[done..done + len];
+            if write {
+                self.device.write_bytes(address, part)?;
+            } else {
+                self.device.read_bytes(address, part)?;
+            }
+            done += len;
+        }
+        Some(())
+    }
+
+    fn is_fixed_root(&self, dir: u32) -> bool {
+        dir == 0 && self.kind != FatType::Fat32
+    }
+
+    /// Raw contents of the directory starting at `dir`
+    fn read_dir(&mut self, dir: u32) -> Option<Vec<u8>> {
+        if self.is_fixed_root(dir) {
+            let mut raw = vec![0; self.root_dir_size];
+            self.device.read_bytes(self.root_dir_start, &mut raw)?;
+            return Some(raw);
+        }
+
+        let chain = self.chain(dir);
+        let mut raw = vec![0; chain.len() * self.cluster_size];
+        self.transfer(&chain, 0, &mut raw, false)?;
+        Some(raw)
+    }
+
+    /// Overwrites the start of the entry at `offset` in the directory
+    fn write_dir_entry(&mut self, dir: u32, offset: usize, entry: &[u8]) -> Option<()> {
+        if self.is_fixed_root(dir) {
+            self.device
+                .write_bytes(self.root_dir_start + offset, entry)?;
+            return Some(());
+        }
+
+        let chain = self.chain(dir);
+        let mut entry = entry.to_vec();
+        self.transfer(&chain, offset, &mut entry, true)
+    }
+
+    /// Returns the offset of `count` consecutive free entries in the directory,
+    /// adding clusters to it if needed
+    fn free_slots(&mut self, dir: u32, count: usize) -> Option<usize> {
+        let raw = self.read_dir(dir)?;
+        let slots = raw.len() / DIR_ENTRY_SIZE;
+
+        let mut run = 0;
+        let mut end = false;
+        for i in 0..slots {
+            let first = raw[i * DIR_ENTRY_SIZE];
+            end |= first == ENTRY_END;
+            if end || first == ENTRY_DELETED {
+                run += 1;
+                if run == count {
+                    return Some((i + 1 - count) * DIR_ENTRY_SIZE);
+                }
+            } else {
+                run = 0;
+            }
+        }
+
+        // The root directory of FAT12/16 can't grow
+        if self.is_fixed_root(dir) {
+            return None;
+        }
+        let start = (slots - run) * DIR_ENTRY_SIZE;
+        let mut chain = self.chain(dir);
+        while chain.len() * self.cluster_size < start + count * DIR_ENTRY_SIZE {
+            chain.push(self.allocate_cluster(chain.last().copied())?);
+        }
+        Some(start)
+    }
+
+    /// Writes the first cluster and size of a file to its directory entry
+    fn update_entry(&mut self, file: usize, size: usize) -> Option<()> {
+        // The root directory has no entry
+        if file == 0 || self.files[file].entry == DETACHED {
+            return Some(());
+        }
+        let FatFile {
+            cluster,
+            parent,
+            entry,
+        } = self.files[file];
+
+        let chain = self.chain(parent);
+        let mut raw = [0; DIR_ENTRY_SIZE];
+        if self.is_fixed_root(parent) {
+            self.device
+                .read_bytes(self.root_dir_start + entry, &mut raw)?;
+        } else {
+            self.transfer(&chain, entry, &mut raw, false)?;
+        }
+
+        raw[20..22].copy_from_slice(&((cluster >> 16) as u16).to_le_bytes());
+        raw[26..28].copy_from_slice(&(cluster as u16).to_le_bytes());
+        raw[28..32].copy_from_slice(&(size as u32).to_le_bytes());
+        self.write_dir_entry(parent, entry, &raw)
+    }
+
+    /// Returns the node of a directory entry, creating it on first use
+    fn node(&mut self, volume: usize, dir: u32, entry: &FatDirEntry) -> *mut VFS_Node {
+        let index = match self.by_entry.get(&(dir, entry.offset)) {
+            Some(&index) => index,
+            None => {
+                let index = self.files.len();
+                let kind = if entry.attr & ATTR_DIRECTORY != 0 {
+                    Type::Dir
+                } else {
+                    Type::File
+                };
+                self.files.push(FatFile {
+                    cluster: entry.cluster,
+                    parent: dir,
+                    entry: entry.offset,
+                });
+                self.nodes.push(Box::new(new_node(
+                    entry.name.clone(),
+                    kind,
+                    volume << 32 | index,
+                    entry.size as usize,
+                )));
+                self.by_entry.insert((dir, entry.offset), index);
+                index
+            }
+        };
+
+        &mut *self.nodes[index] as *mut VFS_Node
+    }
+}
+
+/// Checksum of a short name stored in its long name entries
+fn lfn_checksum(short_name: &[u8]) -> u8 {
+    short_name
+        .iter()
+        .fold(0u8, |sum, &c| sum.rotate_right(1).wrapping_add(c))
+}
+
+/// Name of a short entry as shown to the user, "README.TXT" or "readme.txt"
+fn short_display_name(short_name: &[u8], flags: u8) -> String {
+    let part = |bytes: &[u8], lowercase: bool| -> String {
+        let s: String = bytes.iter().map(|&b| b as char).collect();
+        let s = s.trim_end();
+        if lowercase {
+            s.to_lowercase()
+        } else {
+            s.to_string()
+        }
+    };
+
+    let base = part(&short_name[..8], flags & LOWERCASE_BASE != 0);
+    let ext = part(&short_name[8..], flags & LOWERCASE_EXT != 0);
+    if ext.is_empty() {
+        base
+    } else {
+        base + "." + &ext
+    }
+}
+
+/// Parses the raw contents of a directory, skipping "." and ".."
+fn parse_dir(raw: &[u8]) -> Vec<FatDirEntry> {
+    let mut entries = Vec::new();
+    // Long name entries come before their short entry, the last part first
+    let mut long_name: Vec<u16> = Vec::new();
+    let mut long_start = None;
+    let mut checksum = 0;
+
+    for (i, slot) in raw.chunks_exact(DIR_ENTRY_SIZE).enumerate() {
+        let offset = i * DIR_ENTRY_SIZE;
+        let attr = slot[11];
+
+        if slot[0] == ENTRY_END {
+            break;
+        }
+        if slot[0] == ENTRY_DELETED {
+            long_start = None;
+            continue;
+        }
+
+        if attr == ATTR_LONG_NAME {
+            let sequence = (slot[0] & 0x1F) as usize;
+            if slot[0] & LFN_LAST != 0 {
+                long_name = vec![0xFFFF; sequence * LFN_CHARS];
+                long_start = Some(offset);
+                checksum = slot[13];
+            }
+            if sequence == 0 || sequence * LFN_CHARS > long_name.len() || slot[13] != checksum {
+                long_start = None;
+                continue;
+            }
+            for (j, &char_offset) in LFN_CHAR_OFFSETS.iter().enumerate() {
+                long_name[(sequence - 1) * LFN_CHARS + j] = read_u16(slot, char_offset);
+            }
+            continue;
+        }
+
+        let first_slot = long_start.take();
+        if attr & ATTR_VOLUME_ID != 0 || slot[0] == b'.' {
+            continue;
+        }
+
+        let short_name: [u8; 11] = slot[..11].try_into().unwrap();
+        let name = match first_slot {
+            Some(_) if lfn_checksum(&short_name) == checksum => {
+                let units = long_name
+                    .iter()
+                    .copied()
+                    .take_while(|&c| c != 0 && c != 0xFFFF);
+                char::decode_utf16(units)
+                    .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                    .collect()
+            }
+            _ => short_display_name(&short_name, slot[12]),
+        };
+
+        entries.push(FatDirEntry {
+            name,
+            short_name,
+            attr,
+            cluster: (read_u16(slot, 20) as u32) << 16 | read_u16(slot, 26) as u32,
+            size: read_u32(slot, 28),
+            offset,
+            first_slot: first_slot.unwrap_or(offset),
+        });
+    }
+
+    entries
+}
+
+/// Characters allowed in short names besides upper case letters and digits
+fn valid_short_char(c: char) -> bool {
+    c.is_ascii_uppercase() || c.is_ascii_digit() || "!#$%&'()-@^_`{}~".contains(c)
+}
+
+/// Returns the short name for a new file and whether long name entries are needed
+fn short_name(name: &str, existing: &[FatDirEntry]) -> ([u8; 11], bool) {
+    let (base, ext) = match name.rfind('.') {
+        Some(dot) if dot > 0 => (&name[..dot], &name[dot + 1..]),
+        _ => (name, ""),
+    };
+
+    let mut short = [b' '; 11];
+    let fits = (1..=8).contains(&base.len())
+        && ext.len() <= 3
+        && base.chars().chain(ext.chars()).all(valid_short_char);
+    if fits {
+        short[..base.len()].copy_from_slice(base.as_bytes());
+        short[8..8 + ext.len()].copy_from_slice(ext.as_bytes());
+        return (short, false);
+    }
+
+    // Otherwise build a unique "BASIS~N.EXT" name
+    let clean = |s: &str| -> Vec<u8> {
+        s.chars()
+            .filter(|&c| c != ' ' && c != '.')
+            .map(|c| c.to_ascii_uppercase())
+            .map(|c| if valid_short_char(c) { c as u8 } else { b'_' })
+            .collect()
+    };
+    let base = clean(base);
+    let ext = clean(ext);
+    let ext_len = ext.len().min(3);
+    short[8..8 + ext_len].copy_from_slice(&ext[..ext_len]);
+
+    for n in 1.. {
+        let tail = alloc::format!("~{}", n);
+        let base_len = base.len().min(8 - tail.len());
+
+        short[..8].fill(b' ');
+        short[..base_len].copy_from_slice(&base[..base_len]);
+        short[base_len..base_len + tail.len()].copy_from_slice(tail.as_bytes());
+
+        if !existing.iter().any(|e| e.short_name == short) {
+            break;
+        }
+    }
+    (short, true)
+}
+
+/// Current date and time in the FAT format, which holds the years 1980 to 2107
+/// and the seconds in steps of two
+fn timestamp() -> (u16, u16) {
+    let now = DateTime::from_unix(clock::realtime_ns() / clock::NANOS_PER_SEC as i64);
+    let year = now.year.clamp(1980, 2107) - 1980;
+    let date = ((year as u16) << 9) | ((now.month as u16) << 5) | now.day as u16;
+    let time = ((now.hour as u16) << 11) | ((now.minute as u16) << 5) | (now.second / 2) as u16;
+    (date, time)
+}
+
+fn short_entry(short_name: &[u8; 11], attr: u8, cluster: u32) -> [u8; DIR_ENTRY_SIZE] {
+    let mut entry = [0; DIR_ENTRY_SIZE];
+    entry[..11].copy_from_slice(short_name);
+    entry[11] = attr;
+    let (date, time) = timestamp();
+    // Creation, access and modification dates, creation and modification times
+    for offset in [16, 18, 24] {
+        entry[offset..offset + 2].copy_from_slice(&date.to_le_bytes());
+    }
+    for offset in [14, 22] {
+        entry[offset..offset + 2].copy_from_slice(&time.to_le_bytes());
+    }
+    entry[20..22].copy_from_slice(&((cluster >> 16) as u16).to_le_bytes());
+    entry[26..28].copy_from_slice(&(cluster as u16).to_le_bytes());
+    entry
+}
+
+/// Long name entries for `name`, in the order they are stored on disk
+fn long_entries(name: &str, checksum: u8) -> Vec<[u8; DIR_ENTRY_SIZE]> {
+    let mut units: Vec<u16> = name.encode_utf16().collect();
+    let count = units.len().div_ceil(LFN_CHARS);
+    // The name is null terminated unless it fills the last entry, then padded with 0xFFFF
+    if !units.len().is_multiple_of(LFN_CHARS) {
+        units.push(0);
+    }
+    units.resize(count * LFN_CHARS, 0xFFFF);
+
+    (1..=count)
+        .rev()
+        .map(|sequence| {
+            let mut entry = [0; DIR_ENTRY_SIZE];
+            entry[0] = sequence as u8 | if sequence == count { LFN_LAST } else { 0 };
+            entry[11] = ATTR_LONG_NAME;
+            entry[13] = checksum;
+            let chars = &units[(sequence - 1) * LFN_CHARS..sequence * LFN_CHARS];
+            for (&c, &offset) in chars.iter().zip(LFN_CHAR_OFFSETS.iter()) {
+                entry[offset..offset + 2].copy_from_slice(&c.to_le_bytes());
+            }
+            entry
+        })
+        .collect()
+}
+
+pub fn fat_read(node: &VFS_Node, offset: usize, size: usize, buffer: &mut [u8]) -> Option<usize> {
+    let (mut volume, file) = volume(node);
+    if offset >= node.size {
+        return None;
+    }
+    let size = size.min(node.size - offset).min(buffer.len());
+
+    let chain = volume.chain(volume.files[file].cluster);
+    volume.transfer(&chain, offset, &mut buffer[..size], false)?;
+    Some(size)
+}
+
+pub fn fat_write(
+    node: &mut VFS_Node,
+    offset: usize,
+    size: usize,
+    buffer: &[u8],
+) -> Result<usize, FsError> {
+    let (mut volume, file) = volume(node);
+    let size = size.min(buffer.len());
+    let end = offset
+        .checked_add(size)
+        .filter(|&end| end <= u32::MAX as usize)
+        .ok_or(FsError::TooBig)?;
+    if volume.files[file].entry == DETACHED {
+        return Err(FsError::Failed);
+    }
+
+    let allocated = volume.chain(volume.files[file].cluster).len() * volume.cluster_size;
+    let chain = volume.grow(file, end).ok_or(FsError::NoSpace)?;
+
+    // New clusters are zeroed, but the old last cluster may hold stale data past the end
+    if offset > node.size {
+        let mut zeroes = vec![0; offset.min(allocated) - node.size.min(allocated)];
+        volume
+            .transfer(&chain, node.size, &mut zeroes, true)
+            .ok_or(FsError::Failed)?;
+    }
+
+    let mut data = buffer[..size].to_vec();
+    volume
+        .transfer(&chain, offset, &mut data, true)
+        .ok_or(FsError::Failed)?;
+
+    node.size = node.size.max(end);
+    volume
+        .update_entry(file, node.size)
+        .ok_or(FsError::Failed)?;
+    Ok(size)
+}
+
+pub fn fat_truncate(node: &mut VFS_Node, size: usize) -> Result<usize, FsError> {
+    if size > u32::MAX as usize {
+        return Err(FsError::TooBig);
+    }
+    if size > node.size {
+        let zeroes = vec![0; size - node.size];
+        fat_write(node, node.size, zeroes.len(), &zeroes)?;
+        return Ok(size);
+    }
+
+    let (mut volume, file) = volume(node);
+    let first = volume.files[file].cluster;
+    let chain = volume.chain(first);
+    let needed = size.div_ceil(volume.cluster_size);
+    if needed == 0 {
+        volume.free_chain(first).ok_or(FsError::Failed)?;
+        volume.files[file].cluster = 0;
+    } else if needed < chain.len() {
+        volume
+            .set_fat_entry(chain[needed - 1], 0x0FFF_FFFF)
+            .ok_or(FsError::Failed)?;
+        volume.free_chain(chain[needed]).ok_or(FsError::Failed)?;
+    }
+
+    node.size = size;
+    volume.update_entry(file, size).ok_or(FsError::Failed)?;
+    Ok(size)
+}
+
+pub fn fat_readdir(node: &VFS_Node) -> Option<Vec<DirEnt>> {
+    let (mut volume, file) = volume(node);
+    let dir = volume.files[file].cluster;
+    let raw = volume.read_dir(dir)?;
+
+    let ret = parse_dir(&raw)
+        .iter()
+        .map(|entry| {
+            let child = unsafe { &*volume.node(node.inode >> 32, dir, entry) };
+            DirEnt {
+                name: child.name.clone(),
+                inode: child.inode,
+                kind: child.kind.clone(),
+            }
+        })
+        .collect();
+
+    Some(ret)
+}
+
+pub fn fat_finddir(node: &VFS_Node, name: &str) -> Option<*mut VFS_Node> {
+    let (mut volume, file) = volume(node);
+    let dir = volume.files[file].cluster;
+    let raw = volume.read_dir(dir)?;
+
+    // Names are case insensitive
+    parse_dir(&raw)
+        .iter()
+        .find(|entry| entry.name.eq_ignore_ascii_case(name))
+        .map(|entry| volume.node(node.inode >> 32, dir, entry))
+}
+
+pub fn fat_create(node: &mut VFS_Node, name: &str, kind: Type) -> Option<*mut VFS_Node> {
+    if (kind != Type::File && kind != Type::Dir)
+        || name.is_empty()
+        || name.len() > 255
+        || name == "."
+        || name == ".."
+        || name.contains(|c: char| "\\/:*?\"<>|".contains(c) || c < ' ')
+    {
+        return None;
+    }
+    let (mut volume, file) = volume(node);
+    let dir = volume.files[file].cluster;
+    let existing = parse_dir(&volume.read_dir(dir)?);
+    if existing.iter().any(|e| e.name.eq_ignore_ascii_case(name)) {
+        return None;
+    }
+
+    let attr = if kind == Type::Dir {
+        ATTR_DIRECTORY
+    } else {
+        ATTR_ARCHIVE
+    };
+    let cluster = if kind == Type::Dir {
+        volume.allocate_cluster(None)?
+    } else {
+        0
+    };
+
+    let written = (|| {
+        // Directories start with a cluster holding the "." and ".." entries,
+        // where ".." is 0 when the parent is the root
+        if kind == Type::Dir {
+            let parent = if dir == volume.root_cluster { 0 } else { dir };
+            volume.write_dir_entry(
+                cluster,
+                0,
+                &short_entry(b".          ", ATTR_DIRECTORY, cluster),
+            )?;
+            volume.write_dir_entry(
+                cluster,
+                DIR_ENTRY_SIZE,
+                &short_entry(b"..         ", ATTR_DIRECTORY, parent),
+            )?;
+        }
+
+        let (short, needs_long) = short_name(name, &existing);
+        let mut slots = if needs_long {
+            long_entries(name, lfn_checksum(&short))
+        } else {
+            Vec::new()
+        };
+        slots.push(short_entry(&short, attr, cluster));
+
+        let start = volume.free_slots(dir, slots.len())?;
+        for (i, slot) in slots.iter().enumerate() {
+            volume.write_dir_entry(dir, start + i * DIR_ENTRY_SIZE, slot)?;
+        }
+        Some((short, start, slots.len()))
+    })();
+    let Some((short, start, slot_count)) = written else {
+        // Nothing refers to the cluster of a directory which could not be added
+        if cluster != 0 {
+            volume.free_chain(cluster);
+        }
+        return None;
+    };
+
+    let entry = FatDirEntry {
+        name: name.to_string(),
+        short_name: short,
+        attr,
+        cluster,
+        size: 0,
+        offset: start + (slot_count - 1) * DIR_ENTRY_SIZE,
+        first_slot: start,
+    };
+    Some(volume.node(node.inode >> 32, dir, &entry))
+}
+
+pub fn fat_unlink(node: &mut VFS_Node, name: &str) -> Option<()> {
+    let (mut volume, file) = volume(node);
+    let dir = volume.files[file].cluster;
+    let entries = parse_dir(&volume.read_dir(dir)?);
+    let entry = entries
+        .iter()
+        .find(|entry| entry.name.eq_ignore_ascii_case(name))?;
+
+    // Directories must be empty to be removed
+    if entry.attr & ATTR_DIRECTORY != 0 && !parse_dir(&volume.read_dir(entry.cluster)?).is_empty() {
+        return None;
+    }
+
+    // The clusters are freed right away, nodes still open see an empty file
+    // that can't be written anymore
+    if let Some(file) = volume.by_entry.remove(&(dir, entry.offset)) {
+        volume.files[file].cluster = 0;
+        volume.files[file].entry = DETACHED;
+        volume.nodes[file].size = 0;
+    }
+    volume.free_chain(entry.cluster)?;
+
+    for offset in (entry.first_slot..=entry.offset).step_by(DIR_ENTRY_SIZE) {
+        volume.write_dir_entry(dir, offset, &[ENTRY_DELETED])?;
+    }
+    Some(())
+}
@@ -1,8 +1,12 @@
 use core::slice::from_raw_parts_mut;
 
-use crate::arch::addressing::VirtAddr;
+use crate::{
+    arch::addressing::VirtAddr,
+    sync::{IrqSpinMutex, Once},
+};
 
-pub static mut FRAMEBUFFER: Option<Framebuffer> = None;
+/// Also drawn on by the console while logging, so interrupts are disabled while it is locked
+pub static FRAMEBUFFER: Once<IrqSpinMutex<Framebuffer>> = Once::new();
 
 pub struct Framebuffer {
     pub buffer: &'static mut [u32],
@@ -29,16 +33,14 @@ impl Rgb {
 
 impl Framebuffer {
     pub fn init(addr: VirtAddr, width: usize, height: usize, bpp: u8) {
-        unsafe {
-            let fb = Framebuffer {
-                buffer: from_raw_parts_mut(addr.as_u64() as *mut u32, width * height),
-                width,
-                height,
-                bpp,
-            };
-
-            FRAMEBUFFER = Some(fb);
-        }
+        let fb = Framebuffer {
+            buffer: unsafe { from_raw_parts_mut(addr.as_u64() as *mut u32, width * height) },
+            width,
+            height,
+            bpp,
+        };
+
+        FRAMEBUFFER.set(IrqSpinMutex::new(fb));
     }
 
     pub fn fill(&mut self, color: Rgb) {
@@ -2,17 +2,19 @@ use core::slice;
 
 use alloc::{
     string::{String, ToString},
+    vec,
     vec::Vec,
 };
 
 use crate::{
     arch::addressing::KERNEL_BASE,
     filesystem::{DirEnt, Type, VFS_Node},
+    sync::Global,
 };
 
-use super::devfs::initialize_devfs;
+use super::{devfs::initialize_devfs, procfs::initialize_procfs, tmpfs::initialize_tmpfs};
 
-static mut INIT_RD_FS: Option<InitRD> = None;
+static INIT_RD_FS: Global<InitRD> = Global::new();
 
 /// Filesystem header with the number of files
 #[derive(Debug)]
@@ -49,10 +51,38 @@ pub struct InitRD {
     header: Header,
     files: Vec<FileHeader>,
     root: VFS_Node,
-    dev_dir: VFS_Node,
+    mount_points: Vec<VFS_Node>,
     file_nodes: Vec<VFS_Node>,
 }
 
+/// Creates a directory node on which another filesystem is mounted
+fn mount_point(name: &str, mounted: *mut VFS_Node) -> VFS_Node {
+    VFS_Node {
+        name: name.to_string(),
+        kind: Type::Mountpoint,
+        inode: 0,
+        size: 0,
+        links: 1,
+        uid: 0,
+        gid: 0,
+        permissions: Type::Dir.default_permissions(),
+        read: None,
+        write: None,
+        readdir: None,
+        finddir: None,
+        create: None,
+        truncate: None,
+        unlink: None,
+        readlink: None,
+        symlink: None,
+        link: None,
+        mount_point: Some(mounted),
+        open: 0,
+        release: None,
+        seek: None,
+    }
+}
+
 pub fn initialize_initrd(fs_location: u64, size: usize) -> *const VFS_Node {
     let address = fs_location + KERNEL_BASE;
     let location = unsafe { slice::from_raw_parts(address as *const u8, size) };
@@ -65,26 +95,56 @@ pub fn initialize_initrd(fs_location: u64, size: usize) -> *const VFS_Node {
         kind: Type::Dir,
         inode: 0,
         size: 0,
+        links: 1,
+        uid: 0,
+        gid: 0,
+        permissions: Type::Dir.default_permissions(),
         read: None,
         write: None,
         readdir: Some(readdir),
         finddir: Some(finddir),
+        create: None,
+        truncate: None,
+        unlink: None,
+        readlink: None,
+        symlink: None,
+        link: None,
         mount_point: None,
+        open: 0,
+        release: None,
+        seek: None,
     };
 
-    let dev_fs_root = initialize_devfs();
-
-    let dev_dir = VFS_Node {
-        name: "dev".to_string(),
-        kind: Type::Mountpoint,
-        inode: 0,
-        size: 0,
-        read: None,
-        write: None,
-        readdir: None,
-        finddir: None,
-        mount_point: Some(dev_fs_root),
-    };
+    let mount_points = vec![
+        mount_point("dev", initialize_devfs()),
+        mount_point("tmp", initialize_tmpfs()),
+        mount_point("proc", initialize_procfs()),
+        // Empty directory for mounting disks at runtime
+        VFS_Node {
+            name: "mnt".to_string(),
+            kind: Type::Dir,
+            inode: 0,
+            size: 0,
+            links: 1,
+            uid: 0,
+            gid: 0,
+            permissions: Type::Dir.default_permissions(),
+            read: None,
+            write: None,
+            readdir: Some(|_| Some(Vec::new())),
+            finddir: None,
+            create: None,
+            truncate: None,
+            unlink: None,
+            readlink: None,
+            symlink: None,
+            link: None,
+            mount_point: None,
+            open: 0,
+            release: None,
+            seek: None,
+        },
+    ];
 
     let mut files = Vec::new();
     let mut file_nodes = Vec::new();
@@ -98,11 +158,24 @@ pub fn initialize_initrd(fs_location: u64, size: usize) -> *const VFS_Node {
             kind: Type::File,
             inode: i as usize,
             size: file_header.size,
+            links: 1,
+            uid: 0,
+            gid: 0,
+            permissions: Type::File.default_permissions(),
             read: Some(initrd_read),
             write: None,
             readdir: None,
             finddir: None,
+            create: None,
+            truncate: None,
+            unlink: None,
+            readlink: None,
+            symlink: None,
+            link: None,
             mount_point: None,
+            open: 0,
+            release: None,
+            seek: None,
         };
         files.push(file_header);
         file_nodes.push(file_node);
@@ -114,15 +187,23 @@ pub fn initialize_initrd(fs_location: u64, size: usize) -> *const VFS_Node {
         header,
         files,
         root,
-        dev_dir,
+        mount_points,
         file_nodes,
     };
 
-    unsafe {
-        INIT_RD_FS = Some(initrd_struct);
-    }
+    INIT_RD_FS.init(initrd_struct);
 
-    unsafe { &INIT_RD_FS.as_ref().unwrap().root as *const VFS_Node }
+    &INIT_RD_FS.get().root as *const VFS_Node
+}
+
+/// Returns the root of the filesystem mounted on the initrd directory `name`
+pub fn mounted(name: &str) -> Option<*mut VFS_Node> {
+    let fs = INIT_RD_FS.try_get()?;
+
+    fs.mount_points
+        .iter()
+        .find(|node| node.name == name)
+        .and_then(|node| node.mount_point)
 }
 
 pub fn initrd_read(
@@ -132,7 +213,7 @@ pub fn initrd_read(
     buffer: &mut [u8],
 ) -> Option<usize> {
     if node.kind == Type::File {
-        let fs = unsafe { &INIT_RD_FS.as_ref().unwrap() };
+        let fs = INIT_RD_FS.get();
         let header = &fs.files[node.inode];
         let location = unsafe { slice::from_raw_parts(fs.address, fs.size) };
 
@@ -154,17 +235,13 @@ pub fn readdir(node: &VFS_Node) -> Option<Vec<DirEnt>> {
         return None;
     }
     let mut ret = Vec::new();
-    let fs = unsafe { &INIT_RD_FS.as_ref().unwrap() };
+    let fs = INIT_RD_FS.get();
 
-    ret.push(DirEnt {
-        name: "dev".to_string(),
-        inode: 0,
-    });
-
-    for node in &fs.file_nodes {
+    for node in fs.mount_points.iter().chain(&fs.file_nodes) {
         ret.push(DirEnt {
             name: node.name.clone(),
             inode: node.inode,
+            kind: node.kind.clone(),
         });
     }
 
@@ -175,14 +252,11 @@ pub fn finddir(node: &VFS_Node, name: &str) -> Option<*mut VFS_Node> {
     if node.kind != Type::Dir {
         return None;
     }
-    let fs = unsafe { &mut INIT_RD_FS.as_mut().unwrap() };
-
-    if name == "dev" {
-        return Some(&mut fs.dev_dir);
-    }
+    let fs = unsafe { INIT_RD_FS.get_mut() };
 
-    fs.file_nodes
+    fs.mount_points
         .iter_mut()
+        .chain(fs.file_nodes.iter_mut())
         .find(|node| node.name == name)
         .map(|node| node as *mut VFS_Node)
 }
@@ -1,8 +1,12 @@
-use crate::sync::SpinMutex;
+use crate::{
+    arch::{interrupts, io::inb},
+    sync::IrqSpinMutex,
+};
 
 use super::chardev::CharDev;
 
-pub static KEYS: SpinMutex<KeyboardState> = SpinMutex::new(KeyboardState::new());
+/// Updated by the keyboard interrupt handler
+pub static KEYS: IrqSpinMutex<KeyboardState> = IrqSpinMutex::new(KeyboardState::new());
 
 const NO_KEYS: usize = 6;
 
@@ -21,7 +25,7 @@ impl KeyboardState {
 
     /// Fills the given buffer with the currently pressed keys
     pub fn get(buf: &mut [u8]) {
-        crate::arch::interrupts::free(|| buf.copy_from_slice(&KEYS.lock().pressed_keys));
+        buf.copy_from_slice(&KEYS.lock().pressed_keys);
     }
 
     /// Update the currently pressed keys
@@ -59,6 +63,22 @@ impl KeyboardState {
     }
 }
 
+const DATA_PORT: u16 = 0x60;
+const IRQ: u8 = 1;
+
+/// Reads the scancode of the key which was pressed or released
+fn handle_irq(_irq: u8) {
+    unsafe {
+        let scancode = inb(DATA_PORT);
+        KeyboardState::update(scancode);
+    }
+}
+
+/// Handles the interrupts of the PS/2 keyboard
+pub fn init() {
+    interrupts::register_irq(IRQ, handle_irq);
+}
+
 pub struct Keyboard;
 
 impl CharDev for Keyboard {
@@ -71,7 +91,7 @@ impl CharDev for Keyboard {
         Some(buf.iter().filter(|k| **k != 0).count())
     }
 
-    fn write(&mut self, _size: usize, _buf: &[u8]) -> Option<usize> {
+    fn write(&self, _size: usize, _buf: &[u8]) -> Option<usize> {
         None
     }
 }
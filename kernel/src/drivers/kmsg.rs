@@ -0,0 +1,33 @@
+use crate::logging;
+
+use super::chardev::CharDev;
+
+/// The kernel log. File offsets are positions in the log since boot, a reader which
+/// fell behind the ring buffer continues at the oldest message still in it.
+/// Written lines are added to the log
+pub struct Kmsg;
+
+impl CharDev for Kmsg {
+    fn read(&self, size: usize, buf: &mut [u8]) -> Option<usize> {
+        self.read_at(0, size, buf)
+    }
+
+    fn read_at(&self, offset: usize, size: usize, buf: &mut [u8]) -> Option<usize> {
+        let size = size.min(buf.len());
+        logging::read(offset, &mut buf[..size])
+    }
+
+    fn seek(&self, offset: usize) -> usize {
+        logging::seek(offset)
+    }
+
+    fn write(&self, size: usize, buf: &[u8]) -> Option<usize> {
+        let size = size.min(buf.len());
+        let message = core::str::from_utf8(&buf[..size]).ok()?;
+
+        for line in message.lines().filter(|line| !line.is_empty()) {
+            logging::log(logging::Level::Info, "user", format_args!("{}", line));
+        }
+        Some(size)
+    }
+}
@@ -0,0 +1,42 @@
+use super::chardev::CharDev;
+
+/// Discards writes, reads return end of file
+pub struct Null;
+
+impl CharDev for Null {
+    fn read(&self, _size: usize, _buf: &mut [u8]) -> Option<usize> {
+        Some(0)
+    }
+
+    fn write(&self, size: usize, _buf: &[u8]) -> Option<usize> {
+        Some(size)
+    }
+}
+
+/// Reads return zeroes, writes are discarded
+pub struct Zero;
+
+impl CharDev for Zero {
+    fn read(&self, size: usize, buf: &mut [u8]) -> Option<usize> {
+        let size = size.min(buf.len());
+        buf[..size].fill(0);
+        Some(size)
+    }
+
+    fn write(&self, size: usize, _buf: &[u8]) -> Option<usize> {
+        Some(size)
+    }
+}
+
+/// Reads return zeroes, writes always fail as if the device was full
+pub struct Full;
+
+impl CharDev for Full {
+    fn read(&self, size: usize, buf: &mut [u8]) -> Option<usize> {
+        Zero.read(size, buf)
+    }
+
+    fn write(&self, _size: usize, _buf: &[u8]) -> Option<usize> {
+        None
+    }
+}
@@ -1,6 +1,21 @@
+pub mod ata;
+pub mod blockdev;
+pub mod buffer_cache;
 pub mod chardev;
+pub mod console;
 pub mod devfs;
+pub mod ext2;
+pub mod fat;
+pub mod font;
 pub mod framebuffer;
 pub mod initrd;
 pub mod keyboard;
+pub mod kmsg;
+pub mod mem;
+pub mod partition;
+pub mod pci;
+pub mod procfs;
+pub mod random;
 pub mod serial;
+pub mod tmpfs;
+pub mod virtio_blk;
@@ -0,0 +1,197 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::blockdev::{BlockDevice, Partition};
+
+const MBR_SIGNATURE: u16 = 0xAA55;
+const MBR_TABLE_OFFSET: usize = 446;
+const MBR_ENTRY_SIZE: usize = 16;
+
+// MBR partition types with special meaning
+const TYPE_EMPTY: u8 = 0x00;
+const TYPE_EXTENDED_CHS: u8 = 0x05;
+const TYPE_EXTENDED_LBA: u8 = 0x0F;
+const TYPE_GPT_PROTECTIVE: u8 = 0xEE;
+
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+/// Upper bound on logical partitions, protects against EBR loops
+const MAX_LOGICAL: usize = 64;
+
+/// Location of a partition on its disk, in sectors
+#[derive(Debug, Clone, Copy)]
+pub struct PartitionEntry {
+    /// Number in the device name, e.g. 1 for hda1
+    pub number: usize,
+    pub start: u64,
+    pub sectors: u64,
+}
+
+fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(buf[offset..offset + 2].try_into().unwrap())
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(buf: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(buf[offset..offset + 8].try_into().unwrap())
+}
+
+/// CRC-32 (IEEE 802.3) used by the GPT header and partition array
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Reads the partition table of a disk, GPT if the MBR has a protective entry
+pub fn parse(disk: &mut Partition) -> Vec<PartitionEntry> {
+    let mut mbr = vec![0; disk.sector_size()];
+    if disk.read_sectors(0, &mut mbr).is_none() || read_u16(&mbr, 510) != MBR_SIGNATURE {
+        return Vec::new();
+    }
+
+    let protective =
+        (0..4).any(|i| mbr[MBR_TABLE_OFFSET + i * MBR_ENTRY_SIZE + 4] == TYPE_GPT_PROTECTIVE);
+    if protective {
+        if let Some(entries) = parse_gpt(disk) {
+            return entries;
+        }
+    }
+    parse_mbr(disk, &mbr)
+}
+
+/// Type, first sector and length of an MBR entry
+fn mbr_entry(sector: &[u8], index: usize) -> (u8, u64, u64) {
+    let entry = MBR_TABLE_OFFSET + index * MBR_ENTRY_SIZE;
+    (
+        sector[entry + 4],
+        read_u32(sector, entry + 8) as u64,
+        read_u32(sector, entry + 12) as u64,
+    )
+}
+
+fn parse_mbr(disk: &mut Partition, mbr: &[u8]) -> Vec<PartitionEntry> {
+    let mut entries = Vec::new();
+    let mut extended = None;
+
+    for i in 0..4 {
+        let (kind, start, sectors) = mbr_entry(mbr, i);
+        match kind {
+            TYPE_EMPTY => {}
+            TYPE_EXTENDED_CHS | TYPE_EXTENDED_LBA => extended = Some(start),
+            _ => entries.push(PartitionEntry {
+                number: i + 1,
+                start,
+                sectors,
+            }),
+        }
+    }
+
+    // Logical partitions are a linked list of EBRs, numbered from 5
+    if let Some(extended_start) = extended {
+        let mut ebr = vec![0; disk.sector_size()];
+        let mut ebr_lba = extended_start;
+        for number in 5..5 + MAX_LOGICAL {
+            if disk.read_sectors(ebr_lba, &mut ebr).is_none()
+                || read_u16(&ebr, 510) != MBR_SIGNATURE
+            {
+                break;
+            }
+
+            let (kind, start, sectors) = mbr_entry(&ebr, 0);
+            if kind != TYPE_EMPTY {
+                entries.push(PartitionEntry {
+                    number,
+                    start: ebr_lba + start,
+                    sectors,
+                });
+            }
+
+            // The next EBR is relative to the start of the extended partition
+            let (next_kind, next, _) = mbr_entry(&ebr, 1);
+            if next_kind == TYPE_EMPTY || next == 0 {
+                break;
+            }
+            ebr_lba = extended_start + next;
+        }
+    }
+
+    entries
+        .into_iter()
+        .filter(|e| e.sectors != 0 && fits(e, disk))
+        .collect()
+}
+
+/// Whether an entry lies inside the disk, the values of a corrupt table could overflow
+fn fits(entry: &PartitionEntry, disk: &Partition) -> bool {
+    entry
+        .start
+        .checked_add(entry.sectors)
+        .is_some_and(|end| end <= disk.sector_count())
+}
+
+/// Returns None if the GPT header or partition array is not valid
+fn parse_gpt(disk: &mut Partition) -> Option<Vec<PartitionEntry>> {
+    let sector_size = disk.sector_size();
+    let mut header = vec![0; sector_size];
+    disk.read_sectors(1, &mut header)?;
+    if &header[..8] != GPT_SIGNATURE {
+        return None;
+    }
+
+    // The header checksum is computed with its own field zeroed
+    let header_size = read_u32(&header, 12) as usize;
+    if !(92..=sector_size).contains(&header_size) {
+        return None;
+    }
+    let header_crc = read_u32(&header, 16);
+    header[16..20].fill(0);
+    if crc32(&header[..header_size]) != header_crc {
+        return None;
+    }
+
+    let array_lba = read_u64(&header, 72);
+    let count = read_u32(&header, 80) as usize;
+    let entry_size = read_u32(&header, 84) as usize;
+    // The specification only allows powers of two from 128, real tables use 128
+    if !entry_size.is_power_of_two() || !(128..=512).contains(&entry_size) || count > 1024 {
+        return None;
+    }
+
+    let array_size = count * entry_size;
+    let mut array = vec![0; array_size.div_ceil(sector_size) * sector_size];
+    disk.read_sectors(array_lba, &mut array)?;
+    if crc32(&array[..array_size]) != read_u32(&header, 88) {
+        return None;
+    }
+
+    let entries = array[..array_size]
+        .chunks_exact(entry_size)
+        .enumerate()
+        // An all zero type GUID marks an unused entry
+        .filter(|(_, entry)| entry[..16].iter().any(|&b| b != 0))
+        .map(|(i, entry)| {
+            let first = read_u64(entry, 32);
+            let last = read_u64(entry, 40);
+            PartitionEntry {
+                number: i + 1,
+                start: first,
+                sectors: last.saturating_add(1).saturating_sub(first),
+            }
+        })
+        .filter(|e| e.sectors != 0 && fits(e, disk))
+        .collect();
+
+    Some(entries)
+}
@@ -0,0 +1,724 @@
+use alloc::{format, string::String, vec::Vec};
+use core::{
+    ptr::{read_volatile, write_volatile},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::{
+    arch::{
+        acpi,
+        addressing::PhysAddr,
+        io::{inl, outl},
+        msi,
+        paging::PageTableFlags::{NO_CACHE, WRITE_THROUGH},
+    },
+    logging,
+    mm::ALLOCATOR,
+    sync::{IrqSpinMutex, SpinMutex},
+};
+
+const CONFIG_ADDRESS: u16 = 0xCF8;
+const CONFIG_DATA: u16 = 0xCFC;
+
+// Configuration space offsets
+const VENDOR_ID: u8 = 0x00;
+const DEVICE_ID: u8 = 0x02;
+const COMMAND: u8 = 0x04;
+const STATUS: u8 = 0x06;
+const REVISION: u8 = 0x08;
+const HEADER_TYPE: u8 = 0x0E;
+const BAR0: u8 = 0x10;
+/// Bus behind a PCI-to-PCI bridge (header type 1)
+const SECONDARY_BUS: u8 = 0x19;
+const CAPABILITIES_POINTER: u8 = 0x34;
+const INTERRUPT_LINE: u8 = 0x3C;
+
+// Command register bits
+const COMMAND_IO_SPACE: u16 = 1;
+const COMMAND_MEMORY_SPACE: u16 = 1 << 1;
+const COMMAND_BUS_MASTER: u16 = 1 << 2;
+/// Stops the device from asserting its legacy interrupt line
+const COMMAND_INTX_DISABLE: u16 = 1 << 10;
+
+const STATUS_CAPABILITIES: u16 = 1 << 4;
+
+// Capability IDs
+pub const CAPABILITY_MSI: u8 = 0x05;
+pub const CAPABILITY_MSIX: u8 = 0x11;
+
+// MSI capability, offsets from its start
+const MSI_CONTROL: u8 = 0x02;
+const MSI_ADDRESS: u8 = 0x04;
+const MSI_CONTROL_ENABLE: u16 = 1 << 0;
+/// Number of vectors enabled, as a power of two
+const MSI_CONTROL_MULTIPLE_ENABLE: u16 = 0b111 << 4;
+const MSI_CONTROL_64BIT: u16 = 1 << 7;
+
+// MSI-X capability, offsets from its start
+const MSIX_CONTROL: u8 = 0x02;
+const MSIX_TABLE: u8 = 0x04;
+const MSIX_CONTROL_TABLE_SIZE: u16 = 0x7FF;
+const MSIX_CONTROL_FUNCTION_MASK: u16 = 1 << 14;
+const MSIX_CONTROL_ENABLE: u16 = 1 << 15;
+/// The table is in the BAR given by the low bits of its offset
+const MSIX_TABLE_BAR: u32 = 0b111;
+/// Each table entry has the message address, data and vector control
+const MSIX_ENTRY_SIZE: u64 = 16;
+const MSIX_ENTRY_ADDRESS_LOW: u64 = 0x0;
+const MSIX_ENTRY_ADDRESS_HIGH: u64 = 0x4;
+const MSIX_ENTRY_DATA: u64 = 0x8;
+const MSIX_ENTRY_CONTROL: u64 = 0xC;
+const MSIX_ENTRY_MASKED: u32 = 1 << 0;
+
+// Header type register
+const HEADER_MULTI_FUNCTION: u8 = 0x80;
+const HEADER_LAYOUT: u8 = 0x7F;
+const HEADER_BRIDGE: u8 = 0x01;
+
+// Base address register bits
+const BAR_IO: u32 = 1;
+const BAR_64BIT: u32 = 0b10 << 1;
+const BAR_PREFETCHABLE: u32 = 1 << 3;
+
+const CLASS_BRIDGE: u8 = 0x06;
+const SUBCLASS_PCI_BRIDGE: u8 = 0x04;
+
+/// Virtual address of the memory mapped configuration space (ECAM) of each bus of
+/// segment 0, from the MCFG. Set by `init`, 0 for the buses without one
+static ECAM_BUSES: [AtomicU64; 256] = [const { AtomicU64::new(0) }; 256];
+/// The address and data ports are used in two steps
+static CONFIG_PORTS: IrqSpinMutex<()> = IrqSpinMutex::new(());
+
+/// A function found by `init` and the driver which took it
+type Binding = (PciDevice, Option<&'static PciDriver>);
+
+static DEVICES: SpinMutex<Vec<Binding>> = SpinMutex::new(Vec::new());
+static DRIVERS: SpinMutex<Vec<&'static PciDriver>> = SpinMutex::new(Vec::new());
+
+/// Virtual address of a register in the memory mapped configuration space, if the bus has one.
+/// Each bus takes 1 MiB, 4 KiB per function
+fn ecam_address(bus: u8, device: u8, function: u8, offset: u8) -> Option<u64> {
+    let function_offset =
+        ((device as u64 & 0x1F) << 15) | ((function as u64 & 0x7) << 12) | (offset as u64 & 0xFC);
+
+    match ECAM_BUSES[bus as usize].load(Ordering::Acquire) {
+        0 => None,
+        base => Some(base + function_offset),
+    }
+}
+
+/// Reads a 32-bit value from the configuration space, memory mapped if the MCFG
+/// describes the bus, otherwise using the 0xCF8/0xCFC ports.
+/// `offset` must be 4-byte aligned
+pub fn config_read_u32(bus: u8, device: u8, function: u8, offset: u8) -> u32 {
+    if let Some(address) = ecam_address(bus, device, function, offset) {
+        return unsafe { read_volatile(address as *const u32) };
+    }
+
+    let _ports = CONFIG_PORTS.lock();
+    unsafe {
+        outl(
+            CONFIG_ADDRESS,
+            config_address(bus, device, function, offset),
+        );
+        inl(CONFIG_DATA)
+    }
+}
+
+/// Writes a 32-bit value to the configuration space.
+/// `offset` must be 4-byte aligned
+pub fn config_write_u32(bus: u8, device: u8, function: u8, offset: u8, value: u32) {
+    if let Some(address) = ecam_address(bus, device, function, offset) {
+        return unsafe { write_volatile(address as *mut u32, value) };
+    }
+
+    let _ports = CONFIG_PORTS.lock();
+    unsafe {
+        outl(
+            CONFIG_ADDRESS,
+            config_address(bus, device, function, offset),
+        );
+        outl(CONFIG_DATA, value);
+    }
+}
+
+fn config_address(bus: u8, device: u8, function: u8, offset: u8) -> u32 {
+    (1 << 31)
+        | ((bus as u32) << 16)
+        | ((device as u32 & 0x1F) << 11)
+        | ((function as u32 & 0x7) << 8)
+        | (offset as u32 & 0xFC)
+}
+
+/// A decoded Base Address Register
+#[derive(Debug, Clone, Copy)]
+pub enum Bar {
+    Io {
+        port: u16,
+        size: u32,
+    },
+    Memory {
+        address: u64,
+        size: u64,
+        prefetchable: bool,
+    },
+}
+
+/// A function of a device on the PCI bus
+#[derive(Debug, Clone, Copy)]
+pub struct PciDevice {
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub prog_if: u8,
+    pub revision: u8,
+    pub header_type: u8,
+}
+
+impl PciDevice {
+    pub fn read_u32(&self, offset: u8) -> u32 {
+        config_read_u32(self.bus, self.device, self.function, offset)
+    }
+
+    pub fn write_u32(&self, offset: u8, value: u32) {
+        config_write_u32(self.bus, self.device, self.function, offset, value)
+    }
+
+    pub fn read_u16(&self, offset: u8) -> u16 {
+        (self.read_u32(offset & !3) >> ((offset & 2) * 8)) as u16
+    }
+
+    pub fn read_u8(&self, offset: u8) -> u8 {
+        (self.read_u32(offset & !3) >> ((offset & 3) * 8)) as u8
+    }
+
+    pub fn write_u16(&self, offset: u8, value: u16) {
+        let shift = (offset & 2) * 8;
+        let old = self.read_u32(offset & !3) & !(0xFFFF << shift);
+        self.write_u32(offset & !3, old | ((value as u32) << shift));
+    }
+
+    /// Raw value of a Base Address Register
+    pub fn bar(&self, index: u8) -> u32 {
+        self.read_u32(BAR0 + index * 4)
+    }
+
+    /// Number of BARs of the header, bridges only have two
+    fn bar_count(&self) -> u8 {
+        match self.header_type & HEADER_LAYOUT {
+            0 => 6,
+            HEADER_BRIDGE => 2,
+            _ => 0,
+        }
+    }
+
+    /// Base port of an I/O space BAR, None if the BAR maps memory
+    pub fn io_bar(&self, index: u8) -> Option<u16> {
+        let bar = self.bar(index);
+        if bar & BAR_IO == 1 {
+            Some((bar & !3) as u16)
+        } else {
+            None
+        }
+    }
+
+    /// Decodes a BAR and finds the size of its region by writing all ones and reading back
+    /// the writable bits. Decoding is disabled meanwhile. None if the BAR is not implemented
+    /// or is the upper half of a 64-bit BAR
+    pub fn decode_bar(&self, index: u8) -> Option<Bar> {
+        if index >= self.bar_count() {
+            return None;
+        }
+        let offset = BAR0 + index * 4;
+        let low = self.read_u32(offset);
+        let is_64 = low & BAR_IO == 0 && low & 0b110 == BAR_64BIT;
+        if is_64 && index + 1 >= self.bar_count() {
+            return None;
+        }
+
+        let command = self.read_u16(COMMAND);
+        self.write_u16(
+            COMMAND,
+            command & !(COMMAND_IO_SPACE | COMMAND_MEMORY_SPACE),
+        );
+        let size_of = |offset: u8, value: u32| {
+            self.write_u32(offset, u32::MAX);
+            let mask = self.read_u32(offset);
+            self.write_u32(offset, value);
+            mask
+        };
+        let low_mask = size_of(offset, low);
+        let high = if is_64 { self.read_u32(offset + 4) } else { 0 };
+        let high_mask = if is_64 {
+            size_of(offset + 4, high)
+        } else {
+            u32::MAX
+        };
+        self.write_u16(COMMAND, command);
+
+        if low & BAR_IO != 0 {
+            let mask = low_mask & !0b11 & 0xFFFF;
+            if mask == 0 {
+                return None;
+            }
+            return Some(Bar::Io {
+                port: (low & !0b11) as u16,
+                size: (!mask & 0xFFFF) + 1,
+            });
+        }
+
+        let mask = ((high_mask as u64) << 32) | (low_mask & !0xF) as u64;
+        if low_mask & !0xF == 0 {
+            return None;
+        }
+        Some(Bar::Memory {
+            address: ((high as u64) << 32) | (low & !0xF) as u64,
+            size: !mask + 1,
+            prefetchable: low & BAR_PREFETCHABLE != 0,
+        })
+    }
+
+    /// The implemented BARs with their indexes
+    pub fn bars(&self) -> Vec<(u8, Bar)> {
+        let mut bars = Vec::new();
+        let mut index = 0;
+        while index < self.bar_count() {
+            let bar = self.decode_bar(index);
+            let is_64 = self.bar(index) & (BAR_IO | 0b110) == BAR_64BIT;
+            if let Some(bar) = bar {
+                bars.push((index, bar));
+            }
+            index += if is_64 { 2 } else { 1 };
+        }
+        bars
+    }
+
+    /// Maps the memory region of a BAR uncached and returns its virtual address,
+    /// None if the BAR is not a memory BAR
+    pub fn map_bar(&self, index: u8) -> Option<u64> {
+        let Bar::Memory { address, size, .. } = self.decode_bar(index)? else {
+            return None;
+        };
+        let virtual_address = ALLOCATOR
+            .lock()
+            .page_allocator
+            .as_mut()
+            .unwrap()
+            .map_physical_range(PhysAddr::new(address), size, NO_CACHE | WRITE_THROUGH)
+            .as_u64();
+        Some(virtual_address)
+    }
+
+    /// Legacy PIC line the device interrupt is routed to
+    pub fn interrupt_line(&self) -> u8 {
+        self.read_u8(INTERRUPT_LINE)
+    }
+
+    /// Enables I/O and memory decoding and lets the device do DMA
+    pub fn enable_bus_mastering(&self) {
+        let command = self.read_u16(COMMAND);
+        self.write_u16(
+            COMMAND,
+            command | COMMAND_IO_SPACE | COMMAND_MEMORY_SPACE | COMMAND_BUS_MASTER,
+        );
+    }
+
+    /// IDs and offsets of the capabilities in the configuration space
+    pub fn capabilities(&self) -> Vec<(u8, u8)> {
+        let mut capabilities = Vec::new();
+        if self.read_u16(STATUS) & STATUS_CAPABILITIES == 0 {
+            return capabilities;
+        }
+
+        // The list can not be longer than the configuration space, a loop would be
+        let mut offset = self.read_u8(CAPABILITIES_POINTER) & !3;
+        while offset != 0 && capabilities.len() < 48 {
+            capabilities.push((self.read_u8(offset), offset));
+            offset = self.read_u8(offset + 1) & !3;
+        }
+        capabilities
+    }
+
+    /// Offset of a capability, None if the device does not have it
+    pub fn find_capability(&self, id: u8) -> Option<u8> {
+        self.capabilities()
+            .into_iter()
+            .find(|(capability, _)| *capability == id)
+            .map(|(_, offset)| offset)
+    }
+
+    /// Switches the device to a single message signaled interrupt, `handler` is called on each
+    /// of them. The legacy interrupt line is disabled. Returns the vector, None if the device
+    /// has no MSI capability or no vector is free
+    pub fn enable_msi<F>(&self, name: &'static str, handler: F) -> Option<u8>
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        let capability = self.find_capability(CAPABILITY_MSI)?;
+        let vector = msi::allocate_vector(name, handler)?;
+
+        let control = self.read_u16(capability + MSI_CONTROL);
+        let address = msi::message_address();
+        self.write_u32(capability + MSI_ADDRESS, address as u32);
+        let data_offset = if control & MSI_CONTROL_64BIT != 0 {
+            self.write_u32(capability + MSI_ADDRESS + 4, (address >> 32) as u32);
+            capability + 0x0C
+        } else {
+            capability + 0x08
+        };
+        self.write_u16(data_offset, msi::message_data(vector) as u16);
+
+        self.write_u16(
+            capability + MSI_CONTROL,
+            control & !MSI_CONTROL_MULTIPLE_ENABLE | MSI_CONTROL_ENABLE,
+        );
+        self.disable_intx();
+        Some(vector)
+    }
+
+    /// Number of entries of the MSI-X table, None without the MSI-X capability
+    pub fn msix_count(&self) -> Option<u16> {
+        let capability = self.find_capability(CAPABILITY_MSIX)?;
+        Some((self.read_u16(capability + MSIX_CONTROL) & MSIX_CONTROL_TABLE_SIZE) + 1)
+    }
+
+    /// Routes an entry of the MSI-X table to a new vector and enables MSI-X, `handler` is called
+    /// on each interrupt of the entry. The other entries stay masked until they are set up.
+    /// Returns the vector, None if the device has no such entry or no vector is free
+    pub fn enable_msix<F>(&self, entry: u16, name: &'static str, handler: F) -> Option<u8>
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        let capability = self.find_capability(CAPABILITY_MSIX)?;
+        let control = self.read_u16(capability + MSIX_CONTROL);
+        if entry > control & MSIX_CONTROL_TABLE_SIZE {
+            return None;
+        }
+        let table = self.read_u32(capability + MSIX_TABLE);
+        let base = self.map_bar((table & MSIX_TABLE_BAR) as u8)? + (table & !MSIX_TABLE_BAR) as u64;
+        let vector = msi::allocate_vector(name, handler)?;
+
+        // The function mask holds every message while the table is written
+        if control & MSIX_CONTROL_ENABLE == 0 {
+            self.write_u16(
+                capability + MSIX_CONTROL,
+                control | MSIX_CONTROL_ENABLE | MSIX_CONTROL_FUNCTION_MASK,
+            );
+            for index in 0..=(control & MSIX_CONTROL_TABLE_SIZE) as u64 {
+                let register = base + index * MSIX_ENTRY_SIZE + MSIX_ENTRY_CONTROL;
+                unsafe { write_volatile(register as *mut u32, MSIX_ENTRY_MASKED) };
+            }
+        }
+
+        let address = msi::message_address();
+        let entry = base + entry as u64 * MSIX_ENTRY_SIZE;
+        unsafe {
+            write_volatile((entry + MSIX_ENTRY_ADDRESS_LOW) as *mut u32, address as u32);
+            write_volatile(
+                (entry + MSIX_ENTRY_ADDRESS_HIGH) as *mut u32,
+                (address >> 32) as u32,
+            );
+            write_volatile(
+                (entry + MSIX_ENTRY_DATA) as *mut u32,
+                msi::message_data(vector),
+            );
+            write_volatile((entry + MSIX_ENTRY_CONTROL) as *mut u32, 0);
+        }
+
+        let control = self.read_u16(capability + MSIX_CONTROL);
+        self.write_u16(
+            capability + MSIX_CONTROL,
+            (control | MSIX_CONTROL_ENABLE) & !MSIX_CONTROL_FUNCTION_MASK,
+        );
+        self.disable_intx();
+        Some(vector)
+    }
+
+    fn disable_intx(&self) {
+        let command = self.read_u16(COMMAND);
+        self.write_u16(COMMAND, command | COMMAND_INTX_DISABLE);
+    }
+
+    fn is_pci_bridge(&self) -> bool {
+        self.class == CLASS_BRIDGE
+            && self.subclass == SUBCLASS_PCI_BRIDGE
+            && self.header_type & HEADER_LAYOUT == HEADER_BRIDGE
+    }
+
+    /// One line description, like `lspci`
+    pub fn describe(&self) -> String {
+        format!(
+            "{:02x}:{:02x}.{} {:04x}:{:04x} {:02x}{:02x}{:02x} {}",
+            self.bus,
+            self.device,
+            self.function,
+            self.vendor_id,
+            self.device_id,
+            self.class,
+            self.subclass,
+            self.prog_if,
+            class_name(self.class, self.subclass)
+        )
+    }
+}
+
+/// Name of the common device classes
+fn class_name(class: u8, subclass: u8) -> &'static str {
+    match (class, subclass) {
+        (0x01, 0x01) => "IDE controller",
+        (0x01, 0x06) => "SATA controller",
+        (0x01, 0x08) => "NVMe controller",
+        (0x01, _) => "Storage controller",
+        (0x02, 0x00) => "Ethernet controller",
+        (0x02, _) => "Network controller",
+        (0x03, 0x00) => "VGA controller",
+        (0x03, _) => "Display controller",
+        (0x04, _) => "Multimedia controller",
+        (0x05, _) => "Memory controller",
+        (0x06, 0x00) => "Host bridge",
+        (0x06, 0x01) => "ISA bridge",
+        (0x06, 0x04) => "PCI bridge",
+        (0x06, _) => "Bridge",
+        (0x07, _) => "Communication controller",
+        (0x08, _) => "System peripheral",
+        (0x0C, 0x03) => "USB controller",
+        (0x0C, 0x05) => "SMBus",
+        (0x0C, _) => "Serial bus controller",
+        _ => "Unknown device",
+    }
+}
+
+/// Returns the device at the given address, if any
+fn probe(bus: u8, device: u8, function: u8) -> Option<PciDevice> {
+    let id = config_read_u32(bus, device, function, VENDOR_ID);
+    let vendor_id = id as u16;
+    if vendor_id == 0xFFFF {
+        return None;
+    }
+
+    let class = config_read_u32(bus, device, function, REVISION);
+    Some(PciDevice {
+        bus,
+        device,
+        function,
+        vendor_id,
+        device_id: (id >> (DEVICE_ID * 8)) as u16,
+        class: (class >> 24) as u8,
+        subclass: (class >> 16) as u8,
+        prog_if: (class >> 8) as u8,
+        revision: class as u8,
+        header_type: (config_read_u32(bus, device, function, HEADER_TYPE & !3) >> 16) as u8,
+    })
+}
+
+/// Adds the functions of a bus, and of the buses behind its bridges
+fn scan_bus(bus: u8, devices: &mut Vec<PciDevice>) {
+    for device in 0..32 {
+        let Some(first) = probe(bus, device, 0) else {
+            continue;
+        };
+        let functions = if first.header_type & HEADER_MULTI_FUNCTION != 0 {
+            8
+        } else {
+            1
+        };
+
+        for function in 0..functions {
+            let Some(found) = probe(bus, device, function) else {
+                continue;
+            };
+            devices.push(found);
+
+            // A secondary bus below ours would be a loop
+            let secondary = found.read_u8(SECONDARY_BUS);
+            if found.is_pci_bridge() && secondary > bus {
+                scan_bus(secondary, devices);
+            }
+        }
+    }
+}
+
+/// Finds the present devices, starting from the host bridges and following the
+/// PCI-to-PCI bridges
+pub fn scan() -> Vec<PciDevice> {
+    let mut devices = Vec::new();
+
+    match probe(0, 0, 0) {
+        // Each function of a multi-function host bridge is the host bridge of a bus
+        Some(host) if host.header_type & HEADER_MULTI_FUNCTION != 0 => {
+            for function in 0..8 {
+                if probe(0, 0, function).is_some() {
+                    scan_bus(function, &mut devices);
+                }
+            }
+        }
+        _ => scan_bus(0, &mut devices),
+    }
+
+    devices
+}
+
+/// Returns all devices with the given vendor and device IDs
+pub fn find_devices(vendor_id: u16, device_id: u16) -> Vec<PciDevice> {
+    scan()
+        .into_iter()
+        .filter(|d| d.vendor_id == vendor_id && d.device_id == device_id)
+        .collect()
+}
+
+/// Devices a driver handles. `ANY_ID` and `ANY_CLASS` match every value
+#[derive(Debug, Clone, Copy)]
+pub struct DeviceMatch {
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub class: u8,
+    pub subclass: u8,
+}
+
+pub const ANY_ID: u16 = 0xFFFF;
+pub const ANY_CLASS: u8 = 0xFF;
+
+impl DeviceMatch {
+    pub const fn device(vendor_id: u16, device_id: u16) -> DeviceMatch {
+        DeviceMatch {
+            vendor_id,
+            device_id,
+            class: ANY_CLASS,
+            subclass: ANY_CLASS,
+        }
+    }
+
+    pub const fn class(class: u8, subclass: u8) -> DeviceMatch {
+        DeviceMatch {
+            vendor_id: ANY_ID,
+            device_id: ANY_ID,
+            class,
+            subclass,
+        }
+    }
+
+    fn matches(&self, device: &PciDevice) -> bool {
+        (self.vendor_id == ANY_ID || self.vendor_id == device.vendor_id)
+            && (self.device_id == ANY_ID || self.device_id == device.device_id)
+            && (self.class == ANY_CLASS || self.class == device.class)
+            && (self.subclass == ANY_CLASS || self.subclass == device.subclass)
+    }
+}
+
+/// A driver of PCI devices. `probe` is called for each matching device no other
+/// driver took, and returns whether it handles it
+pub struct PciDriver {
+    pub name: &'static str,
+    pub matches: &'static [DeviceMatch],
+    pub probe: fn(&PciDevice) -> bool,
+}
+
+/// Offers the devices without a driver to a driver
+fn bind(driver: &'static PciDriver) {
+    let candidates: Vec<PciDevice> = DEVICES
+        .lock()
+        .iter()
+        .filter(|(device, bound)| {
+            bound.is_none() && driver.matches.iter().any(|m| m.matches(device))
+        })
+        .map(|(device, _)| *device)
+        .collect();
+
+    // The lock is not held while probing, drivers can look at the other devices
+    for device in candidates {
+        if (driver.probe)(&device) {
+            info!(
+                "{:02x}:{:02x}.{} bound to {}",
+                device.bus, device.device, device.function, driver.name
+            );
+            let mut devices = DEVICES.lock();
+            if let Some(entry) = devices.iter_mut().find(|(d, _)| {
+                (d.bus, d.device, d.function) == (device.bus, device.device, device.function)
+            }) {
+                entry.1 = Some(driver);
+            }
+        }
+    }
+}
+
+/// Adds a driver and probes the matching devices already found
+pub fn register_driver(driver: &'static PciDriver) {
+    DRIVERS.lock().push(driver);
+    bind(driver);
+}
+
+/// Uses the memory mapped configuration space when the ACPI MCFG has one, scans the buses,
+/// logs the devices and offers them to the registered drivers. Needs the kernel page allocator
+pub fn init() {
+    // Mapped once here, so config space accesses never take the allocator lock
+    for entry in acpi::mcfg().unwrap_or_default() {
+        if entry.segment != 0 || entry.start_bus > entry.end_bus {
+            continue;
+        }
+        info!(
+            "PCI ECAM at {:#x} for buses {}-{}",
+            entry.base_address, entry.start_bus, entry.end_bus
+        );
+        let first = entry.base_address + ((entry.start_bus as u64) << 20);
+        let size = (entry.end_bus as u64 - entry.start_bus as u64 + 1) << 20;
+        let base = ALLOCATOR
+            .lock()
+            .page_allocator
+            .as_mut()
+            .unwrap()
+            .map_physical_range(PhysAddr::new(first), size, NO_CACHE | WRITE_THROUGH)
+            .as_u64();
+        for bus in entry.start_bus..=entry.end_bus {
+            let offset = ((bus - entry.start_bus) as u64) << 20;
+            ECAM_BUSES[bus as usize].store(base + offset, Ordering::Release);
+        }
+    }
+
+    let devices = scan();
+    for device in &devices {
+        info!("PCI {}", device.describe());
+        for (index, bar) in device.bars() {
+            match bar {
+                Bar::Io { port, size } => {
+                    debug!("  BAR{}: I/O ports {:#x} ({} bytes)", index, port, size)
+                }
+                Bar::Memory {
+                    address,
+                    size,
+                    prefetchable,
+                } => debug!(
+                    "  BAR{}: memory at {:#x} ({} KiB{})",
+                    index,
+                    address,
+                    size / 1024,
+                    if prefetchable { ", prefetchable" } else { "" }
+                ),
+            }
+        }
+    }
+    *DEVICES.lock() = devices.into_iter().map(|device| (device, None)).collect();
+
+    let drivers: Vec<&'static PciDriver> = DRIVERS.lock().clone();
+    for driver in drivers {
+        bind(driver);
+    }
+}
+
+/// Contents of /proc/pci: the devices and their drivers
+pub fn proc_pci() -> String {
+    DEVICES
+        .lock()
+        .iter()
+        .map(|(device, driver)| {
+            format!(
+                "{}{}\n",
+                device.describe(),
+                driver.map_or(String::new(), |driver| format!(" [{}]", driver.name))
+            )
+        })
+        .collect()
+}
@@ -0,0 +1,336 @@
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use crate::{
+    arch::{
+        interrupts::IRQ_COUNTS,
+        msi,
+        paging::{GLOBAL_FRAME_ALLOCATOR, PAGE_SIZE},
+        pic::Timer,
+    },
+    drivers::pci,
+    filesystem::{DirEnt, Inode, Type, VFS_Node, MOUNTS},
+    mm::ALLOCATOR,
+    sync::{Once, SpinMutex},
+    task::{Task, HEAP_PAGES, MULTIPROCESSING, PROGRAM_PAGES, STACK_PAGES},
+};
+
+static PROC_FS: Once<SpinMutex<ProcFilesystem>> = Once::new();
+
+/// Generates the contents of a file of the root directory
+type GlobalFile = fn() -> String;
+
+/// Files of the root directory and the functions generating their contents
+const GLOBAL_FILES: [(&str, GlobalFile); 5] = [
+    ("meminfo", meminfo),
+    ("uptime", uptime),
+    ("mounts", mounts),
+    ("interrupts", interrupts),
+    ("pci", pci::proc_pci),
+];
+
+/// Generates the contents of a file of a process directory
+type TaskFile = fn(&Task) -> String;
+
+/// Files of each process directory
+const TASK_FILES: [(&str, TaskFile); 4] = [
+    ("status", status),
+    ("cmdline", cmdline),
+    ("maps", maps),
+    ("fds", fds),
+];
+
+/// Read-only view of the kernel state, file contents are generated on every read.
+///
+/// The root is inode 0 and the global files follow it. The directory of process
+/// `pid` is inode `(pid + 1) << 8` and its files follow the directory
+pub struct ProcFilesystem {
+    root: VFS_Node,
+    /// Nodes returned by lookups, boxed so their addresses stay valid
+    nodes: BTreeMap<Inode, Box<VFS_Node>>,
+}
+
+// The nodes are only used behind the lock of the filesystem, or through the
+// pointers handed to the VFS which keep their boxes alive
+unsafe impl Send for ProcFilesystem {}
+
+pub fn initialize_procfs() -> *mut VFS_Node {
+    let proc_fs = ProcFilesystem {
+        root: new_node("proc".to_string(), Type::Dir, 0),
+        nodes: BTreeMap::new(),
+    };
+
+    PROC_FS.set(SpinMutex::new(proc_fs));
+
+    &mut PROC_FS.lock().root as *mut VFS_Node
+}
+
+/// Creates a read-only node, owned by root
+fn new_node(name: String, kind: Type, inode: Inode) -> VFS_Node {
+    let is_dir = kind == Type::Dir;
+
+    VFS_Node {
+        name,
+        kind,
+        inode,
+        size: 0,
+        links: 1,
+        uid: 0,
+        gid: 0,
+        permissions: if is_dir { 0o555 } else { 0o444 },
+        read: if is_dir { None } else { Some(procfs_read) },
+        write: None,
+        readdir: if is_dir { Some(procfs_readdir) } else { None },
+        finddir: if is_dir { Some(procfs_finddir) } else { None },
+        create: None,
+        truncate: None,
+        unlink: None,
+        readlink: None,
+        symlink: None,
+        link: None,
+        mount_point: None,
+        open: 0,
+        release: None,
+        seek: None,
+    }
+}
+
+fn task_dir(pid: u64) -> Inode {
+    ((pid + 1) << 8) as Inode
+}
+
+/// Returns the process an inode belongs to, if any, and the index of the file
+/// in its directory (0 for the directory itself, files are numbered from 1)
+fn split_inode(inode: Inode) -> (Option<u64>, usize) {
+    match inode >> 8 {
+        0 => (None, inode),
+        dir => (Some(dir as u64 - 1), inode & 0xFF),
+    }
+}
+
+fn tasks() -> &'static [Task] {
+    MULTIPROCESSING.try_get().map_or(&[], |mp| &mp.tasks[..])
+}
+
+fn find_task(pid: u64) -> Option<&'static Task> {
+    tasks().iter().find(|task| task.id == pid)
+}
+
+/// Returns the node with the given inode, creating it on first use.
+/// Nodes of a process belong to its effective user
+fn node(name: &str, kind: Type, inode: Inode) -> *mut VFS_Node {
+    let mut fs = PROC_FS.lock();
+
+    let node = fs
+        .nodes
+        .entry(inode)
+        .or_insert_with(|| Box::new(new_node(name.to_string(), kind, inode)));
+
+    if let Some(task) = split_inode(inode).0.and_then(find_task) {
+        node.uid = task.credentials.euid;
+        node.gid = task.credentials.egid;
+    }
+    &mut **node as *mut VFS_Node
+}
+
+/// Generates the contents of a file, None if its process exited
+fn contents(inode: Inode) -> Option<String> {
+    let (pid, index) = split_inode(inode);
+    let index = index.checked_sub(1)?;
+
+    match pid {
+        None => GLOBAL_FILES.get(index).map(|(_, generate)| generate()),
+        Some(pid) => {
+            let task = find_task(pid)?;
+            TASK_FILES.get(index).map(|(_, generate)| generate(task))
+        }
+    }
+}
+
+pub fn procfs_read(
+    node: &VFS_Node,
+    offset: usize,
+    size: usize,
+    buffer: &mut [u8],
+) -> Option<usize> {
+    let data = contents(node.inode)?;
+
+    if offset >= data.len() {
+        return None;
+    }
+    let size = size.min(data.len() - offset).min(buffer.len());
+
+    buffer[..size].copy_from_slice(&data.as_bytes()[offset..offset + size]);
+    Some(size)
+}
+
+pub fn procfs_readdir(node: &VFS_Node) -> Option<Vec<DirEnt>> {
+    let file = |dir: Inode, i: usize, name: &str| DirEnt {
+        name: name.to_string(),
+        inode: dir + i + 1,
+        kind: Type::File,
+    };
+
+    match split_inode(node.inode) {
+        (None, 0) => {
+            let globals = GLOBAL_FILES
+                .iter()
+                .enumerate()
+                .map(|(i, (name, _))| file(0, i, name));
+            let processes = tasks().iter().map(|task| DirEnt {
+                name: task.id.to_string(),
+                inode: task_dir(task.id),
+                kind: Type::Dir,
+            });
+            Some(globals.chain(processes).collect())
+        }
+        (Some(pid), 0) => {
+            find_task(pid)?;
+            let dir = task_dir(pid);
+            Some(
+                TASK_FILES
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (name, _))| file(dir, i, name))
+                    .collect(),
+            )
+        }
+        _ => None,
+    }
+}
+
+pub fn procfs_finddir(node: &VFS_Node, name: &str) -> Option<*mut VFS_Node> {
+    match split_inode(node.inode) {
+        (None, 0) => {
+            if let Some(i) = GLOBAL_FILES.iter().position(|(file, _)| *file == name) {
+                return Some(self::node(name, Type::File, i + 1));
+            }
+            let pid = name.parse().ok()?;
+            find_task(pid)?;
+            Some(self::node(name, Type::Dir, task_dir(pid)))
+        }
+        (Some(pid), 0) => {
+            find_task(pid)?;
+            let i = TASK_FILES.iter().position(|(file, _)| *file == name)?;
+            Some(self::node(name, Type::File, task_dir(pid) + i + 1))
+        }
+        _ => None,
+    }
+}
+
+fn meminfo() -> String {
+    let (total, used) = {
+        let frames = GLOBAL_FRAME_ALLOCATOR.lock();
+        (frames.total_frames(), frames.used_frames())
+    };
+    let (heap_size, heap_used) = ALLOCATOR.lock().usage();
+    let frame_kb = PAGE_SIZE / 1024;
+
+    format!(
+        "MemTotal:  {:>10} kB\nMemFree:   {:>10} kB\nHeapTotal: {:>10} kB\nHeapUsed:  {:>10} kB\n",
+        total * frame_kb,
+        (total - used) * frame_kb,
+        heap_size / 1024,
+        heap_used / 1024
+    )
+}
+
+/// Seconds since boot, with hundredths
+fn uptime() -> String {
+    let ms = Timer::uptime();
+    format!("{}.{:02}\n", ms / 1000, ms % 1000 / 10)
+}
+
+fn mounts() -> String {
+    MOUNTS
+        .lock()
+        .iter()
+        .map(|mount| format!("{} {} {}\n", mount.source, mount.target, mount.fstype))
+        .collect()
+}
+
+/// Legacy IRQ lines, then the vectors of message signaled interrupts with their device
+fn interrupts() -> String {
+    let irqs = IRQ_COUNTS.iter().enumerate().map(|(irq, count)| {
+        format!(
+            "{:>3}: {:>10}\n",
+            irq,
+            count.load(core::sync::atomic::Ordering::Relaxed)
+        )
+    });
+    let vectors = msi::vectors()
+        .into_iter()
+        .map(|(vector, name, count)| format!("{:>3}: {:>10}  MSI {}\n", vector, count, name));
+    irqs.chain(vectors).collect()
+}
+
+fn status(task: &Task) -> String {
+    let running = MULTIPROCESSING
+        .try_get()
+        .is_some_and(|mp| mp.current_id == task.id);
+    let credentials = &task.credentials;
+
+    format!(
+        "Name:\t{}\nPid:\t{}\nState:\t{}\nUid:\t{}\t{}\t{}\nGid:\t{}\t{}\t{}\nFDSize:\t{}\n",
+        task.name,
+        task.id,
+        // The other tasks wait for their child to exit
+        if running {
+            "R (running)"
+        } else {
+            "S (sleeping)"
+        },
+        credentials.uid,
+        credentials.euid,
+        credentials.suid,
+        credentials.gid,
+        credentials.egid,
+        credentials.sgid,
+        task.open_fd.len()
+    )
+}
+
+/// Arguments separated by null characters, only the program path for now
+fn cmdline(task: &Task) -> String {
+    format!("{}\0", task.name)
+}
+
+/// Memory regions of the process, every process has the same layout
+fn maps(task: &Task) -> String {
+    let regions = [
+        (0, PROGRAM_PAGES, task.name.as_str()),
+        (PROGRAM_PAGES, HEAP_PAGES, "[heap]"),
+        (PROGRAM_PAGES + HEAP_PAGES, STACK_PAGES, "[stack]"),
+    ];
+
+    regions
+        .iter()
+        .map(|&(start, pages, name)| {
+            let start = start as u64 * PAGE_SIZE;
+            let end = start + pages as u64 * PAGE_SIZE;
+            format!("{:016x}-{:016x} rwxp {}\n", start, end, name)
+        })
+        .collect()
+}
+
+/// One line per open file: descriptor, access mode, offset and node name
+fn fds(task: &Task) -> String {
+    task.open_fd
+        .iter()
+        .enumerate()
+        .map(|(fd, file)| {
+            let mode = match (file.readable(), file.writable()) {
+                (true, true) => "rw",
+                (true, false) => "r",
+                _ => "w",
+            };
+            let name = unsafe { &(*file.node).name };
+            format!("{}\t{}\t{}\t{}\n", fd, mode, file.offset, name)
+        })
+        .collect()
+}
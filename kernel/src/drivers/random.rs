@@ -0,0 +1,122 @@
+use core::arch::{
+    asm,
+    x86_64::{__cpuid, _rdtsc},
+};
+
+use crate::sync::SpinMutex;
+
+use super::chardev::CharDev;
+
+static GENERATOR: SpinMutex<Generator> = SpinMutex::new(Generator::new());
+
+/// xoshiro256** generator, reseeded from the CPU before every read.
+///
+/// With RDRAND the output is as good as the hardware generator, without it the only
+/// entropy comes from the time stamp counter, so it is not suitable for cryptography.
+struct Generator {
+    state: [u64; 4],
+    seeded: bool,
+    rdrand: bool,
+}
+
+/// Used to spread a seed over the whole state
+fn splitmix64(x: &mut u64) -> u64 {
+    *x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *x;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Returns a hardware random number, None if the CPU has none ready
+fn rdrand() -> Option<u64> {
+    let value: u64;
+    let ok: u8;
+    unsafe {
+        asm!("rdrand {}", "setc {}", out(reg) value, out(reg_byte) ok, options(nomem, nostack));
+    }
+    (ok != 0).then_some(value)
+}
+
+impl Generator {
+    const fn new() -> Self {
+        Generator {
+            state: [0; 4],
+            seeded: false,
+            rdrand: false,
+        }
+    }
+
+    /// Mixes a value into the state
+    fn mix(&mut self, value: u64) {
+        let mut seed = value ^ self.state[0];
+        for word in &mut self.state {
+            *word ^= splitmix64(&mut seed);
+        }
+    }
+
+    fn reseed(&mut self) {
+        if !self.seeded {
+            // CPUID.01H:ECX bit 30
+            self.rdrand = __cpuid(1).ecx & (1 << 30) != 0;
+            self.seeded = true;
+        }
+
+        self.mix(unsafe { _rdtsc() });
+        if self.rdrand {
+            for _ in 0..4 {
+                if let Some(value) = rdrand() {
+                    self.mix(value);
+                }
+            }
+        }
+    }
+
+    fn next(&mut self) -> u64 {
+        let result = self.state[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = self.state[1] << 17;
+
+        self.state[2] ^= self.state[0];
+        self.state[3] ^= self.state[1];
+        self.state[1] ^= self.state[2];
+        self.state[0] ^= self.state[3];
+        self.state[2] ^= t;
+        self.state[3] = self.state[3].rotate_left(45);
+
+        result
+    }
+}
+
+/// Fills the buffer with random bytes
+pub fn fill(buf: &mut [u8]) {
+    let mut generator = GENERATOR.lock();
+    generator.reseed();
+
+    for chunk in buf.chunks_mut(8) {
+        let bytes = generator.next().to_le_bytes();
+        chunk.copy_from_slice(&bytes[..chunk.len()]);
+    }
+}
+
+/// Both /dev/random and /dev/urandom, reads never block.
+/// Written data is mixed into the generator state
+pub struct Random;
+
+impl CharDev for Random {
+    fn read(&self, size: usize, buf: &mut [u8]) -> Option<usize> {
+        let size = size.min(buf.len());
+        fill(&mut buf[..size]);
+        Some(size)
+    }
+
+    fn write(&self, size: usize, buf: &[u8]) -> Option<usize> {
+        let size = size.min(buf.len());
+        let mut generator = GENERATOR.lock();
+        for chunk in buf[..size].chunks(8) {
+            let mut bytes = [0; 8];
+            bytes[..chunk.len()].copy_from_slice(chunk);
+            generator.mix(u64::from_le_bytes(bytes));
+        }
+        Some(size)
+    }
+}
@@ -51,7 +51,7 @@ impl CharDev for Serial {
         Some(index)
     }
 
-    fn write(&mut self, size: usize, buf: &[u8]) -> Option<usize> {
+    fn write(&self, size: usize, buf: &[u8]) -> Option<usize> {
         let mut index = 0_usize;
         while index < size {
             Serial::put_char(buf[index]);
@@ -0,0 +1,291 @@
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use crate::filesystem::{DirEnt, FsError, Inode, Type, VFS_Node};
+use crate::sync::{Once, SpinMutex};
+
+static TMP_FS: Once<SpinMutex<TmpFilesystem>> = Once::new();
+
+/// Largest file, the contents are kept in the kernel heap
+const MAX_FILE_SIZE: usize = 1024 * 1024;
+
+/// Contents of a tmpfs node
+struct TmpInode {
+    /// Shared by all the hard links to the inode, boxed so its address stays valid
+    node: Box<VFS_Node>,
+    /// File contents or symbolic link target
+    data: Vec<u8>,
+    /// Directory entries as names and inodes
+    entries: Vec<(String, Inode)>,
+}
+
+impl TmpInode {
+    fn new(node: VFS_Node) -> Self {
+        TmpInode {
+            node: Box::new(node),
+            data: Vec::new(),
+            entries: Vec::new(),
+        }
+    }
+}
+
+/// In-memory writable filesystem, its contents are lost on reboot
+pub struct TmpFilesystem {
+    /// Indexed by the inode number, the root directory is inode 0.
+    /// Unlinked inodes are kept while files opened on them stay readable
+    inodes: Vec<TmpInode>,
+    /// Released inodes, reused by the next nodes created
+    free: Vec<Inode>,
+}
+
+// The nodes are only used behind the lock of the filesystem, or through the
+// pointers handed to the VFS which keep their boxes alive
+unsafe impl Send for TmpFilesystem {}
+
+impl TmpFilesystem {
+    /// Returns the inode of the entry `name` in the directory `dir`
+    fn find(&self, dir: Inode, name: &str) -> Option<Inode> {
+        self.inodes[dir]
+            .entries
+            .iter()
+            .find(|(entry, _)| entry == name)
+            .map(|(_, inode)| *inode)
+    }
+}
+
+pub fn initialize_tmpfs() -> *mut VFS_Node {
+    // Every user can create files in the root directory
+    let mut root = new_node("tmp".to_string(), Type::Dir, 0);
+    root.permissions = 0o777;
+
+    let tmp_fs = TmpFilesystem {
+        inodes: alloc::vec![TmpInode::new(root)],
+        free: Vec::new(),
+    };
+
+    TMP_FS.set(SpinMutex::new(tmp_fs));
+
+    &mut *TMP_FS.lock().inodes[0].node as *mut VFS_Node
+}
+
+/// Creates a VFS node with the operations matching its type
+fn new_node(name: String, kind: Type, inode: Inode) -> VFS_Node {
+    let is_dir = kind == Type::Dir;
+    let is_file = kind == Type::File;
+
+    VFS_Node {
+        name,
+        kind: kind.clone(),
+        inode,
+        size: 0,
+        links: 1,
+        uid: 0,
+        gid: 0,
+        permissions: kind.default_permissions(),
+        read: if is_file { Some(tmpfs_read) } else { None },
+        write: if is_file { Some(tmpfs_write) } else { None },
+        readdir: if is_dir { Some(tmpfs_readdir) } else { None },
+        finddir: if is_dir { Some(tmpfs_finddir) } else { None },
+        create: if is_dir { Some(tmpfs_create) } else { None },
+        truncate: if is_file { Some(tmpfs_truncate) } else { None },
+        unlink: if is_dir { Some(tmpfs_unlink) } else { None },
+        readlink: if kind == Type::Symlink {
+            Some(tmpfs_readlink)
+        } else {
+            None
+        },
+        symlink: if is_dir { Some(tmpfs_symlink) } else { None },
+        link: if is_dir { Some(tmpfs_link) } else { None },
+        mount_point: None,
+        open: 0,
+        release: Some(tmpfs_release),
+        seek: None,
+    }
+}
+
+/// Adds a new inode with an entry in the directory `dir`, returns its node
+fn add_inode(fs: &mut TmpFilesystem, dir: Inode, name: &str, kind: Type) -> *mut VFS_Node {
+    let inode = match fs.free.pop() {
+        // The box is kept, so no pointer to a live node is invalidated
+        Some(inode) => {
+            *fs.inodes[inode].node = new_node(name.to_string(), kind, inode);
+            inode
+        }
+        None => {
+            let inode = fs.inodes.len();
+            fs.inodes
+                .push(TmpInode::new(new_node(name.to_string(), kind, inode)));
+            inode
+        }
+    };
+    fs.inodes[dir].entries.push((name.to_string(), inode));
+
+    &mut *fs.inodes[inode].node as *mut VFS_Node
+}
+
+pub fn tmpfs_read(node: &VFS_Node, offset: usize, size: usize, buffer: &mut [u8]) -> Option<usize> {
+    let fs = TMP_FS.lock();
+    let data = &fs.inodes[node.inode].data;
+
+    if offset >= data.len() {
+        return None;
+    }
+    let size = size.min(data.len() - offset).min(buffer.len());
+
+    buffer[..size].copy_from_slice(&data[offset..offset + size]);
+    Some(size)
+}
+
+pub fn tmpfs_write(
+    node: &mut VFS_Node,
+    offset: usize,
+    size: usize,
+    buffer: &[u8],
+) -> Result<usize, FsError> {
+    let size = size.min(buffer.len());
+    let end = offset.checked_add(size).ok_or(FsError::TooBig)?;
+    let mut fs = TMP_FS.lock();
+    let data = &mut fs.inodes[node.inode].data;
+
+    // Writing past the end fills the gap with zeroes
+    if end > data.len() {
+        resize(data, end)?;
+    }
+    data[offset..end].copy_from_slice(&buffer[..size]);
+
+    node.size = data.len();
+    Ok(size)
+}
+
+pub fn tmpfs_truncate(node: &mut VFS_Node, size: usize) -> Result<usize, FsError> {
+    resize(&mut TMP_FS.lock().inodes[node.inode].data, size)?;
+
+    node.size = size;
+    Ok(size)
+}
+
+/// Resizes file contents up to `MAX_FILE_SIZE`, failing instead of panicking
+/// when the heap is full
+fn resize(data: &mut Vec<u8>, size: usize) -> Result<(), FsError> {
+    if size > MAX_FILE_SIZE {
+        return Err(FsError::TooBig);
+    }
+    data.try_reserve(size.saturating_sub(data.len()))
+        .map_err(|_| FsError::NoSpace)?;
+    data.resize(size, 0);
+    Ok(())
+}
+
+pub fn tmpfs_readdir(node: &VFS_Node) -> Option<Vec<DirEnt>> {
+    if node.kind != Type::Dir {
+        return None;
+    }
+    let fs = TMP_FS.lock();
+
+    let ret = fs.inodes[node.inode]
+        .entries
+        .iter()
+        .map(|(name, inode)| DirEnt {
+            name: name.clone(),
+            inode: *inode,
+            kind: fs.inodes[*inode].node.kind.clone(),
+        })
+        .collect();
+
+    Some(ret)
+}
+
+pub fn tmpfs_finddir(node: &VFS_Node, name: &str) -> Option<*mut VFS_Node> {
+    if node.kind != Type::Dir {
+        return None;
+    }
+    let mut fs = TMP_FS.lock();
+
+    let inode = fs.find(node.inode, name)?;
+    Some(&mut *fs.inodes[inode].node as *mut VFS_Node)
+}
+
+pub fn tmpfs_create(node: &mut VFS_Node, name: &str, kind: Type) -> Option<*mut VFS_Node> {
+    if node.kind != Type::Dir || (kind != Type::File && kind != Type::Dir) {
+        return None;
+    }
+    let mut fs = TMP_FS.lock();
+    if fs.find(node.inode, name).is_some() {
+        return None;
+    }
+
+    Some(add_inode(&mut fs, node.inode, name, kind))
+}
+
+pub fn tmpfs_unlink(node: &mut VFS_Node, name: &str) -> Option<()> {
+    let mut fs = TMP_FS.lock();
+    let entries = &fs.inodes[node.inode].entries;
+    let index = entries.iter().position(|(entry, _)| entry == name)?;
+    let inode = entries[index].1;
+
+    // Directories must be empty to be removed
+    let target = &mut fs.inodes[inode];
+    if target.node.kind == Type::Dir && !target.entries.is_empty() {
+        return None;
+    }
+    target.node.links -= 1;
+
+    fs.inodes[node.inode].entries.remove(index);
+    let target = &mut *fs.inodes[inode].node as *mut VFS_Node;
+
+    // Releasing the node locks the filesystem again
+    drop(fs);
+    unsafe { (*target).release_if_unused() };
+    Some(())
+}
+
+/// Frees the contents of an inode without links or open files and recycles its number
+pub fn tmpfs_release(node: &mut VFS_Node) {
+    let mut fs = TMP_FS.lock();
+    let inode = &mut fs.inodes[node.inode];
+
+    inode.data = Vec::new();
+    inode.entries = Vec::new();
+    node.size = 0;
+    fs.free.push(node.inode);
+}
+
+pub fn tmpfs_readlink(node: &VFS_Node) -> Option<String> {
+    String::from_utf8(TMP_FS.lock().inodes[node.inode].data.clone()).ok()
+}
+
+pub fn tmpfs_symlink(node: &mut VFS_Node, name: &str, target: &str) -> Option<*mut VFS_Node> {
+    let mut fs = TMP_FS.lock();
+    if fs.find(node.inode, name).is_some() {
+        return None;
+    }
+    let link = add_inode(&mut fs, node.inode, name, Type::Symlink);
+
+    let inode = unsafe { (*link).inode };
+    fs.inodes[inode].data = target.as_bytes().to_vec();
+    fs.inodes[inode].node.size = target.len();
+
+    Some(link)
+}
+
+/// Only files and symbolic links of this tmpfs can be linked, not directories
+pub fn tmpfs_link(node: &mut VFS_Node, name: &str, target: &mut VFS_Node) -> Option<()> {
+    let mut fs = TMP_FS.lock();
+
+    let inode = fs.inodes.get(target.inode)?;
+    if !core::ptr::eq(&*inode.node, target) || target.kind == Type::Dir {
+        return None;
+    }
+    if fs.find(node.inode, name).is_some() {
+        return None;
+    }
+
+    fs.inodes[target.inode].node.links += 1;
+    fs.inodes[node.inode]
+        .entries
+        .push((name.to_string(), target.inode));
+    Some(())
+}
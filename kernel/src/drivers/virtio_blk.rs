@@ -0,0 +1,446 @@
+use core::{
+    alloc::Layout,
+    mem::size_of,
+    ptr::{read_volatile, write_volatile},
+    sync::atomic::{fence, AtomicBool, AtomicU16, AtomicU8, AtomicUsize, Ordering},
+};
+
+use alloc::{alloc::alloc_zeroed, boxed::Box, format};
+
+use crate::{
+    arch::{
+        addressing::{translate_virtual_address, VirtAddr},
+        interrupts,
+        io::{inb, inl, inw, outb, outl, outw},
+        pic::Timer,
+    },
+    logging,
+    task::WaitQueue,
+    utils::align_up,
+};
+
+use super::{blockdev::BlockDevice, pci};
+
+pub const SECTOR_SIZE: usize = 512;
+
+const VENDOR_ID: u16 = 0x1AF4;
+/// Transitional virtio-blk device, it has the legacy I/O port interface
+const DEVICE_ID: u16 = 0x1001;
+
+/// Maximum number of devices handled by the driver
+const MAX_DEVICES: usize = 4;
+
+/// Sectors transferred by one request, limited by the bounce buffer
+const SECTORS_PER_REQUEST: usize = 8;
+
+/// Milliseconds to wait for a request before giving up
+const TIMEOUT_MS: u64 = 1000;
+
+// Legacy interface register offsets from BAR0
+const REG_DEVICE_FEATURES: u16 = 0x00;
+const REG_GUEST_FEATURES: u16 = 0x04;
+const REG_QUEUE_ADDRESS: u16 = 0x08;
+const REG_QUEUE_SIZE: u16 = 0x0C;
+const REG_QUEUE_SELECT: u16 = 0x0E;
+const REG_QUEUE_NOTIFY: u16 = 0x10;
+const REG_DEVICE_STATUS: u16 = 0x12;
+const REG_ISR_STATUS: u16 = 0x13;
+/// Start of the virtio-blk configuration, the capacity in sectors
+const REG_CAPACITY: u16 = 0x14;
+
+// Device status bits
+const STATUS_ACKNOWLEDGE: u8 = 1;
+const STATUS_DRIVER: u8 = 2;
+const STATUS_DRIVER_OK: u8 = 4;
+const STATUS_FAILED: u8 = 128;
+
+/// The device is read-only
+const FEATURE_RO: u32 = 1 << 5;
+
+// Descriptor flags
+const DESC_NEXT: u16 = 1;
+const DESC_WRITE: u16 = 2;
+
+// Request types
+const REQUEST_IN: u32 = 0;
+const REQUEST_OUT: u32 = 1;
+
+/// Legacy queues are aligned to 4096 bytes and given to the device as a page number
+const QUEUE_ALIGN: usize = 4096;
+
+/// I/O base of each registered device, 0 for unused slots
+static DEVICE_PORTS: [AtomicU16; MAX_DEVICES] = [const { AtomicU16::new(0) }; MAX_DEVICES];
+/// PIC line of each registered device
+static DEVICE_IRQS: [AtomicU8; MAX_DEVICES] = [const { AtomicU8::new(0) }; MAX_DEVICES];
+/// Set by the IRQ handler when the device used a buffer
+static IRQ_RECEIVED: [AtomicBool; MAX_DEVICES] = [const { AtomicBool::new(false) }; MAX_DEVICES];
+/// Tasks waiting for the IRQ of each device
+static IRQ_WAITERS: [WaitQueue; MAX_DEVICES] = [const { WaitQueue::new() }; MAX_DEVICES];
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct RequestHeader {
+    kind: u32,
+    reserved: u32,
+    sector: u64,
+}
+
+/// Memory shared with the device for a single request.
+/// The data buffer is page aligned so it is physically contiguous
+#[repr(C, align(4096))]
+struct RequestBuffer {
+    data: [u8; SECTORS_PER_REQUEST * SECTOR_SIZE],
+    header: RequestHeader,
+    status: u8,
+}
+
+/// A legacy split virtqueue: descriptor table, available ring and used ring
+/// in one physically contiguous allocation
+struct Virtqueue {
+    size: u16,
+    descriptors: *mut Descriptor,
+    /// flags, idx, ring[size]
+    avail: *mut u16,
+    /// flags, idx, ring[size] of (id: u32, len: u32)
+    used: *mut u16,
+    last_used: u16,
+}
+
+impl Virtqueue {
+    /// Allocates the queue memory, returns the queue and its physical address
+    fn new(size: u16) -> Option<(Self, u64)> {
+        let n = size as usize;
+        let avail_offset = size_of::<Descriptor>() * n;
+        let used_offset = align_up((avail_offset + 6 + 2 * n) as u64, QUEUE_ALIGN as u64) as usize;
+        let total = used_offset + align_up((6 + 8 * n) as u64, QUEUE_ALIGN as u64) as usize;
+
+        // Aligning to the allocation size keeps it inside a single 2MB frame
+        let layout = Layout::from_size_align(total, total.next_power_of_two()).ok()?;
+        let memory = unsafe { alloc_zeroed(layout) };
+        if memory.is_null() {
+            return None;
+        }
+        let phys = translate_virtual_address(VirtAddr::from_ptr(memory))?;
+
+        let queue = unsafe {
+            Virtqueue {
+                size,
+                descriptors: memory as *mut Descriptor,
+                avail: memory.add(avail_offset) as *mut u16,
+                used: memory.add(used_offset) as *mut u16,
+                last_used: 0,
+            }
+        };
+        Some((queue, phys.as_u64()))
+    }
+
+    unsafe fn set_descriptor(&mut self, index: u16, descriptor: Descriptor) {
+        write_volatile(self.descriptors.add(index as usize), descriptor);
+    }
+
+    /// Makes the chain starting at descriptor `head` available to the device
+    unsafe fn push_avail(&mut self, head: u16) {
+        let idx = read_volatile(self.avail.add(1));
+        write_volatile(self.avail.add(2 + (idx % self.size) as usize), head);
+        // The ring entry must be visible before the index
+        fence(Ordering::SeqCst);
+        write_volatile(self.avail.add(1), idx.wrapping_add(1));
+    }
+
+    /// Returns true if the device used a buffer since the last call
+    unsafe fn pop_used(&mut self) -> bool {
+        let idx = read_volatile(self.used.add(1));
+        if idx == self.last_used {
+            return false;
+        }
+        self.last_used = self.last_used.wrapping_add(1);
+        true
+    }
+}
+
+/// A virtio block device using the legacy PCI interface
+pub struct VirtioBlk {
+    slot: usize,
+    io_base: u16,
+    sectors: u64,
+    read_only: bool,
+    queue: Virtqueue,
+    request: Box<RequestBuffer>,
+}
+
+impl VirtioBlk {
+    /// Resets and configures the device, the device slot is used by the IRQ handler
+    unsafe fn new(device: pci::PciDevice, slot: usize) -> Option<Self> {
+        let io_base = device.io_bar(0)?;
+        // 0xFF when the firmware connected no line, the driver can not wait for requests
+        let irq = device.interrupt_line();
+        if irq >= interrupts::IRQ_LINES {
+            warn!("virtio-blk: no interrupt line routed to the device");
+            return None;
+        }
+        device.enable_bus_mastering();
+
+        outb(io_base + REG_DEVICE_STATUS, 0);
+        outb(io_base + REG_DEVICE_STATUS, STATUS_ACKNOWLEDGE);
+        outb(
+            io_base + REG_DEVICE_STATUS,
+            STATUS_ACKNOWLEDGE | STATUS_DRIVER,
+        );
+
+        // No optional features are used
+        let features = inl(io_base + REG_DEVICE_FEATURES);
+        outl(io_base + REG_GUEST_FEATURES, 0);
+
+        outw(io_base + REG_QUEUE_SELECT, 0);
+        // Every request needs a chain of 3 descriptors
+        let queue_size = inw(io_base + REG_QUEUE_SIZE);
+        let queue = if queue_size >= 3 {
+            Virtqueue::new(queue_size)
+        } else {
+            None
+        };
+        let Some((queue, queue_phys)) = queue else {
+            outb(io_base + REG_DEVICE_STATUS, STATUS_FAILED);
+            return None;
+        };
+        outl(
+            io_base + REG_QUEUE_ADDRESS,
+            (queue_phys / QUEUE_ALIGN as u64) as u32,
+        );
+
+        let sectors =
+            inl(io_base + REG_CAPACITY) as u64 | (inl(io_base + REG_CAPACITY + 4) as u64) << 32;
+
+        // Route the interrupt before the device can raise it, the handler of a line
+        // shared with another disk is already there
+        let shared = DEVICE_IRQS[..slot]
+            .iter()
+            .any(|other| other.load(Ordering::Acquire) == irq);
+        if !shared && !interrupts::register_irq(irq, handle_irq) {
+            outb(io_base + REG_DEVICE_STATUS, STATUS_FAILED);
+            return None;
+        }
+        DEVICE_PORTS[slot].store(io_base, Ordering::Release);
+        DEVICE_IRQS[slot].store(irq, Ordering::Release);
+
+        outb(
+            io_base + REG_DEVICE_STATUS,
+            STATUS_ACKNOWLEDGE | STATUS_DRIVER | STATUS_DRIVER_OK,
+        );
+
+        Some(VirtioBlk {
+            slot,
+            io_base,
+            sectors,
+            read_only: features & FEATURE_RO != 0,
+            queue,
+            request: Box::new(RequestBuffer {
+                data: [0; SECTORS_PER_REQUEST * SECTOR_SIZE],
+                header: RequestHeader {
+                    kind: 0,
+                    reserved: 0,
+                    sector: 0,
+                },
+                status: 0,
+            }),
+        })
+    }
+
+    /// Sends one request for at most SECTORS_PER_REQUEST sectors using the
+    /// request buffer and waits for the device to complete it
+    unsafe fn transfer(&mut self, kind: u32, sector: u64, len: usize) -> Option<()> {
+        self.request.header = RequestHeader {
+            kind,
+            reserved: 0,
+            sector,
+        };
+        self.request.status = 0xFF;
+
+        let header_phys = translate_virtual_address(VirtAddr::from_ptr(&self.request.header))?;
+        let data_phys = translate_virtual_address(VirtAddr::from_ptr(self.request.data.as_ptr()))?;
+        let status_phys = translate_virtual_address(VirtAddr::from_ptr(&self.request.status))?;
+
+        // The device writes into the data buffer on reads
+        let data_flags = if kind == REQUEST_IN {
+            DESC_NEXT | DESC_WRITE
+        } else {
+            DESC_NEXT
+        };
+
+        self.queue.set_descriptor(
+            0,
+            Descriptor {
+                addr: header_phys.as_u64(),
+                len: size_of::<RequestHeader>() as u32,
+                flags: DESC_NEXT,
+                next: 1,
+            },
+        );
+        self.queue.set_descriptor(
+            1,
+            Descriptor {
+                addr: data_phys.as_u64(),
+                len: len as u32,
+                flags: data_flags,
+                next: 2,
+            },
+        );
+        self.queue.set_descriptor(
+            2,
+            Descriptor {
+                addr: status_phys.as_u64(),
+                len: 1,
+                flags: DESC_WRITE,
+                next: 0,
+            },
+        );
+
+        IRQ_RECEIVED[self.slot].store(false, Ordering::Release);
+        self.queue.push_avail(0);
+        outw(self.io_base + REG_QUEUE_NOTIFY, 0);
+
+        self.wait()?;
+        if read_volatile(&self.request.status) != 0 {
+            error!(
+                "virtio-blk: request failed with status {}",
+                self.request.status
+            );
+            return None;
+        }
+        Some(())
+    }
+
+    /// Waits for the device to place the request in the used ring.
+    /// Sleeps until the interrupt arrives, or polls if interrupts are disabled
+    unsafe fn wait(&mut self) -> Option<()> {
+        let interrupts = crate::arch::interrupts::are_enabled();
+        let deadline = Timer::uptime() + TIMEOUT_MS;
+
+        while !self.queue.pop_used() {
+            if Timer::uptime() > deadline {
+                error!("virtio-blk: request timed out");
+                return None;
+            }
+            if interrupts {
+                IRQ_WAITERS[self.slot].wait_while_timeout(
+                    || !IRQ_RECEIVED[self.slot].swap(false, Ordering::Acquire),
+                    Some(deadline.saturating_sub(Timer::uptime())),
+                );
+            } else {
+                core::hint::spin_loop();
+            }
+        }
+        Some(())
+    }
+}
+
+impl BlockDevice for VirtioBlk {
+    fn sector_size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    fn sector_count(&self) -> u64 {
+        self.sectors
+    }
+
+    fn read_sectors(&mut self, lba: u64, buf: &mut [u8]) -> Option<usize> {
+        if !buf.len().is_multiple_of(SECTOR_SIZE)
+            || lba + (buf.len() / SECTOR_SIZE) as u64 > self.sectors
+        {
+            return None;
+        }
+
+        for (i, chunk) in buf
+            .chunks_mut(SECTORS_PER_REQUEST * SECTOR_SIZE)
+            .enumerate()
+        {
+            let sector = lba + (i * SECTORS_PER_REQUEST) as u64;
+            unsafe { self.transfer(REQUEST_IN, sector, chunk.len())? };
+            chunk.copy_from_slice(&self.request.data[..chunk.len()]);
+        }
+        Some(buf.len())
+    }
+
+    fn write_sectors(&mut self, lba: u64, buf: &[u8]) -> Option<usize> {
+        if self.read_only
+            || !buf.len().is_multiple_of(SECTOR_SIZE)
+            || lba + (buf.len() / SECTOR_SIZE) as u64 > self.sectors
+        {
+            return None;
+        }
+
+        for (i, chunk) in buf.chunks(SECTORS_PER_REQUEST * SECTOR_SIZE).enumerate() {
+            let sector = lba + (i * SECTORS_PER_REQUEST) as u64;
+            self.request.data[..chunk.len()].copy_from_slice(chunk);
+            unsafe { self.transfer(REQUEST_OUT, sector, chunk.len())? };
+        }
+        Some(buf.len())
+    }
+}
+
+/// Handler of the IRQ lines of the devices.
+/// Reading the ISR status register acknowledges the interrupt on the device
+fn handle_irq(irq: u8) {
+    for slot in 0..MAX_DEVICES {
+        let io_base = DEVICE_PORTS[slot].load(Ordering::Acquire);
+        if io_base == 0 || DEVICE_IRQS[slot].load(Ordering::Acquire) != irq {
+            continue;
+        }
+        // Bit 0 signals a used buffer notification
+        if unsafe { inb(io_base + REG_ISR_STATUS) } & 1 != 0 {
+            IRQ_RECEIVED[slot].store(true, Ordering::Release);
+            IRQ_WAITERS[slot].wake_all();
+        }
+    }
+}
+
+static DRIVER: pci::PciDriver = pci::PciDriver {
+    name: "virtio-blk",
+    matches: &[pci::DeviceMatch::device(VENDOR_ID, DEVICE_ID)],
+    probe,
+};
+
+/// Next free device slot
+static NEXT_SLOT: AtomicUsize = AtomicUsize::new(0);
+
+/// Registers a device as vda, vdb...
+fn probe(device: &pci::PciDevice) -> bool {
+    let slot = NEXT_SLOT.load(Ordering::Relaxed);
+    if slot >= MAX_DEVICES {
+        return false;
+    }
+    let name = format!("vd{}", (b'a' + slot as u8) as char);
+
+    match unsafe { VirtioBlk::new(*device, slot) } {
+        Some(disk) => {
+            NEXT_SLOT.store(slot + 1, Ordering::Relaxed);
+            log!(
+                "{}: virtio-blk ({} sectors, IRQ {}{})",
+                name,
+                disk.sectors,
+                device.interrupt_line(),
+                if disk.read_only { ", read-only" } else { "" }
+            );
+            super::blockdev::register_disk(&name, Box::new(disk));
+            true
+        }
+        None => {
+            warn!("{}: failed to initialize virtio-blk device", name);
+            false
+        }
+    }
+}
+
+/// Registers the driver of the virtio block devices on the PCI bus
+pub fn init() {
+    pci::register_driver(&DRIVER);
+}
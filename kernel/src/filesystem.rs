@@ -1,16 +1,121 @@
-use crate::drivers::initrd::initialize_initrd;
-use crate::{arch::addressing::KERNEL_BASE, multiboot::MultibootInfo};
+use crate::drivers::blockdev::{self, Partition};
+use crate::drivers::{ext2, fat, initrd};
+use crate::task::{self, Credentials};
+use crate::{
+    arch::addressing::KERNEL_BASE,
+    multiboot::MultibootInfo,
+    sync::{Global, SpinMutex},
+};
 use alloc::{string::String, vec::Vec};
 
-pub static mut FS_ROOT: Option<*const VFS_Node> = None;
+/// Root directory, the initrd until `mount_root` replaces it
+pub static FS_ROOT: Global<*const VFS_Node> = Global::new();
+
+/// Mounted filesystems, in the order they were mounted
+pub static MOUNTS: SpinMutex<Vec<Mount>> = SpinMutex::new(Vec::new());
+
+/// Filesystems mounted on initrd directories, by directory name and type
+const INITRD_MOUNTS: [(&str, &str); 3] = [("dev", "devfs"), ("tmp", "tmpfs"), ("proc", "proc")];
 
 pub type Inode = usize;
 
 // Definitions for the node function pointer types
 type read_fs = fn(&VFS_Node, usize, usize, &mut [u8]) -> Option<usize>;
-type write_fs = fn(&mut VFS_Node, usize, usize, &[u8]) -> Option<usize>;
+type write_fs = fn(&mut VFS_Node, usize, usize, &[u8]) -> Result<usize, FsError>;
 type readdir_fs = fn(&VFS_Node) -> Option<Vec<DirEnt>>;
 type finddir_fs = fn(&VFS_Node, name: &str) -> Option<*mut VFS_Node>;
+type create_fs = fn(&mut VFS_Node, name: &str, kind: Type) -> Option<*mut VFS_Node>;
+type truncate_fs = fn(&mut VFS_Node, usize) -> Result<usize, FsError>;
+type unlink_fs = fn(&mut VFS_Node, name: &str) -> Option<()>;
+type readlink_fs = fn(&VFS_Node) -> Option<String>;
+type symlink_fs = fn(&mut VFS_Node, name: &str, target: &str) -> Option<*mut VFS_Node>;
+type link_fs = fn(&mut VFS_Node, name: &str, target: &mut VFS_Node) -> Option<()>;
+type release_fs = fn(&mut VFS_Node);
+type seek_fs = fn(&VFS_Node, usize) -> usize;
+
+/// Maximum number of symbolic links followed while resolving a path
+const SYMLOOP_MAX: usize = 40;
+
+/// Reasons a path lookup or a file operation fails, returned to user space as negative errno values
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FsError {
+    /// ENOENT
+    NotFound,
+    /// ENOTDIR
+    NotDir,
+    /// ELOOP, too many symbolic links
+    Loop,
+    /// EACCES, the permission bits of a node deny the access
+    Access,
+    /// EPERM, the operation failed or is not supported by the filesystem
+    Failed,
+    /// EFBIG, the file would grow past the largest size of its filesystem
+    TooBig,
+    /// ENOSPC, no memory or disk space is left for the data
+    NoSpace,
+    /// EINVAL, the device holds no volume of the filesystem or a corrupt one
+    Invalid,
+}
+
+impl FsError {
+    pub fn errno(&self) -> i64 {
+        match self {
+            FsError::NotFound => Errno::ENOENT,
+            FsError::NotDir => Errno::ENOTDIR,
+            FsError::Loop => Errno::ELOOP,
+            FsError::Access => Errno::EACCES,
+            FsError::Failed => Errno::EPERM,
+            FsError::TooBig => Errno::EFBIG,
+            FsError::NoSpace => Errno::ENOSPC,
+            FsError::Invalid => Errno::EINVAL,
+        }
+    }
+}
+
+/// Error numbers, returned negated by the system calls, same values as Linux
+#[allow(non_snake_case)]
+pub mod Errno {
+    /// The caller is not allowed to do it, or it failed
+    pub const EPERM: i64 = 1;
+    pub const ENOENT: i64 = 2;
+    pub const EACCES: i64 = 13;
+    /// The file to create already exists
+    pub const EEXIST: i64 = 17;
+    pub const ENOTDIR: i64 = 20;
+    /// An invalid argument, e.g. an unknown clock or an invalid time
+    pub const EINVAL: i64 = 22;
+    /// The file would grow past the largest size of its filesystem
+    pub const EFBIG: i64 = 27;
+    /// No memory or disk space is left for the data
+    pub const ENOSPC: i64 = 28;
+    pub const ELOOP: i64 = 40;
+}
+
+/// Access checked by `VFS_Node::permits`, same values as the permission bits
+#[allow(non_snake_case)]
+pub mod Access {
+    pub const READ: u64 = 4;
+    pub const WRITE: u64 = 2;
+    pub const EXEC: u64 = 1;
+}
+
+/// Flags given to the open() syscall, same values as Linux
+#[allow(non_snake_case)]
+pub mod OpenFlags {
+    pub const O_RDONLY: u64 = 0;
+    pub const O_WRONLY: u64 = 1;
+    pub const O_RDWR: u64 = 2;
+    /// Mask for the access mode bits
+    pub const O_ACCMODE: u64 = 3;
+    /// Create the file if it doesn't exist
+    pub const O_CREAT: u64 = 0o100;
+    /// Together with O_CREAT, fail if the file exists
+    pub const O_EXCL: u64 = 0o200;
+    /// Truncate the file to length 0 if opened for writing
+    pub const O_TRUNC: u64 = 0o1000;
+    /// Every write goes to the end of the file
+    pub const O_APPEND: u64 = 0o2000;
+}
 
 #[derive(PartialEq, Debug, Clone)]
 pub enum Type {
@@ -19,6 +124,42 @@ pub enum Type {
     CharDev,
     BlockDev,
     Mountpoint,
+    Symlink,
+}
+
+impl Type {
+    /// Numeric file type as seen by user space (`DT_*` values in `dirent.h`).
+    /// A mountpoint is reported as a plain directory.
+    pub fn as_user_type(&self) -> u8 {
+        match self {
+            Type::File => 1,
+            Type::Dir | Type::Mountpoint => 2,
+            Type::CharDev => 3,
+            Type::BlockDev => 4,
+            Type::Symlink => 5,
+        }
+    }
+
+    /// File type bits of the `mode` field (`S_IF*` values in `sys/stat.h`)
+    pub fn mode_bits(&self) -> u64 {
+        match self {
+            Type::File => 0o100000,
+            Type::Dir | Type::Mountpoint => 0o040000,
+            Type::CharDev => 0o020000,
+            Type::BlockDev => 0o060000,
+            Type::Symlink => 0o120000,
+        }
+    }
+
+    /// Permission bits given to nodes of this type
+    pub fn default_permissions(&self) -> u64 {
+        match self {
+            Type::File | Type::Dir | Type::Mountpoint => 0o755,
+            Type::CharDev => 0o666,
+            Type::BlockDev => 0o660,
+            Type::Symlink => 0o777,
+        }
+    }
 }
 
 /// Virtual Filesystem node type.
@@ -28,11 +169,64 @@ pub struct VFS_Node {
     pub kind: Type,
     pub inode: Inode,
     pub size: usize,
+    /// Number of directory entries referring to the node
+    pub links: usize,
+    /// Owner of the node
+    pub uid: u32,
+    pub gid: u32,
+    /// Permission bits of the mode, the file type comes from `kind`
+    pub permissions: u64,
     pub read: Option<read_fs>,
     pub write: Option<write_fs>,
     pub readdir: Option<readdir_fs>,
     pub finddir: Option<finddir_fs>,
+    pub create: Option<create_fs>,
+    pub truncate: Option<truncate_fs>,
+    pub unlink: Option<unlink_fs>,
+    pub readlink: Option<readlink_fs>,
+    pub symlink: Option<symlink_fs>,
+    pub link: Option<link_fs>,
     pub mount_point: Option<*mut VFS_Node>,
+    /// Number of files opened by the tasks on the node
+    pub open: usize,
+    /// Frees the contents of a node without links once its last open file is closed
+    pub release: Option<release_fs>,
+    /// Offset reads at an offset continue from, for devices dropping their oldest data
+    pub seek: Option<seek_fs>,
+}
+
+/// A file opened by a task, with its own offset and open flags
+#[derive(Debug, Clone, Copy)]
+pub struct OpenFile {
+    pub node: *mut VFS_Node,
+    pub offset: u64,
+    pub flags: u64,
+}
+
+impl OpenFile {
+    pub fn new(node: *mut VFS_Node, flags: u64) -> Self {
+        unsafe { (*node).open += 1 };
+        OpenFile {
+            node,
+            offset: 0,
+            flags,
+        }
+    }
+
+    /// Called when the file descriptor is closed or its task exits
+    pub fn close(self) {
+        let node = unsafe { &mut *self.node };
+        node.open -= 1;
+        node.release_if_unused();
+    }
+
+    pub fn readable(&self) -> bool {
+        self.flags & OpenFlags::O_ACCMODE != OpenFlags::O_WRONLY
+    }
+
+    pub fn writable(&self) -> bool {
+        self.flags & OpenFlags::O_ACCMODE != OpenFlags::O_RDONLY
+    }
 }
 
 /// Structure returned by the readdir() function
@@ -40,9 +234,61 @@ pub struct VFS_Node {
 pub struct DirEnt {
     pub name: String,
     pub inode: Inode,
+    pub kind: Type,
+}
+
+/// Maximum length of a file name returned to user space, including the null terminator
+pub const NAME_MAX: usize = 64;
+
+/// Directory entry as returned to user space by getdents().
+/// Layout must match `struct dirent` in the libc `dirent.h`
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct UserDirEnt {
+    pub inode: u64,
+    pub kind: u8,
+    pub name: [u8; NAME_MAX],
+}
+
+impl From<&DirEnt> for UserDirEnt {
+    fn from(entry: &DirEnt) -> Self {
+        let mut name = [0; NAME_MAX];
+        // Names that don't fit are truncated, always leaving room for the null terminator
+        let len = entry.name.len().min(NAME_MAX - 1);
+        name[..len].copy_from_slice(&entry.name.as_bytes()[..len]);
+
+        UserDirEnt {
+            inode: entry.inode as u64,
+            kind: entry.kind.as_user_type(),
+            name,
+        }
+    }
+}
+
+/// Node information returned to user space by stat() and fstat().
+/// Layout must match `struct stat` in the libc `sys/stat.h`
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Stat {
+    pub inode: u64,
+    pub kind: u64,
+    pub size: u64,
+    pub mode: u64,
+    pub nlink: u64,
+    pub uid: u64,
+    pub gid: u64,
 }
 
 impl VFS_Node {
+    /// Frees the contents of the node once no directory entry and no open file refers to it
+    pub fn release_if_unused(&mut self) {
+        if self.links == 0 && self.open == 0 {
+            if let Some(releasefn) = self.release {
+                releasefn(self);
+            }
+        }
+    }
+
     /// File read
     pub fn read(&self, offset: usize, size: usize, buffer: &mut [u8]) -> Option<usize> {
         if let Some(readfn) = self.read {
@@ -51,12 +297,20 @@ impl VFS_Node {
         None
     }
 
+    /// Offset the next read at `offset` starts from, the same offset for most nodes
+    pub fn seek(&self, offset: usize) -> usize {
+        match self.seek {
+            Some(seekfn) => seekfn(self, offset),
+            None => offset,
+        }
+    }
+
     /// File write
-    pub fn write(&mut self, offset: usize, size: usize, buffer: &[u8]) -> Option<usize> {
+    pub fn write(&mut self, offset: usize, size: usize, buffer: &[u8]) -> Result<usize, FsError> {
         if let Some(writefn) = self.write {
             return writefn(self, offset, size, buffer);
         }
-        None
+        Err(FsError::Failed)
     }
 
     /// Returns FS indexes of nodes inside the directory.
@@ -88,29 +342,395 @@ impl VFS_Node {
         }
         None
     }
+
+    /// Creates a new node named `name` inside this directory.
+    /// Passes request to mounted directory if it is a mountpoint
+    pub fn create(&mut self, name: &str, kind: Type) -> Option<*mut VFS_Node> {
+        let mut which: *mut VFS_Node = self;
+        // Passthrough mounted directory if needed
+        if let Some(mounted) = self.mount_point {
+            which = mounted;
+        }
+
+        let which = unsafe { &mut *which };
+        if let Some(createfn) = which.create {
+            return createfn(which, name, kind);
+        }
+        None
+    }
+
+    /// Removes the entry `name` from this directory, directories must be empty.
+    /// Passes request to mounted directory if it is a mountpoint
+    pub fn unlink(&mut self, name: &str) -> Option<()> {
+        let mut which: *mut VFS_Node = self;
+        // Passthrough mounted directory if needed
+        if let Some(mounted) = self.mount_point {
+            which = mounted;
+        }
+
+        let which = unsafe { &mut *which };
+        if let Some(unlinkfn) = which.unlink {
+            return unlinkfn(which, name);
+        }
+        None
+    }
+
+    /// Returns the path a symbolic link points to
+    pub fn readlink(&self) -> Option<String> {
+        if let Some(readlinkfn) = self.readlink {
+            return readlinkfn(self);
+        }
+        None
+    }
+
+    /// Creates a symbolic link named `name` pointing to `target` inside this directory.
+    /// Passes request to mounted directory if it is a mountpoint
+    pub fn symlink(&mut self, name: &str, target: &str) -> Option<*mut VFS_Node> {
+        let mut which: *mut VFS_Node = self;
+        // Passthrough mounted directory if needed
+        if let Some(mounted) = self.mount_point {
+            which = mounted;
+        }
+
+        let which = unsafe { &mut *which };
+        if let Some(symlinkfn) = which.symlink {
+            return symlinkfn(which, name, target);
+        }
+        None
+    }
+
+    /// Adds an entry `name` to this directory referring to the existing node `target`.
+    /// Passes request to mounted directory if it is a mountpoint
+    pub fn link(&mut self, name: &str, target: &mut VFS_Node) -> Option<()> {
+        let mut which: *mut VFS_Node = self;
+        // Passthrough mounted directory if needed
+        if let Some(mounted) = self.mount_point {
+            which = mounted;
+        }
+
+        let which = unsafe { &mut *which };
+        if let Some(linkfn) = which.link {
+            return linkfn(which, name, target);
+        }
+        None
+    }
+
+    /// Changes the size of the file, returns the new size
+    pub fn truncate(&mut self, size: usize) -> Result<usize, FsError> {
+        if let Some(truncatefn) = self.truncate {
+            return truncatefn(self, size);
+        }
+        Err(FsError::Failed)
+    }
+
+    /// Returns the node holding the owner and permissions of this one,
+    /// the root of the mounted directory if it is a mountpoint
+    fn attributes(&self) -> *mut VFS_Node {
+        self.mount_point
+            .unwrap_or(self as *const VFS_Node as *mut VFS_Node)
+    }
+
+    /// Changes the permission bits, kept in memory only by filesystems without owners
+    pub fn chmod(&mut self, permissions: u64) {
+        unsafe { (*self.attributes()).permissions = permissions & 0o7777 };
+    }
+
+    /// Changes the owner and group of the node
+    pub fn chown(&mut self, uid: u32, gid: u32) {
+        let node = unsafe { &mut *self.attributes() };
+        node.uid = uid;
+        node.gid = gid;
+    }
+
+    /// Checks the `Access` bits against the permissions for the given credentials.
+    /// The root user is only denied executing files without any execute bit
+    pub fn permits(&self, credentials: &Credentials, access: u64) -> bool {
+        let node = unsafe { &*self.attributes() };
+
+        if credentials.is_root() {
+            return access & Access::EXEC == 0
+                || node.kind != Type::File
+                || node.permissions & 0o111 != 0;
+        }
+
+        let bits = if credentials.euid == node.uid {
+            node.permissions >> 6
+        } else if credentials.egid == node.gid {
+            node.permissions >> 3
+        } else {
+            node.permissions
+        };
+        bits & access == access
+    }
+
+    /// Returns the information exposed by the stat() syscall
+    pub fn stat(&self) -> Stat {
+        let owner = unsafe { &*self.attributes() };
+
+        Stat {
+            inode: self.inode as u64,
+            kind: self.kind.as_user_type() as u64,
+            size: self.size as u64,
+            mode: self.kind.mode_bits() | owner.permissions,
+            nlink: self.links as u64,
+            uid: owner.uid as u64,
+            gid: owner.gid as u64,
+        }
+    }
 }
 
-/// Returns file node given its path in the FS
+/// Returns file node given its path in the FS, following symbolic links
 pub fn fopen(pathname: &str) -> Option<&mut VFS_Node> {
-    let parts: Vec<&str> = pathname.split('/').collect();
+    lookup(pathname, true).ok()
+}
 
+/// Returns file node given its absolute path in the FS. Symbolic links in the
+/// path are followed, the last component only if `follow` is set
+pub fn lookup(pathname: &str, follow: bool) -> Result<&'static mut VFS_Node, FsError> {
     // Only supporting absolute paths for now
-    if parts.len() < 2 || !parts[0].is_empty() {
-        return None;
+    if !pathname.starts_with('/') {
+        return Err(FsError::NotFound);
+    }
+
+    let credentials = task::credentials();
+    let mut links = 0;
+    let mut stack = Vec::new();
+    walk(&mut stack, pathname, follow, &mut links, &credentials)?;
+
+    let node = *stack.last().ok_or(FsError::NotFound)?;
+    unsafe { Ok(&mut *node) }
+}
+
+/// Resolves `path` starting from the directory on top of `stack`, which holds the
+/// directories from the root to the current one so ".." can go back up.
+/// The resolved node is left on top of the stack. Every directory searched must
+/// give execute permission to `credentials`
+fn walk(
+    stack: &mut Vec<*mut VFS_Node>,
+    path: &str,
+    follow: bool,
+    links: &mut usize,
+    credentials: &Credentials,
+) -> Result<(), FsError> {
+    if path.starts_with('/') {
+        let root = *FS_ROOT.get() as *mut VFS_Node;
+        stack.clear();
+        stack.push(root);
     }
 
-    let mut current_dir = unsafe { &**FS_ROOT.as_mut()? };
+    // Empty components come from repeated or trailing slashes, "/" is the root itself
+    let parts: Vec<&str> = path.split('/').filter(|p| !p.is_empty()).collect();
+    for (i, &part) in parts.iter().enumerate() {
+        let current = unsafe { &**stack.last().ok_or(FsError::NotFound)? };
+        if current.kind != Type::Dir && current.kind != Type::Mountpoint {
+            return Err(FsError::NotDir);
+        }
+        if !current.permits(credentials, Access::EXEC) {
+            return Err(FsError::Access);
+        }
+
+        match part {
+            "." => continue,
+            ".." => {
+                // The root is its own parent
+                if stack.len() > 1 {
+                    stack.pop();
+                }
+                continue;
+            }
+            _ => {}
+        }
+
+        let next = current.finddir(part).ok_or(FsError::NotFound)?;
+        let next_ref = unsafe { &*next };
+        let last = i == parts.len() - 1;
 
-    for i in 1..parts.len() {
-        // Reached file
-        if i == parts.len() - 1 {
-            let file = current_dir.finddir(parts[i])?;
-            return unsafe { Some(&mut *file) };
+        if next_ref.kind == Type::Symlink && (follow || !last) {
+            *links += 1;
+            if *links > SYMLOOP_MAX {
+                return Err(FsError::Loop);
+            }
+            // Relative targets start from the directory holding the link
+            let target = next_ref.readlink().ok_or(FsError::NotFound)?;
+            walk(stack, &target, true, links, credentials)?;
+        } else {
+            stack.push(next);
         }
-        current_dir = unsafe { &*(current_dir.finddir(parts[i])?) };
     }
 
-    None
+    Ok(())
+}
+
+/// Splits a path into its parent directory and the last component
+fn split_parent(pathname: &str) -> Option<(&str, &str)> {
+    let (parent, name) = pathname.trim_end_matches('/').rsplit_once('/')?;
+    if name.is_empty() || name == "." || name == ".." {
+        return None;
+    }
+
+    Some((if parent.is_empty() { "/" } else { parent }, name))
+}
+
+/// Returns the directory that holds the last component of the path and that component.
+/// The directory is going to be modified, so it must be writable by the current task
+fn parent_dir(pathname: &str) -> Result<(&'static mut VFS_Node, &str), FsError> {
+    let (parent, name) = split_parent(pathname).ok_or(FsError::NotFound)?;
+    let parent = lookup(parent, true)?;
+    if !parent.permits(&task::credentials(), Access::WRITE | Access::EXEC) {
+        return Err(FsError::Access);
+    }
+
+    Ok((parent, name))
+}
+
+/// Gives a newly created node to the current user
+fn set_creator(node: &mut VFS_Node) {
+    let credentials = task::credentials();
+    node.chown(credentials.euid, credentials.egid);
+}
+
+/// Creates a new node at the given path, the parent directory must exist
+pub fn fcreate(pathname: &str, kind: Type) -> Result<&'static mut VFS_Node, FsError> {
+    let (parent, name) = parent_dir(pathname)?;
+    let node = unsafe { &mut *parent.create(name, kind).ok_or(FsError::Failed)? };
+
+    set_creator(node);
+    Ok(node)
+}
+
+/// Removes the node at the given path. A symbolic link is removed itself, not its target
+pub fn funlink(pathname: &str) -> Result<(), FsError> {
+    let (parent, name) = parent_dir(pathname)?;
+    parent.unlink(name).ok_or(FsError::Failed)
+}
+
+/// Creates a symbolic link at `linkpath` pointing to `target`, which is not checked
+pub fn fsymlink(target: &str, linkpath: &str) -> Result<(), FsError> {
+    let (parent, name) = parent_dir(linkpath)?;
+    if parent.finddir(name).is_some() {
+        return Err(FsError::Failed);
+    }
+    let link = parent.symlink(name, target).ok_or(FsError::Failed)?;
+
+    set_creator(unsafe { &mut *link });
+    Ok(())
+}
+
+/// Creates a hard link at `newpath` to the node at `oldpath`. Both must be on the same
+/// filesystem, which decides if the node can be linked
+pub fn flink(oldpath: &str, newpath: &str) -> Result<(), FsError> {
+    let target = lookup(oldpath, false)?;
+    let (parent, name) = parent_dir(newpath)?;
+    if parent.finddir(name).is_some() {
+        return Err(FsError::Failed);
+    }
+    parent.link(name, target).ok_or(FsError::Failed)
+}
+
+/// Changes the permission bits of the node at the given path.
+/// Only its owner and the root user can do this
+pub fn fchmod(pathname: &str, permissions: u64) -> Result<(), FsError> {
+    let node = lookup(pathname, true)?;
+    let credentials = task::credentials();
+    if !credentials.is_root() && credentials.euid != unsafe { (*node.attributes()).uid } {
+        return Err(FsError::Failed);
+    }
+
+    node.chmod(permissions);
+    Ok(())
+}
+
+/// Changes the owner and group of the node at the given path, `None` keeps the
+/// current value. Only the root user can do this
+pub fn fchown(pathname: &str, uid: Option<u32>, gid: Option<u32>) -> Result<(), FsError> {
+    let node = lookup(pathname, true)?;
+    if !task::credentials().is_root() {
+        return Err(FsError::Failed);
+    }
+
+    let owner = unsafe { &*node.attributes() };
+    node.chown(uid.unwrap_or(owner.uid), gid.unwrap_or(owner.gid));
+    Ok(())
+}
+
+/// Entry of the mount table, listed in /proc/mounts
+#[derive(Debug, Clone)]
+pub struct Mount {
+    pub source: String,
+    pub target: String,
+    pub fstype: String,
+}
+
+fn add_mount(source: &str, target: &str, fstype: &str) {
+    MOUNTS.lock().push(Mount {
+        source: source.into(),
+        target: target.into(),
+        fstype: fstype.into(),
+    });
+}
+
+/// Mounts a filesystem from a block device and returns its root
+type MountFn = fn(Partition) -> Result<*mut VFS_Node, FsError>;
+
+/// Filesystems that can be mounted from a block device, by name
+const FILESYSTEMS: &[(&str, MountFn)] = &[("ext2", ext2::mount), ("fat", fat::mount)];
+
+/// Mounts the filesystem root `root` on the directory at `target`
+pub fn mount(target: &str, root: *mut VFS_Node) -> Option<()> {
+    let node = fopen(target)?;
+    if node.kind != Type::Dir || node.mount_point.is_some() {
+        return None;
+    }
+
+    node.kind = Type::Mountpoint;
+    node.mount_point = Some(root);
+    Some(())
+}
+
+/// Reads the filesystem on the block device at `source` (e.g. /dev/hda1) and
+/// returns its root and type. An empty `fstype` tries every known filesystem
+fn mount_fs(source: &str, fstype: &str) -> Result<(*mut VFS_Node, &'static str), FsError> {
+    let device = source
+        .strip_prefix("/dev/")
+        .and_then(blockdev::open)
+        .ok_or(FsError::NotFound)?;
+
+    FILESYSTEMS
+        .iter()
+        .filter(|(name, _)| fstype.is_empty() || *name == fstype)
+        .find_map(|(name, mount_fn)| Some((mount_fn(device.clone()).ok()?, *name)))
+        .ok_or(FsError::Invalid)
+}
+
+/// Mounts the block device at `source` on `target`
+pub fn mount_device(source: &str, target: &str, fstype: &str) -> Result<(), FsError> {
+    let (root, name) = mount_fs(source, fstype)?;
+    mount(target, root).ok_or(FsError::Invalid)?;
+
+    add_mount(source, target, name);
+    Ok(())
+}
+
+/// Replaces the initrd as the root directory with the filesystem on `source`.
+/// The device, temporary and process filesystems move to `/dev`, `/tmp` and `/proc`
+/// and the initrd to `/initrd`, when the new root has these directories
+pub fn mount_root(source: &str, fstype: &str) -> Result<(), FsError> {
+    let (root, root_type) = mount_fs(source, fstype)?;
+    let initrd_root = core::mem::replace(unsafe { FS_ROOT.get_mut() }, root);
+
+    MOUNTS.lock().clear();
+    add_mount(source, "/", root_type);
+    for (name, fstype) in INITRD_MOUNTS {
+        let target = alloc::format!("/{}", name);
+        let mounted = initrd::mounted(name).and_then(|mounted| mount(&target, mounted));
+        if mounted.is_some() {
+            add_mount(fstype, &target, fstype);
+        }
+    }
+    if mount("/initrd", initrd_root as *mut VFS_Node).is_some() {
+        add_mount("initrd", "/initrd", "initrd");
+    }
+    Ok(())
 }
 
 pub fn initialize_fs(mb_info: &'static MultibootInfo) {
@@ -126,9 +746,12 @@ pub fn initialize_fs(mb_info: &'static MultibootInfo) {
         size = initrd_end - initrd_location;
     }
 
-    let root = initialize_initrd(initrd_location as u64, size as usize);
+    let root = initrd::initialize_initrd(initrd_location as u64, size as usize);
 
-    unsafe {
-        FS_ROOT = Some(root);
+    FS_ROOT.init(root);
+
+    add_mount("initrd", "/", "initrd");
+    for (name, fstype) in INITRD_MOUNTS {
+        add_mount(fstype, &alloc::format!("/{}", name), fstype);
     }
 }
@@ -40,7 +40,7 @@ use crate::{
 
 #[panic_handler]
 pub fn panic_implementation(info: &PanicInfo) -> ! {
-    log!("{}", info);
+    error!("{}", info);
     loop {}
 }
 
@@ -54,7 +54,11 @@ pub fn hlt_loop() -> ! {
 pub extern "C" fn kmain(multiboot_magic: u64, multiboot_info: u64) {
     // Needed stuff
     let mb_info = unsafe { multiboot::MultibootInfo::read(multiboot_info) };
-    log!("{:#?}", mb_info);
+    // log=warn,drivers::ata=trace sets the default level and the level of some modules
+    if let Some(spec) = mb_info.cmdline_option("log") {
+        logging::configure(spec);
+    }
+    debug!("{:#?}", mb_info);
 
     unsafe {
         init_kernel(mb_info);
@@ -63,33 +67,39 @@ pub extern "C" fn kmain(multiboot_magic: u64, multiboot_info: u64) {
     // End needed stuff
 
     {
-        log!("multiboot_magic: 0x{:x}", multiboot_magic);
+        debug!("multiboot_magic: 0x{:x}", multiboot_magic);
 
-        log!("multiboot_info: 0x{:x}", multiboot_info);
+        debug!("multiboot_info: 0x{:x}", multiboot_info);
         unsafe {
             let mb_info = multiboot::MultibootInfo::read(multiboot_info);
-            //log!("{:?}", mb_info);
+            //info!("{:?}", mb_info);
 
             for i in 0..(mb_info.mmap_length / size_of::<multiboot::MmapEntry>() as u32) {
                 let mmap_entry = &*((mb_info.mmap_addr as u64 + arch::addressing::KERNEL_BASE)
                     as *const multiboot::MmapEntry)
                     .add(i as usize);
 
-                log!("Entry {}: {:?}", i, mmap_entry);
+                debug!("Entry {}: {:?}", i, mmap_entry);
             }
         }
     }
 
-    let fs_root = unsafe { &**filesystem::FS_ROOT.as_mut().unwrap() };
+    let fs_root = unsafe { &**filesystem::FS_ROOT.get() };
     for f in fs_root.readdir().unwrap() {
-        log!("{:?}", f);
+        debug!("{:?}", f);
     }
 
-    log!(":)\n\n");
+    debug!(":)");
 
     unsafe {
-        MULTIPROCESSING = Some(Multiprocessing::new());
-        MULTIPROCESSING.as_mut().unwrap().init("/init");
+        MULTIPROCESSING.init(Multiprocessing::new());
+        // The initrd keeps the init program when a disk is the root
+        let init = if filesystem::fopen("/init").is_some() {
+            "/init"
+        } else {
+            "/initrd/init"
+        };
+        MULTIPROCESSING.get_mut().init(init);
     }
 
     hlt_loop()
@@ -97,26 +107,51 @@ pub extern "C" fn kmain(multiboot_magic: u64, multiboot_info: u64) {
 
 unsafe fn init_kernel(multiboot: &'static MultibootInfo) {
     arch::gdt::init_tss();
-    log!("Initialized TSS");
+    info!("Initialized TSS");
 
     arch::interrupts::init_idt();
-    log!("Initialized IDT");
+    info!("Initialized IDT");
 
     arch::paging::init_pfa(multiboot);
-    log!("Initialized PageFrameAllocator");
+    info!("Initialized PageFrameAllocator");
 
     arch::pic::PICS.lock().initialize();
     arch::pic::Timer::init_timer(1000); // 1 interrupt per ms
-    log!("Initialized PIC and Timer");
+    info!("Initialized PIC and Timer");
+
+    drivers::keyboard::init();
 
     arch::interrupts::enable();
     let allocator =
         arch::paging::PageAllocator::new_kernel(511, 510, arch::addressing::KERNEL_BASE);
     mm::ALLOCATOR.lock().init(allocator, 6);
-    log!("Initialized heap allocator");
+    info!("Initialized heap allocator");
+
+    arch::acpi::init();
+    arch::apic::init();
+    arch::clock::init();
+    arch::rtc::init();
+    arch::tick::init();
+    arch::smp::init();
+    drivers::pci::init();
 
     filesystem::initialize_fs(multiboot);
-    log!("Initialized filesystem");
+    info!("Initialized filesystem");
+
+    drivers::ata::init();
+    info!("Initialized ATA drives");
+
+    drivers::virtio_blk::init();
+    info!("Initialized virtio block devices");
+
+    // root=/dev/hda1 mounts a disk as the root directory, rootfstype= picks its filesystem
+    if let Some(root) = multiboot.cmdline_option("root") {
+        let fstype = multiboot.cmdline_option("rootfstype").unwrap_or("");
+        match filesystem::mount_root(root, fstype) {
+            Ok(()) => info!("Mounted {} as root", root),
+            Err(_) => warn!("Failed to mount {} as root, keeping the initrd", root),
+        }
+    }
 
     let fb_addr = mm::ALLOCATOR
         .lock()
@@ -131,5 +166,6 @@ unsafe fn init_kernel(multiboot: &'static MultibootInfo) {
         multiboot.framebuffer.height as usize,
         multiboot.framebuffer.bpp,
     );
-    log!("Initialized framebuffer");
+    logging::init_console();
+    info!("Initialized framebuffer console");
 }
@@ -1,51 +1,304 @@
-use core::fmt;
-use core::sync::atomic;
+use core::fmt::{self, Write};
 
-/// A formatter object
-pub struct Writer(bool);
+use crate::{
+    arch::pic::Timer,
+    drivers::{
+        console::{Console, CONSOLE},
+        framebuffer::Rgb,
+        serial::Serial,
+    },
+    sync::IrqSpinMutex,
+};
 
-/// A primitive lock for the logging output
-///
-/// This is not really a lock. Since there is no threading at the moment, all
-/// it does is prevent writing when a collision would occur.
-static LOGGING_LOCK: atomic::AtomicBool = atomic::AtomicBool::new(false);
+/// Size of the kernel log ring buffer, the oldest messages are overwritten
+const LOG_SIZE: usize = 64 * 1024;
+/// Maximum number of per-module filters of the `log=` option
+const MAX_FILTERS: usize = 16;
+
+/// Also taken by interrupt and exception handlers logging messages
+static LOG: IrqSpinMutex<Log> = IrqSpinMutex::new(Log {
+    ring: RingBuffer {
+        data: [0; LOG_SIZE],
+        start: 0,
+        len: 0,
+        written: 0,
+    },
+    default_level: Level::Info,
+    filters: [("", Level::Info); MAX_FILTERS],
+    filter_count: 0,
+});
 
-impl Writer {
-    /// Obtain a logger for the specified module
-    pub fn get(module: &str) -> Writer {
-        // This "acquires" the lock (actually just disables output if paralel writes are attempted
-        let mut ret = Writer(!LOGGING_LOCK.swap(true, atomic::Ordering::Acquire));
+/// Severity of a message, a filter level also shows the less verbose levels
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error = 1,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
 
-        // Print the module name before returning (prefixes all messages)
-        {
-            use core::fmt::Write;
-            let _ = write!(&mut ret, "[{module}] ");
+impl Level {
+    pub fn name(self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
         }
+    }
 
-        ret
+    /// Parses a level name, in any case
+    pub fn parse(name: &str) -> Option<Level> {
+        [
+            Level::Error,
+            Level::Warn,
+            Level::Info,
+            Level::Debug,
+            Level::Trace,
+        ]
+        .into_iter()
+        .find(|level| level.name().eq_ignore_ascii_case(name))
+    }
+
+    fn color(self) -> Rgb {
+        match self {
+            Level::Error => Rgb::new(0xFF, 0x55, 0x55),
+            Level::Warn => Rgb::new(0xFF, 0xFF, 0x55),
+            Level::Info => Rgb::new(0xAA, 0xAA, 0xAA),
+            Level::Debug | Level::Trace => Rgb::new(0x55, 0x55, 0xFF),
+        }
     }
 }
 
-impl ::core::ops::Drop for Writer {
-    fn drop(&mut self) {
-        // Write a terminating newline before releasing the lock
-        {
-            use core::fmt::Write;
-            let _ = writeln!(self);
+/// Byte ring buffer keeping the most recent log lines.
+/// It is a static array so it can be used before the heap is initialized
+struct RingBuffer {
+    data: [u8; LOG_SIZE],
+    /// Index of the oldest byte
+    start: usize,
+    len: usize,
+    /// Bytes pushed since boot, the position in the log of the byte after the newest
+    written: usize,
+}
+
+impl RingBuffer {
+    fn push(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            let end = (self.start + self.len) % LOG_SIZE;
+            self.data[end] = byte;
+            if self.len == LOG_SIZE {
+                self.start = (self.start + 1) % LOG_SIZE;
+            } else {
+                self.len += 1;
+            }
         }
-        // On drop, "release" the lock
-        if self.0 {
-            LOGGING_LOCK.store(false, atomic::Ordering::Release);
+        self.written += bytes.len();
+    }
+
+    /// Position in the log of the oldest byte still in the ring
+    fn oldest(&self) -> usize {
+        self.written - self.len
+    }
+
+    fn byte(&self, index: usize) -> u8 {
+        self.data[(self.start + index) % LOG_SIZE]
+    }
+
+    /// Copies the bytes starting at a position in the log, or at the oldest
+    /// byte if it was overwritten. Returns how many were copied
+    fn read(&self, position: usize, buffer: &mut [u8]) -> usize {
+        let index = position.saturating_sub(self.oldest());
+        let size = buffer.len().min(self.len.saturating_sub(index));
+        for (i, byte) in buffer[..size].iter_mut().enumerate() {
+            *byte = self.byte(index + i);
         }
+        size
     }
 }
 
-impl fmt::Write for Writer {
+struct Log {
+    ring: RingBuffer,
+    /// Level of the modules without a filter
+    default_level: Level,
+    /// Module path prefixes and their levels
+    filters: [(&'static str, Level); MAX_FILTERS],
+    filter_count: usize,
+}
+
+impl Log {
+    /// The longest filter matching the module decides its level
+    fn enabled(&self, level: Level, module: &str) -> bool {
+        let module = module.strip_prefix("kernel::").unwrap_or(module);
+        let max_level = self.filters[..self.filter_count]
+            .iter()
+            .filter(|(prefix, _)| {
+                module
+                    .strip_prefix(prefix)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(self.default_level, |&(_, level)| level);
+
+        level <= max_level
+    }
+
+    /// Filters past the first `MAX_FILTERS` are ignored
+    fn add_filter(&mut self, module: &'static str, level: Level) {
+        if self.filter_count < MAX_FILTERS {
+            self.filters[self.filter_count] = (module, level);
+            self.filter_count += 1;
+        }
+    }
+}
+
+/// Writes a log line to every output at once
+struct LineWriter<'a> {
+    ring: Option<&'a mut RingBuffer>,
+    console: Option<&'a mut Console>,
+}
+
+impl Write for LineWriter<'_> {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        // If the lock is owned by this instance, then we can safely write to the output
-        if self.0 {
-            crate::drivers::serial::Serial::puts(s);
+        if let Some(ring) = self.ring.as_mut() {
+            ring.push(s.as_bytes());
         }
+        if let Some(console) = self.console.as_mut() {
+            let _ = console.write_str(s);
+        }
+        Serial::puts(s);
         Ok(())
     }
 }
+
+/// Applies the `log=` command line option, a comma separated list of a
+/// default level and `module=level` filters, e.g. `log=warn,drivers::ata=trace`
+pub fn configure(spec: &'static str) {
+    let mut log = LOG.lock();
+
+    for directive in spec.split(',').filter(|d| !d.is_empty()) {
+        match directive.split_once('=') {
+            None => match Level::parse(directive) {
+                Some(level) => log.default_level = level,
+                // A module without a level shows every message, like `env_logger`
+                None => log.add_filter(directive, Level::Trace),
+            },
+            Some((module, level)) => {
+                if let Some(level) = Level::parse(level) {
+                    log.add_filter(module, level);
+                }
+            }
+        }
+    }
+}
+
+/// Records a message of a module, used by the logging macros.
+///
+/// Lines are stored in the ring buffer and written to the serial port and the
+/// framebuffer console. When the log is already locked, e.g. by an exception
+/// raised while logging, the message only goes to the serial port
+pub fn log(level: Level, module: &'static str, args: fmt::Arguments) {
+    let ms = Timer::uptime();
+
+    let Some(mut log) = LOG.try_lock() else {
+        let mut serial = LineWriter {
+            ring: None,
+            console: None,
+        };
+        let _ = writeln!(serial, "{} {}: {}", level.name(), module, args);
+        return;
+    };
+    if !log.enabled(level, module) {
+        return;
+    }
+
+    let mut console = CONSOLE.try_lock();
+    let console = console.as_mut().and_then(|console| console.as_mut());
+    let mut writer = LineWriter {
+        ring: Some(&mut log.ring),
+        console,
+    };
+
+    let _ = write!(writer, "[{:>5}.{:03}] ", ms / 1000, ms % 1000);
+    if let Some(console) = writer.console.as_mut() {
+        console.set_color(level.color());
+    }
+    let _ = write!(writer, "{:<5}", level.name());
+    if let Some(console) = writer.console.as_mut() {
+        console.set_color(Level::Info.color());
+    }
+    let _ = writeln!(writer, " {}: {}", module, args);
+}
+
+/// Copies the log starting at a position counted in bytes since boot, so it does not
+/// move when the ring wraps. Returns None at the end of the log
+pub fn read(position: usize, buffer: &mut [u8]) -> Option<usize> {
+    match LOG.lock().ring.read(position, buffer) {
+        0 => None,
+        size => Some(size),
+    }
+}
+
+/// Position reads continue from: `position`, or the oldest byte still in the log
+/// for a reader which fell behind
+pub fn seek(position: usize) -> usize {
+    position.max(LOG.lock().ring.oldest())
+}
+
+/// Creates the framebuffer console and shows the most recent lines of the log on it
+pub fn init_console() {
+    Console::init();
+
+    let log = LOG.lock();
+    let mut console = CONSOLE.lock();
+    let Some(console) = console.as_mut() else {
+        return;
+    };
+
+    // Start after the newline preceding the last screen of lines
+    let ring = &log.ring;
+    let mut lines = 0;
+    let mut start = ring.len;
+    while start > 0 {
+        if ring.byte(start - 1) == b'\n' {
+            lines += 1;
+            if lines > console.rows() {
+                break;
+            }
+        }
+        start -= 1;
+    }
+
+    let mut i = start;
+    while i < ring.len {
+        // Lines start with the timestamp, followed by the level
+        let mut header = [0; 32];
+        let size = ring.read(i, &mut header);
+        let header = &header[..size];
+        let level = header.iter().position(|&b| b == b']').and_then(|end| {
+            let name = core::str::from_utf8(header.get(end + 2..end + 7)?).ok()?;
+            Some((end + 2, Level::parse(name.trim_end())?))
+        });
+        if let Some((level_start, level)) = level {
+            header[..level_start]
+                .iter()
+                .for_each(|&byte| console.write_byte(byte));
+            console.set_color(level.color());
+            header[level_start..level_start + 5]
+                .iter()
+                .for_each(|&byte| console.write_byte(byte));
+            console.set_color(Level::Info.color());
+            i += level_start + 5;
+        }
+
+        while i < ring.len {
+            let byte = ring.byte(i);
+            console.write_byte(byte);
+            i += 1;
+            if byte == b'\n' {
+                break;
+            }
+        }
+    }
+}
@@ -1,11 +1,34 @@
-/// A very primitive logging macro
+/// Logs a message at the given level
 ///
-/// Obtaines a logger instance (locking the log channel) with the current module name passed
-/// then passes the standard format! arguments to it
-macro_rules! log{
-	( $($arg:tt)* ) => ({
-		// Import the Writer trait (required by write!)
-		use core::fmt::Write;
-		let _ = write!(&mut logging::Writer::get(module_path!()), $($arg)*);
+/// Passes the module name and the standard format! arguments to the kernel log,
+/// which drops the message when the level is filtered out for the module
+macro_rules! log_at{
+	( $level:expr, $($arg:tt)* ) => ({
+		logging::log($level, module_path!(), format_args!($($arg)*));
 	})
 }
+
+/// Logs a message at the info level
+macro_rules! log{
+	( $($arg:tt)* ) => (log_at!(logging::Level::Info, $($arg)*))
+}
+
+macro_rules! error{
+	( $($arg:tt)* ) => (log_at!(logging::Level::Error, $($arg)*))
+}
+
+macro_rules! warn{
+	( $($arg:tt)* ) => (log_at!(logging::Level::Warn, $($arg)*))
+}
+
+macro_rules! info{
+	( $($arg:tt)* ) => (log_at!(logging::Level::Info, $($arg)*))
+}
+
+macro_rules! debug{
+	( $($arg:tt)* ) => (log_at!(logging::Level::Debug, $($arg)*))
+}
+
+macro_rules! trace{
+	( $($arg:tt)* ) => (log_at!(logging::Level::Trace, $($arg)*))
+}
@@ -36,6 +36,14 @@ impl BumpAllocator {
         self.heap_end = self.heap_start + no_pages * PAGE_SIZE as usize - 1;
         self.next = self.heap_start;
     }
+
+    /// Size of the heap and the number of bytes handed out since it was last empty
+    pub fn usage(&self) -> (usize, usize) {
+        (
+            self.heap_end + 1 - self.heap_start,
+            self.next - self.heap_start,
+        )
+    }
 }
 
 unsafe impl GlobalAlloc for SpinMutex<BumpAllocator> {
@@ -34,6 +34,27 @@ impl MultibootInfo {
         //TODO: check magic numbers and flags
         &*((info_address + crate::arch::addressing::KERNEL_BASE) as *const Self) as _
     }
+
+    /// Kernel command line given by the bootloader, if flag bit 2 is set
+    pub fn cmdline(&self) -> Option<&'static str> {
+        if self.flags & (1 << 2) == 0 {
+            return None;
+        }
+        let start = (self.cmdline as u64 + crate::arch::addressing::KERNEL_BASE) as *const u8;
+        unsafe {
+            let len = (0..).take_while(|&i| *start.add(i) != 0).count();
+            core::str::from_utf8(core::slice::from_raw_parts(start, len)).ok()
+        }
+    }
+
+    /// Value of a `name=value` option of the command line
+    pub fn cmdline_option(&self, name: &str) -> Option<&'static str> {
+        self.cmdline()?
+            .split_ascii_whitespace()
+            .filter_map(|option| option.split_once('='))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value)
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -1,8 +1,15 @@
 #![allow(dead_code)]
 use core::{
+    any::type_name,
     cell::UnsafeCell,
+    mem::{ManuallyDrop, MaybeUninit},
     ops::{Deref, DerefMut},
-    sync::atomic::{AtomicBool, Ordering},
+    sync::atomic::{AtomicBool, AtomicU64, AtomicU8, AtomicUsize, Ordering},
+};
+
+use crate::{
+    arch::interrupts,
+    task::{self, WaitQueue},
 };
 
 /// Busy waiting based mutex
@@ -59,7 +66,7 @@ impl<T> SpinMutex<T> {
     ///
     /// The returned value may be dereferenced for data access
     /// and the lock will be dropped when the guard falls out of scope.
-    pub fn lock(&self) -> MutexGuard<T> {
+    pub fn lock(&self) -> MutexGuard<'_, T> {
         self.obtain_lock();
         MutexGuard {
             lock: &self.lock,
@@ -69,7 +76,7 @@ impl<T> SpinMutex<T> {
 
     /// Tries to lock the mutex. If it is already locked, it will return None. Otherwise it returns
     /// a guard within Some.
-    pub fn try_lock(&self) -> Option<MutexGuard<T>> {
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
         if !self
             .lock
             .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
@@ -104,3 +111,704 @@ impl<'a, T: ?Sized> Drop for MutexGuard<'a, T> {
         self.lock.store(false, Ordering::Release);
     }
 }
+
+/// Spinlock that disables interrupts while it is held
+///
+/// Data shared with an interrupt handler must use this lock: if the handler
+/// interrupted code holding a plain `SpinMutex` it would spin forever.
+/// The interrupt flag is restored when the guard is dropped, so these locks can be nested.
+#[derive(Debug)]
+pub struct IrqSpinMutex<T: ?Sized> {
+    inner: SpinMutex<T>,
+}
+
+/// A guard to which the protected data can be accessed
+///
+/// When the guard falls out of scope it releases the lock, then enables
+/// interrupts again if they were enabled when the lock was taken.
+#[derive(Debug)]
+pub struct IrqMutexGuard<'a, T: ?Sized + 'a> {
+    guard: ManuallyDrop<MutexGuard<'a, T>>,
+    interrupts_enabled: bool,
+}
+
+impl<T> IrqSpinMutex<T> {
+    /// Creates a new interrupt-safe spinlock wrapping the supplied data.
+    pub const fn new(user_data: T) -> IrqSpinMutex<T> {
+        IrqSpinMutex {
+            inner: SpinMutex::new(user_data),
+        }
+    }
+
+    /// Consumes this mutex, returning the underlying data.
+    pub fn into_inner(self) -> T {
+        self.inner.into_inner()
+    }
+
+    /// Disables interrupts, locks the spinlock and returns a guard.
+    pub fn lock(&self) -> IrqMutexGuard<'_, T> {
+        let interrupts_enabled = interrupts::are_enabled();
+        interrupts::disable();
+
+        IrqMutexGuard {
+            guard: ManuallyDrop::new(self.inner.lock()),
+            interrupts_enabled,
+        }
+    }
+
+    /// Tries to lock the mutex. If it is already locked, it will return None and
+    /// leave the interrupt flag unchanged. Otherwise it returns a guard within Some.
+    pub fn try_lock(&self) -> Option<IrqMutexGuard<'_, T>> {
+        let interrupts_enabled = interrupts::are_enabled();
+        interrupts::disable();
+
+        match self.inner.try_lock() {
+            Some(guard) => Some(IrqMutexGuard {
+                guard: ManuallyDrop::new(guard),
+                interrupts_enabled,
+            }),
+            None => {
+                if interrupts_enabled {
+                    interrupts::enable();
+                }
+                None
+            }
+        }
+    }
+}
+
+impl<'a, T: ?Sized> Deref for IrqMutexGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for IrqMutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<'a, T: ?Sized> Drop for IrqMutexGuard<'a, T> {
+    /// Releases the lock before interrupts can be enabled again.
+    fn drop(&mut self) {
+        unsafe { ManuallyDrop::drop(&mut self.guard) };
+        if self.interrupts_enabled {
+            interrupts::enable();
+        }
+    }
+}
+
+/// Fair spinlock, waiters get the lock in the order they asked for it
+///
+/// Each waiter takes a ticket and spins until it is served, so no waiter
+/// can be starved by others repeatedly taking the lock.
+#[derive(Debug)]
+pub struct TicketMutex<T: ?Sized> {
+    next_ticket: AtomicUsize,
+    now_serving: AtomicUsize,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Sync for TicketMutex<T> {}
+unsafe impl<T: ?Sized + Send> Send for TicketMutex<T> {}
+
+/// A guard to which the protected data can be accessed
+///
+/// When the guard falls out of scope the next ticket is served.
+#[derive(Debug)]
+pub struct TicketMutexGuard<'a, T: ?Sized + 'a> {
+    now_serving: &'a AtomicUsize,
+    data: &'a mut T,
+}
+
+impl<T> TicketMutex<T> {
+    /// Creates a new ticket lock wrapping the supplied data.
+    pub const fn new(user_data: T) -> TicketMutex<T> {
+        TicketMutex {
+            next_ticket: AtomicUsize::new(0),
+            now_serving: AtomicUsize::new(0),
+            data: UnsafeCell::new(user_data),
+        }
+    }
+
+    /// Consumes this mutex, returning the underlying data.
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+
+    /// Takes a ticket, waits for it to be served and returns a guard.
+    pub fn lock(&self) -> TicketMutexGuard<'_, T> {
+        let ticket = self.next_ticket.fetch_add(1, Ordering::Relaxed);
+        while self.now_serving.load(Ordering::Acquire) != ticket {
+            core::hint::spin_loop()
+        }
+
+        TicketMutexGuard {
+            now_serving: &self.now_serving,
+            data: unsafe { &mut *self.data.get() },
+        }
+    }
+
+    /// Tries to lock the mutex. If anyone holds or waits for the lock, it will
+    /// return None. Otherwise it returns a guard within Some.
+    pub fn try_lock(&self) -> Option<TicketMutexGuard<'_, T>> {
+        let ticket = self.now_serving.load(Ordering::Acquire);
+        self.next_ticket
+            .compare_exchange(ticket, ticket + 1, Ordering::Acquire, Ordering::Relaxed)
+            .ok()?;
+
+        Some(TicketMutexGuard {
+            now_serving: &self.now_serving,
+            data: unsafe { &mut *self.data.get() },
+        })
+    }
+}
+
+impl<'a, T: ?Sized> Deref for TicketMutexGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &*self.data
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for TicketMutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut *self.data
+    }
+}
+
+impl<'a, T: ?Sized> Drop for TicketMutexGuard<'a, T> {
+    /// Serves the next ticket.
+    fn drop(&mut self) {
+        self.now_serving.fetch_add(1, Ordering::Release);
+    }
+}
+
+/// Reader-writer spinlock, any number of readers or a single writer
+///
+/// The state holds the number of readers, with the highest bit set while a
+/// writer holds the lock. Writers are not favored, so constant reading can delay them.
+#[derive(Debug)]
+pub struct RwSpinLock<T: ?Sized> {
+    state: AtomicUsize,
+    data: UnsafeCell<T>,
+}
+
+const WRITER: usize = 1 << (usize::BITS - 1);
+
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwSpinLock<T> {}
+unsafe impl<T: ?Sized + Send> Send for RwSpinLock<T> {}
+
+/// A guard giving shared access to the protected data
+#[derive(Debug)]
+pub struct RwReadGuard<'a, T: ?Sized + 'a> {
+    state: &'a AtomicUsize,
+    data: &'a T,
+}
+
+/// A guard giving exclusive access to the protected data
+#[derive(Debug)]
+pub struct RwWriteGuard<'a, T: ?Sized + 'a> {
+    state: &'a AtomicUsize,
+    data: &'a mut T,
+}
+
+impl<T> RwSpinLock<T> {
+    /// Creates a new reader-writer lock wrapping the supplied data.
+    pub const fn new(user_data: T) -> RwSpinLock<T> {
+        RwSpinLock {
+            state: AtomicUsize::new(0),
+            data: UnsafeCell::new(user_data),
+        }
+    }
+
+    /// Consumes this lock, returning the underlying data.
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+
+    /// Waits until no writer holds the lock and returns a shared guard.
+    pub fn read(&self) -> RwReadGuard<'_, T> {
+        loop {
+            if let Some(guard) = self.try_read() {
+                return guard;
+            }
+            while self.state.load(Ordering::Relaxed) & WRITER != 0 {
+                core::hint::spin_loop()
+            }
+        }
+    }
+
+    /// Returns a shared guard within Some, or None if a writer holds the lock.
+    pub fn try_read(&self) -> Option<RwReadGuard<'_, T>> {
+        let state = self.state.fetch_add(1, Ordering::Acquire);
+        if state & WRITER != 0 {
+            self.state.fetch_sub(1, Ordering::Release);
+            return None;
+        }
+
+        Some(RwReadGuard {
+            state: &self.state,
+            data: unsafe { &*self.data.get() },
+        })
+    }
+
+    /// Waits until nobody holds the lock and returns an exclusive guard.
+    pub fn write(&self) -> RwWriteGuard<'_, T> {
+        loop {
+            if let Some(guard) = self.try_write() {
+                return guard;
+            }
+            while self.state.load(Ordering::Relaxed) != 0 {
+                core::hint::spin_loop()
+            }
+        }
+    }
+
+    /// Returns an exclusive guard within Some, or None if the lock is held.
+    pub fn try_write(&self) -> Option<RwWriteGuard<'_, T>> {
+        self.state
+            .compare_exchange(0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .ok()?;
+
+        Some(RwWriteGuard {
+            state: &self.state,
+            data: unsafe { &mut *self.data.get() },
+        })
+    }
+}
+
+impl<'a, T: ?Sized> Deref for RwReadGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.data
+    }
+}
+
+impl<'a, T: ?Sized> Drop for RwReadGuard<'a, T> {
+    /// Removes this reader from the count.
+    fn drop(&mut self) {
+        self.state.fetch_sub(1, Ordering::Release);
+    }
+}
+
+impl<'a, T: ?Sized> Deref for RwWriteGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &*self.data
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for RwWriteGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut *self.data
+    }
+}
+
+impl<'a, T: ?Sized> Drop for RwWriteGuard<'a, T> {
+    /// Clears the writer bit, letting readers and writers in.
+    fn drop(&mut self) {
+        self.state.fetch_and(!WRITER, Ordering::Release);
+    }
+}
+
+/// Owner of an unlocked `Mutex`
+const NO_OWNER: u64 = 0;
+
+/// Value identifying the running task as the owner of a `Mutex`
+fn lock_owner() -> u64 {
+    // The kernel before the first task is its own owner
+    task::current_id().map_or(u64::MAX, |id| id + 1)
+}
+
+/// Sleeping mutex, tasks waiting for it are blocked instead of spinning
+///
+/// Must not be taken by interrupt handlers, which cannot sleep.
+/// With the `lock-debug` feature, locking a mutex the task already holds panics
+/// instead of blocking it forever.
+pub struct Mutex<T: ?Sized> {
+    locked: AtomicBool,
+    /// Task holding the lock, see `lock_owner`
+    owner: AtomicU64,
+    waiters: WaitQueue,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Sync for Mutex<T> {}
+unsafe impl<T: ?Sized + Send> Send for Mutex<T> {}
+
+/// A guard to which the data of a `Mutex` can be accessed
+///
+/// When the guard falls out of scope the lock is released and the
+/// longest waiting task is woken.
+pub struct LockGuard<'a, T: ?Sized + 'a> {
+    mutex: &'a Mutex<T>,
+    data: &'a mut T,
+}
+
+impl<T> Mutex<T> {
+    /// Creates a new sleeping mutex wrapping the supplied data.
+    pub const fn new(user_data: T) -> Mutex<T> {
+        Mutex {
+            locked: AtomicBool::new(false),
+            owner: AtomicU64::new(NO_OWNER),
+            waiters: WaitQueue::new(),
+            data: UnsafeCell::new(user_data),
+        }
+    }
+
+    /// Consumes this mutex, returning the underlying data.
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+
+    fn acquire(&self) -> bool {
+        self.locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    fn guard(&self) -> LockGuard<'_, T> {
+        self.owner.store(lock_owner(), Ordering::Relaxed);
+        LockGuard {
+            mutex: self,
+            data: unsafe { &mut *self.data.get() },
+        }
+    }
+
+    /// Locks the mutex, blocking the task until it is released, and returns a guard.
+    pub fn lock(&self) -> LockGuard<'_, T> {
+        if !self.acquire() {
+            if cfg!(feature = "lock-debug") && self.owner.load(Ordering::Relaxed) == lock_owner() {
+                panic!("recursive lock of a Mutex by task {:?}", task::current_id());
+            }
+            self.waiters.wait_while(|| !self.acquire());
+        }
+        self.guard()
+    }
+
+    /// Tries to lock the mutex. If it is already locked, it will return None. Otherwise it returns
+    /// a guard within Some.
+    pub fn try_lock(&self) -> Option<LockGuard<'_, T>> {
+        self.acquire().then(|| self.guard())
+    }
+}
+
+impl<'a, T: ?Sized> Deref for LockGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &*self.data
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for LockGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut *self.data
+    }
+}
+
+impl<'a, T: ?Sized> Drop for LockGuard<'a, T> {
+    /// Releases the lock and wakes the next waiting task.
+    fn drop(&mut self) {
+        self.mutex.owner.store(NO_OWNER, Ordering::Relaxed);
+        self.mutex.locked.store(false, Ordering::Release);
+        self.mutex.waiters.wake_one();
+    }
+}
+
+/// Counting semaphore, tasks acquiring it while the count is zero are blocked
+///
+/// `release` can be called from interrupt handlers, e.g. to count completed requests.
+pub struct Semaphore {
+    count: AtomicUsize,
+    waiters: WaitQueue,
+}
+
+impl Semaphore {
+    /// Creates a semaphore with `count` available permits.
+    pub const fn new(count: usize) -> Semaphore {
+        Semaphore {
+            count: AtomicUsize::new(count),
+            waiters: WaitQueue::new(),
+        }
+    }
+
+    /// Number of permits that can be acquired without blocking.
+    pub fn available(&self) -> usize {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Takes a permit if one is available.
+    pub fn try_acquire(&self) -> bool {
+        self.count
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |count| {
+                count.checked_sub(1)
+            })
+            .is_ok()
+    }
+
+    /// Takes a permit, blocking the task until one is available.
+    pub fn acquire(&self) {
+        self.waiters.wait_while(|| !self.try_acquire());
+    }
+
+    /// Like `acquire`, giving up after `timeout` milliseconds.
+    /// Returns whether a permit was taken
+    pub fn acquire_timeout(&self, timeout: u64) -> bool {
+        self.waiters
+            .wait_while_timeout(|| !self.try_acquire(), Some(timeout))
+    }
+
+    /// Gives a permit back and wakes a waiting task.
+    pub fn release(&self) {
+        self.count.fetch_add(1, Ordering::Release);
+        self.waiters.wake_one();
+    }
+}
+
+/// Condition variable, blocks tasks until another task or an interrupt handler notifies them
+///
+/// Waiting releases the `Mutex` guarding the condition and takes it again
+/// before returning. Wake ups can be spurious, so the condition must be checked again:
+/// `wait_while` does it.
+pub struct Condvar {
+    waiters: WaitQueue,
+}
+
+impl Condvar {
+    pub const fn new() -> Condvar {
+        Condvar {
+            waiters: WaitQueue::new(),
+        }
+    }
+
+    /// Releases the mutex, blocks until notified and locks the mutex again.
+    pub fn wait<'a, T>(&self, guard: LockGuard<'a, T>) -> LockGuard<'a, T> {
+        self.wait_timeout(guard, None).0
+    }
+
+    /// Like `wait`, giving up after `timeout` milliseconds if there is one.
+    /// The returned boolean is false on timeout
+    pub fn wait_timeout<'a, T>(
+        &self,
+        guard: LockGuard<'a, T>,
+        timeout: Option<u64>,
+    ) -> (LockGuard<'a, T>, bool) {
+        let deadline = timeout.map(|ms| crate::arch::pic::Timer::uptime() + ms);
+        let mutex = guard.mutex;
+
+        // Registered before unlocking so a notification right after is not lost
+        let woken = self.waiters.enqueue();
+        drop(guard);
+
+        let notified = task::block(&woken, deadline);
+        if !notified {
+            self.waiters.dequeue(&woken);
+        }
+        (mutex.lock(), notified)
+    }
+
+    /// Blocks while `condition` returns true for the protected data.
+    pub fn wait_while<'a, T>(
+        &self,
+        mut guard: LockGuard<'a, T>,
+        mut condition: impl FnMut(&mut T) -> bool,
+    ) -> LockGuard<'a, T> {
+        while condition(&mut *guard) {
+            guard = self.wait(guard);
+        }
+        guard
+    }
+
+    /// Wakes the task waiting the longest.
+    pub fn notify_one(&self) {
+        self.waiters.wake_one();
+    }
+
+    /// Wakes every waiting task.
+    pub fn notify_all(&self) {
+        self.waiters.wake_all();
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+const INCOMPLETE: u8 = 0;
+const RUNNING: u8 = 1;
+const COMPLETE: u8 = 2;
+
+/// Cell initialized once, then only read
+///
+/// Dereferencing it before initialization panics with the name of the type,
+/// so globals used too early during boot are caught instead of reading garbage.
+/// A lock can be put inside for state that changes after initialization,
+/// e.g. `Once<SpinMutex<T>>`.
+pub struct Once<T> {
+    state: AtomicU8,
+    data: UnsafeCell<MaybeUninit<T>>,
+}
+
+unsafe impl<T: Send + Sync> Sync for Once<T> {}
+unsafe impl<T: Send> Send for Once<T> {}
+
+impl<T> Once<T> {
+    /// Creates an uninitialized cell.
+    pub const fn new() -> Once<T> {
+        Once {
+            state: AtomicU8::new(INCOMPLETE),
+            data: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    /// Initializes the cell with `init` if it is not yet, then returns the value.
+    /// Callers racing the initialization wait for it to complete
+    pub fn call_once(&self, init: impl FnOnce() -> T) -> &T {
+        if self
+            .state
+            .compare_exchange(INCOMPLETE, RUNNING, Ordering::Acquire, Ordering::Acquire)
+            .is_ok()
+        {
+            unsafe { (*self.data.get()).write(init()) };
+            self.state.store(COMPLETE, Ordering::Release);
+        }
+
+        while self.state.load(Ordering::Acquire) != COMPLETE {
+            core::hint::spin_loop()
+        }
+        unsafe { self.get_unchecked() }
+    }
+
+    /// Initializes the cell, panics if it already was.
+    pub fn set(&self, value: T) {
+        let mut value = Some(value);
+        self.call_once(|| value.take().unwrap());
+        if value.is_some() {
+            panic!("{} initialized twice", type_name::<T>());
+        }
+    }
+
+    /// Returns the value, None before initialization.
+    pub fn get(&self) -> Option<&T> {
+        self.is_completed().then(|| unsafe { self.get_unchecked() })
+    }
+
+    pub fn is_completed(&self) -> bool {
+        self.state.load(Ordering::Acquire) == COMPLETE
+    }
+
+    unsafe fn get_unchecked(&self) -> &T {
+        (*self.data.get()).assume_init_ref()
+    }
+
+    /// Panics with the name of the type when the cell is not initialized
+    fn expect_completed(&self) {
+        if !self.is_completed() {
+            panic!("{} used before initialization", type_name::<T>());
+        }
+    }
+}
+
+impl<T> Deref for Once<T> {
+    type Target = T;
+    /// Panics if the cell is not initialized.
+    fn deref(&self) -> &T {
+        self.expect_completed();
+        unsafe { self.get_unchecked() }
+    }
+}
+
+impl<T> Drop for Once<T> {
+    fn drop(&mut self) {
+        if *self.state.get_mut() == COMPLETE {
+            unsafe { self.data.get_mut().assume_init_drop() };
+        }
+    }
+}
+
+/// Value computed by `init` the first time it is dereferenced
+pub struct Lazy<T, F = fn() -> T> {
+    cell: Once<T>,
+    init: F,
+}
+
+unsafe impl<T: Send + Sync, F: Sync> Sync for Lazy<T, F> {}
+
+impl<T, F: Fn() -> T> Lazy<T, F> {
+    pub const fn new(init: F) -> Lazy<T, F> {
+        Lazy {
+            cell: Once::new(),
+            init,
+        }
+    }
+}
+
+impl<T, F: Fn() -> T> Deref for Lazy<T, F> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.cell.call_once(|| (self.init)())
+    }
+}
+
+/// Global initialized once during boot and then changed in place without a lock
+///
+/// Only for state that cannot be locked, like the task list which the context
+/// switch code leaves without returning. Initialization is checked like `Once`,
+/// but callers of `get_mut` must make sure no other reference to the value is in use.
+/// This holds while the kernel runs one task at a time on a single CPU and interrupt
+/// handlers do not access the value.
+pub struct Global<T> {
+    cell: Once<T>,
+}
+
+unsafe impl<T> Sync for Global<T> {}
+
+impl<T> Global<T> {
+    pub const fn new() -> Global<T> {
+        Global { cell: Once::new() }
+    }
+
+    /// Initializes the global, panics if it already was.
+    pub fn init(&self, value: T) {
+        self.cell.set(value)
+    }
+
+    pub fn is_initialized(&self) -> bool {
+        self.cell.is_completed()
+    }
+
+    /// Returns the value, panics before initialization.
+    pub fn get(&self) -> &T {
+        &self.cell
+    }
+
+    /// Returns the value, None before initialization.
+    pub fn try_get(&self) -> Option<&T> {
+        self.cell.get()
+    }
+
+    /// Returns the value mutably, panics before initialization.
+    ///
+    /// # Safety
+    /// No other reference to the value may be used while the returned one is.
+    #[allow(clippy::mut_from_ref)]
+    pub unsafe fn get_mut(&self) -> &mut T {
+        self.cell.expect_completed();
+        (*self.cell.data.get()).assume_init_mut()
+    }
+
+    /// Returns the value mutably, None before initialization.
+    ///
+    /// # Safety
+    /// Same as `get_mut`.
+    #[allow(clippy::mut_from_ref)]
+    pub unsafe fn try_get_mut(&self) -> Option<&mut T> {
+        self.is_initialized()
+            .then(|| (*self.cell.data.get()).assume_init_mut())
+    }
+}
@@ -1,13 +1,17 @@
 use core::{
+    mem::size_of,
     slice::{from_raw_parts, from_raw_parts_mut},
     str::from_utf8_unchecked,
 };
 
 use crate::{
-    arch::interrupts::Registers,
-    drivers::framebuffer::FRAMEBUFFER,
-    filesystem::{self, VFS_Node},
-    task::MULTIPROCESSING,
+    arch::{clock, interrupts::Registers, power, rtc},
+    drivers::{blockdev, framebuffer::FRAMEBUFFER},
+    filesystem::{
+        self, Access, Errno, FsError, OpenFile, OpenFlags, Stat, Type, UserDirEnt, VFS_Node,
+    },
+    logging,
+    task::{self, MULTIPROCESSING},
 };
 
 #[no_mangle]
@@ -15,7 +19,7 @@ pub unsafe extern "C" fn syscall_handler(regs: Registers) {
     let ret = match regs.rax {
         0 => syscall_read(regs.rdi, regs.rsi, regs.rdx),
         1 => syscall_write(regs.rdi, regs.rsi, regs.rdx),
-        2 => syscall_open(regs.rdi),
+        2 => syscall_open(regs.rdi, regs.rsi),
         3 => syscall_close(regs.rdi),
         4 => syscall_sleep(regs.rdi),
         5 => syscall_exit(),
@@ -24,19 +28,163 @@ pub unsafe extern "C" fn syscall_handler(regs: Registers) {
         8 => syscall_exec(regs.rdi),
         9 => syscall_blit(regs.rdi),
         10 => syscall_fseek(regs.rdi, regs.rsi, regs.rdx),
+        11 => syscall_stat(regs.rdi, regs.rsi),
+        12 => syscall_fstat(regs.rdi, regs.rsi),
+        13 => syscall_getdents(regs.rdi, regs.rsi, regs.rdx),
+        14 => syscall_sync(),
+        15 => syscall_mkdir(regs.rdi),
+        16 => syscall_unlink(regs.rdi),
+        17 => syscall_rmdir(regs.rdi),
+        18 => syscall_mount(regs.rdi, regs.rsi, regs.rdx),
+        19 => syscall_symlink(regs.rdi, regs.rsi),
+        20 => syscall_readlink(regs.rdi, regs.rsi, regs.rdx),
+        21 => syscall_link(regs.rdi, regs.rsi),
+        22 => syscall_lstat(regs.rdi, regs.rsi),
+        23 => syscall_chmod(regs.rdi, regs.rsi),
+        24 => syscall_chown(regs.rdi, regs.rsi, regs.rdx),
+        25 => syscall_getuid(),
+        26 => syscall_geteuid(),
+        27 => syscall_getgid(),
+        28 => syscall_getegid(),
+        29 => syscall_setuid(regs.rdi),
+        30 => syscall_seteuid(regs.rdi),
+        31 => syscall_setgid(regs.rdi),
+        32 => syscall_setegid(regs.rdi),
+        33 => syscall_reboot(regs.rdi),
+        34 => syscall_clock_gettime(regs.rdi, regs.rsi),
+        35 => syscall_nanosleep(regs.rdi, regs.rsi),
+        36 => syscall_time(regs.rdi),
+        37 => syscall_gettimeofday(regs.rdi),
+        38 => syscall_settimeofday(regs.rdi),
         _ => 0,
     };
+    trace!(
+        "syscall {}({:#x}, {:#x}, {:#x}) = {}",
+        { regs.rax },
+        { regs.rdi },
+        { regs.rsi },
+        { regs.rdx },
+        ret
+    );
 
     core::arch::asm!("mov rax, {}", in(reg) ret)
 }
 
+/// Builds a string from a null terminated user space string
+unsafe fn user_str(addr: u64) -> &'static str {
+    let ptr = addr as *const u8;
+    let len = {
+        let mut l = 0;
+        while *ptr.add(l) != 0 {
+            l += 1;
+        }
+        l
+    };
+    from_utf8_unchecked(from_raw_parts(ptr, len))
+}
+
 unsafe fn syscall_sleep(ms: u64) -> i64 {
     crate::arch::pic::Timer::sleep(ms);
     0
 }
 
+/// Same layout as the C `struct timespec`
+#[repr(C)]
+struct Timespec {
+    tv_sec: i64,
+    tv_nsec: i64,
+}
+
+/// Same layout as the C `struct timeval`
+#[repr(C)]
+struct Timeval {
+    tv_sec: i64,
+    tv_usec: i64,
+}
+
+/// Clocks of clock_gettime(), same ids as Linux
+#[allow(non_snake_case)]
+pub mod ClockId {
+    pub const CLOCK_REALTIME: u64 = 0;
+    pub const CLOCK_MONOTONIC: u64 = 1;
+}
+
+/// Writes the time of a clock: the realtime clock, from 1970 in UTC, or the monotonic
+/// clock, counting from boot
+unsafe fn syscall_clock_gettime(clock_id: u64, timespec_addr: u64) -> i64 {
+    let ns = match clock_id {
+        ClockId::CLOCK_REALTIME => clock::realtime_ns(),
+        ClockId::CLOCK_MONOTONIC => clock::monotonic_ns() as i64,
+        _ => return -Errno::EINVAL,
+    };
+
+    let timespec = &mut *(timespec_addr as *mut Timespec);
+    timespec.tv_sec = ns.div_euclid(clock::NANOS_PER_SEC as i64);
+    timespec.tv_nsec = ns.rem_euclid(clock::NANOS_PER_SEC as i64);
+    0
+}
+
+/// Returns the seconds since 1970, also written to `tloc` when it is not null
+unsafe fn syscall_time(tloc_addr: u64) -> i64 {
+    let seconds = clock::realtime_ns().div_euclid(clock::NANOS_PER_SEC as i64);
+    if tloc_addr != 0 {
+        *(tloc_addr as *mut i64) = seconds;
+    }
+    seconds
+}
+
+unsafe fn syscall_gettimeofday(timeval_addr: u64) -> i64 {
+    let ns = clock::realtime_ns();
+    *(timeval_addr as *mut Timeval) = Timeval {
+        tv_sec: ns.div_euclid(clock::NANOS_PER_SEC as i64),
+        tv_usec: ns.rem_euclid(clock::NANOS_PER_SEC as i64) / 1000,
+    };
+    0
+}
+
+/// Sets the realtime clock and the CMOS clock. Only the root user can do it
+unsafe fn syscall_settimeofday(timeval_addr: u64) -> i64 {
+    if !task::credentials().is_root() {
+        return -Errno::EPERM;
+    }
+    let timeval = &*(timeval_addr as *const Timeval);
+    let max_seconds = i64::MAX / clock::NANOS_PER_SEC as i64 - 1;
+    if !(0..max_seconds).contains(&timeval.tv_sec) || !(0..1_000_000).contains(&timeval.tv_usec) {
+        return -Errno::EINVAL;
+    }
+
+    // Times the CMOS clock can not hold would be lost at the next boot
+    if !rtc::write(&rtc::DateTime::from_unix(timeval.tv_sec)) {
+        return -Errno::EINVAL;
+    }
+    clock::set_realtime_ns(timeval.tv_sec * clock::NANOS_PER_SEC as i64 + timeval.tv_usec * 1000);
+    0
+}
+
+/// Sleeps for the time in `req`. Nothing interrupts it, so `rem` is always zero
+unsafe fn syscall_nanosleep(req_addr: u64, rem_addr: u64) -> i64 {
+    let req = &*(req_addr as *const Timespec);
+    if req.tv_sec < 0 || !(0..clock::NANOS_PER_SEC as i64).contains(&req.tv_nsec) {
+        return -Errno::EINVAL;
+    }
+
+    clock::sleep_ns(
+        (req.tv_sec as u64)
+            .saturating_mul(clock::NANOS_PER_SEC)
+            .saturating_add(req.tv_nsec as u64),
+    );
+
+    if rem_addr != 0 {
+        *(rem_addr as *mut Timespec) = Timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+    }
+    0
+}
+
 unsafe fn syscall_exit() -> i64 {
-    let mp_module = MULTIPROCESSING.as_mut().unwrap();
+    let mp_module = MULTIPROCESSING.get_mut();
 
     mp_module.exit();
 
@@ -44,56 +192,81 @@ unsafe fn syscall_exit() -> i64 {
 }
 
 unsafe fn syscall_getpid() -> i64 {
-    crate::task::MULTIPROCESSING.as_ref().unwrap().current_id as i64
+    MULTIPROCESSING.get().current_id as i64
 }
 
 unsafe fn syscall_uptime() -> i64 {
-    crate::arch::pic::Timer::UPTIME as i64
+    crate::arch::pic::Timer::uptime() as i64
 }
 
-unsafe fn syscall_open(path_addr: u64) -> i64 {
-    let path_ptr = path_addr as *const u8;
-    let path_len = {
-        let mut l = 0;
-        while *path_ptr.add(l) != 0 {
-            l += 1;
+unsafe fn syscall_open(path_addr: u64, flags: u64) -> i64 {
+    use OpenFlags::*;
+    let path = user_str(path_addr);
+
+    let mp_module = MULTIPROCESSING.get_mut();
+
+    let file_ref = match filesystem::lookup(path, true) {
+        Ok(_) if flags & O_CREAT != 0 && flags & O_EXCL != 0 => return -Errno::EEXIST,
+        Ok(file_ref) => file_ref,
+        Err(FsError::NotFound) if flags & O_CREAT != 0 => {
+            match filesystem::fcreate(path, Type::File) {
+                Ok(file_ref) => file_ref,
+                Err(error) => return -error.errno(),
+            }
         }
-        l
+        Err(error) => return -error.errno(),
     };
-    let path = from_utf8_unchecked(from_raw_parts(path_ptr, path_len));
-
-    let mp_module = MULTIPROCESSING.as_mut().unwrap();
-
-    match filesystem::fopen(path) {
-        Some(file_ref) => {
-            let open_fd = (file_ref as *mut VFS_Node, 0);
-            let fd = mp_module.tasks[mp_module.current_id as usize].open_fd.len();
-            mp_module.tasks[mp_module.current_id as usize]
-                .open_fd
-                .push(open_fd);
-            fd as i64
+
+    let open_file = OpenFile::new(file_ref as *mut VFS_Node, flags);
+
+    let mut access = 0;
+    if open_file.readable() {
+        access |= Access::READ;
+    }
+    if open_file.writable() {
+        access |= Access::WRITE;
+    }
+    if !file_ref.permits(&task::credentials(), access) {
+        return -FsError::Access.errno();
+    }
+
+    let truncate = flags & O_TRUNC != 0 && open_file.writable() && file_ref.kind == Type::File;
+    if truncate {
+        if let Err(error) = file_ref.truncate(0) {
+            return -error.errno();
         }
-        None => -1,
     }
+
+    let fd = mp_module.tasks[mp_module.current_id as usize].open_fd.len();
+    mp_module.tasks[mp_module.current_id as usize]
+        .open_fd
+        .push(open_file);
+    fd as i64
 }
 
 unsafe fn syscall_close(fd: u64) -> i64 {
-    let mp_module = MULTIPROCESSING.as_mut().unwrap();
+    let mp_module = MULTIPROCESSING.get_mut();
     mp_module.tasks[mp_module.current_id as usize]
         .open_fd
-        .remove(fd as usize);
+        .remove(fd as usize)
+        .close();
     fd as i64
 }
 
 unsafe fn syscall_read(fd: u64, length: u64, buf_addr: u64) -> i64 {
-    let mp_module = MULTIPROCESSING.as_mut().unwrap();
-    let (file_ptr, pos) = mp_module.tasks[mp_module.current_id as usize].open_fd[fd as usize];
+    let mp_module = MULTIPROCESSING.get_mut();
+    let open_file = mp_module.tasks[mp_module.current_id as usize].open_fd[fd as usize];
+    if !open_file.readable() {
+        return -1;
+    }
     let slice = from_raw_parts_mut(buf_addr as *mut u8, length as usize);
-    let file_node = &*file_ptr;
-    let ret = file_node.read(pos as usize, length as usize, slice);
+    let file_node = &*open_file.node;
+    let offset = file_node.seek(open_file.offset as usize);
+    let ret = file_node.read(offset, length as usize, slice);
 
     if let Some(read) = ret {
-        mp_module.tasks[mp_module.current_id as usize].open_fd[fd as usize].1 += read as u64;
+        mp_module.tasks[mp_module.current_id as usize].open_fd[fd as usize].offset =
+            (offset + read) as u64;
         read as i64
     } else {
         -1
@@ -101,25 +274,34 @@ unsafe fn syscall_read(fd: u64, length: u64, buf_addr: u64) -> i64 {
 }
 
 unsafe fn syscall_write(fd: u64, length: u64, buf_addr: u64) -> i64 {
-    let mp_module = MULTIPROCESSING.as_mut().unwrap();
-    let (file_ptr, pos) = mp_module.tasks[mp_module.current_id as usize].open_fd[fd as usize];
+    let mp_module = MULTIPROCESSING.get_mut();
+    let open_file = &mut mp_module.tasks[mp_module.current_id as usize].open_fd[fd as usize];
+    if !open_file.writable() {
+        return -1;
+    }
     let slice = from_raw_parts_mut(buf_addr as *mut u8, length as usize);
-    let file_node = &mut *file_ptr;
-    let ret = file_node.write(pos as usize, length as usize, slice);
+    let file_node = &mut *open_file.node;
 
-    if let Some(wrote) = ret {
-        mp_module.tasks[mp_module.current_id as usize].open_fd[fd as usize].1 += wrote as u64;
-        wrote as i64
-    } else {
-        -1
+    // Appends always go to the current end of the file
+    if open_file.flags & OpenFlags::O_APPEND != 0 {
+        open_file.offset = file_node.size as u64;
+    }
+    let ret = file_node.write(open_file.offset as usize, length as usize, slice);
+
+    match ret {
+        Ok(wrote) => {
+            open_file.offset += wrote as u64;
+            wrote as i64
+        }
+        Err(error) => -error.errno(),
     }
 }
 
 unsafe fn syscall_fseek(fd: u64, offset: u64, whence: u64) -> i64 {
-    let mp_module = MULTIPROCESSING.as_mut().unwrap();
+    let mp_module = MULTIPROCESSING.get_mut();
     let file_size =
-        { (*mp_module.tasks[mp_module.current_id as usize].open_fd[fd as usize].0).size };
-    let pos = &mut mp_module.tasks[mp_module.current_id as usize].open_fd[fd as usize].1;
+        { (*mp_module.tasks[mp_module.current_id as usize].open_fd[fd as usize].node).size };
+    let pos = &mut mp_module.tasks[mp_module.current_id as usize].open_fd[fd as usize].offset;
 
     match whence {
         0 => {
@@ -138,18 +320,326 @@ unsafe fn syscall_fseek(fd: u64, offset: u64, whence: u64) -> i64 {
     }
 }
 
-unsafe fn syscall_exec(path_addr: u64) -> i64 {
-    let path_ptr = path_addr as *const u8;
-    let path_len = {
-        let mut l = 0;
-        while *path_ptr.add(l) != 0 {
-            l += 1;
+unsafe fn syscall_stat(path_addr: u64, stat_addr: u64) -> i64 {
+    let path = user_str(path_addr);
+
+    match filesystem::lookup(path, true) {
+        Ok(node) => {
+            *(stat_addr as *mut Stat) = node.stat();
+            0
         }
-        l
+        Err(error) => -error.errno(),
+    }
+}
+
+/// Like stat, but returns information about a symbolic link itself
+unsafe fn syscall_lstat(path_addr: u64, stat_addr: u64) -> i64 {
+    let path = user_str(path_addr);
+
+    match filesystem::lookup(path, false) {
+        Ok(node) => {
+            *(stat_addr as *mut Stat) = node.stat();
+            0
+        }
+        Err(error) => -error.errno(),
+    }
+}
+
+unsafe fn syscall_fstat(fd: u64, stat_addr: u64) -> i64 {
+    let mp_module = MULTIPROCESSING.get_mut();
+    let open_fd = &mp_module.tasks[mp_module.current_id as usize].open_fd;
+    if fd as usize >= open_fd.len() {
+        return -1;
+    }
+
+    *(stat_addr as *mut Stat) = (*open_fd[fd as usize].node).stat();
+    0
+}
+
+/// Fills the buffer with as many directory entries as fit, starting with the
+/// entry at the current position of the descriptor.
+/// Returns the number of bytes written, 0 when all entries were read
+unsafe fn syscall_getdents(fd: u64, length: u64, buf_addr: u64) -> i64 {
+    let mp_module = MULTIPROCESSING.get_mut();
+    let open_fd = &mut mp_module.tasks[mp_module.current_id as usize].open_fd;
+    if fd as usize >= open_fd.len() {
+        return -1;
+    }
+    let OpenFile {
+        node: dir_ptr,
+        offset: pos,
+        ..
+    } = open_fd[fd as usize];
+
+    let entries = match (*dir_ptr).readdir() {
+        Some(entries) => entries,
+        None => return -1,
     };
-    let path = from_utf8_unchecked(from_raw_parts(path_ptr, path_len));
 
-    let mp_module = MULTIPROCESSING.as_mut().unwrap();
+    let max_entries = length as usize / size_of::<UserDirEnt>();
+    let buffer = from_raw_parts_mut(buf_addr as *mut UserDirEnt, max_entries);
+
+    let mut count = 0;
+    for (slot, entry) in buffer.iter_mut().zip(entries.iter().skip(pos as usize)) {
+        *slot = UserDirEnt::from(entry);
+        count += 1;
+    }
+
+    open_fd[fd as usize].offset += count as u64;
+    (count * size_of::<UserDirEnt>()) as i64
+}
+
+/// Writes the buffer caches of all disks back
+unsafe fn syscall_sync() -> i64 {
+    match blockdev::sync_all() {
+        Some(()) => 0,
+        None => -1,
+    }
+}
+
+unsafe fn syscall_mkdir(path_addr: u64) -> i64 {
+    let path = user_str(path_addr);
+
+    match filesystem::fcreate(path, Type::Dir) {
+        Ok(_) => 0,
+        Err(error) => -error.errno(),
+    }
+}
+
+/// Removes a file or symbolic link, directories are removed with rmdir
+unsafe fn syscall_unlink(path_addr: u64) -> i64 {
+    let path = user_str(path_addr);
+
+    let result = match filesystem::lookup(path, false) {
+        Ok(node) if node.kind != Type::Dir && node.kind != Type::Mountpoint => {
+            filesystem::funlink(path)
+        }
+        Ok(_) => Err(FsError::Failed),
+        Err(error) => Err(error),
+    };
+    match result {
+        Ok(()) => 0,
+        Err(error) => -error.errno(),
+    }
+}
+
+/// Removes an empty directory
+unsafe fn syscall_rmdir(path_addr: u64) -> i64 {
+    let path = user_str(path_addr);
+
+    let result = match filesystem::lookup(path, false) {
+        Ok(node) if node.kind == Type::Dir => filesystem::funlink(path),
+        Ok(_) => Err(FsError::NotDir),
+        Err(error) => Err(error),
+    };
+    match result {
+        Ok(()) => 0,
+        Err(error) => -error.errno(),
+    }
+}
+
+unsafe fn syscall_symlink(target_addr: u64, linkpath_addr: u64) -> i64 {
+    let target = user_str(target_addr);
+    let linkpath = user_str(linkpath_addr);
+
+    match filesystem::fsymlink(target, linkpath) {
+        Ok(()) => 0,
+        Err(error) => -error.errno(),
+    }
+}
+
+/// Copies the target of a symbolic link, without a null terminator.
+/// Returns the number of bytes copied
+unsafe fn syscall_readlink(path_addr: u64, buf_addr: u64, size: u64) -> i64 {
+    let path = user_str(path_addr);
+
+    let node = match filesystem::lookup(path, false) {
+        Ok(node) => node,
+        Err(error) => return -error.errno(),
+    };
+    let Some(target) = node.readlink() else {
+        return -Errno::EINVAL;
+    };
+
+    let len = target.len().min(size as usize);
+    let buffer = from_raw_parts_mut(buf_addr as *mut u8, len);
+    buffer.copy_from_slice(&target.as_bytes()[..len]);
+    len as i64
+}
+
+unsafe fn syscall_link(oldpath_addr: u64, newpath_addr: u64) -> i64 {
+    let oldpath = user_str(oldpath_addr);
+    let newpath = user_str(newpath_addr);
+
+    match filesystem::flink(oldpath, newpath) {
+        Ok(()) => 0,
+        Err(error) => -error.errno(),
+    }
+}
+
+unsafe fn syscall_chmod(path_addr: u64, mode: u64) -> i64 {
+    let path = user_str(path_addr);
+
+    match filesystem::fchmod(path, mode) {
+        Ok(()) => 0,
+        Err(error) => -error.errno(),
+    }
+}
+
+/// Changes the owner of a node, an id of -1 is left unchanged
+unsafe fn syscall_chown(path_addr: u64, uid: u64, gid: u64) -> i64 {
+    let path = user_str(path_addr);
+    let id = |id: u64| (id as u32 != u32::MAX).then_some(id as u32);
+
+    match filesystem::fchown(path, id(uid), id(gid)) {
+        Ok(()) => 0,
+        Err(error) => -error.errno(),
+    }
+}
+
+/// Credentials of the running task
+unsafe fn current_credentials() -> &'static mut task::Credentials {
+    let mp_module = MULTIPROCESSING.get_mut();
+    &mut mp_module.tasks[mp_module.current_id as usize].credentials
+}
+
+unsafe fn syscall_getuid() -> i64 {
+    current_credentials().uid as i64
+}
+
+unsafe fn syscall_geteuid() -> i64 {
+    current_credentials().euid as i64
+}
+
+unsafe fn syscall_getgid() -> i64 {
+    current_credentials().gid as i64
+}
+
+unsafe fn syscall_getegid() -> i64 {
+    current_credentials().egid as i64
+}
+
+/// The root user sets the real, effective and saved uids, other users can only
+/// set the effective uid to their saved uid
+unsafe fn syscall_setuid(uid: u64) -> i64 {
+    let credentials = current_credentials();
+    let uid = uid as u32;
+
+    if credentials.is_root() {
+        credentials.uid = uid;
+        credentials.euid = uid;
+        credentials.suid = uid;
+    } else if uid == credentials.suid {
+        credentials.euid = uid;
+    } else {
+        return -Errno::EPERM;
+    }
+    0
+}
+
+/// Only changes the effective uid, to any uid for root or to the saved uid.
+/// A task which was root can drop privileges and take them back later, the
+/// programs it starts can not
+unsafe fn syscall_seteuid(uid: u64) -> i64 {
+    let credentials = current_credentials();
+    let uid = uid as u32;
+
+    if !credentials.is_root() && uid != credentials.suid {
+        return -Errno::EPERM;
+    }
+    credentials.euid = uid;
+    0
+}
+
+unsafe fn syscall_setgid(gid: u64) -> i64 {
+    let credentials = current_credentials();
+    let gid = gid as u32;
+
+    if credentials.is_root() {
+        credentials.gid = gid;
+        credentials.egid = gid;
+        credentials.sgid = gid;
+    } else if gid == credentials.sgid {
+        credentials.egid = gid;
+    } else {
+        return -Errno::EPERM;
+    }
+    0
+}
+
+unsafe fn syscall_setegid(gid: u64) -> i64 {
+    let credentials = current_credentials();
+    let gid = gid as u32;
+
+    if !credentials.is_root() && gid != credentials.sgid {
+        return -Errno::EPERM;
+    }
+    credentials.egid = gid;
+    0
+}
+
+/// Commands of the reboot() syscall, same values as Linux
+#[allow(non_snake_case)]
+pub mod RebootCmd {
+    pub const RB_AUTOBOOT: u64 = 0x01234567;
+    pub const RB_HALT_SYSTEM: u64 = 0xCDEF0123;
+    pub const RB_POWER_OFF: u64 = 0x4321FEDC;
+}
+
+/// Restarts, halts or powers off the machine after writing the cached disk blocks.
+/// Only the root user can do it
+unsafe fn syscall_reboot(cmd: u64) -> i64 {
+    use RebootCmd::*;
+
+    if !task::credentials().is_root() {
+        return -Errno::EPERM;
+    }
+    let action: fn() -> ! = match cmd {
+        RB_AUTOBOOT => power::reboot,
+        RB_HALT_SYSTEM => power::halt,
+        RB_POWER_OFF => power::shutdown,
+        _ => return -Errno::EINVAL,
+    };
+
+    if blockdev::sync_all().is_none() {
+        error!("Failed to write the disk caches");
+    }
+    action()
+}
+
+/// Mounts a block device on a directory. A null `fstype` detects the filesystem.
+/// Only the root user can mount
+unsafe fn syscall_mount(source_addr: u64, target_addr: u64, fstype_addr: u64) -> i64 {
+    if !task::credentials().is_root() {
+        return -Errno::EPERM;
+    }
+    let source = user_str(source_addr);
+    let target = user_str(target_addr);
+    let fstype = if fstype_addr == 0 {
+        ""
+    } else {
+        user_str(fstype_addr)
+    };
+
+    match filesystem::mount_device(source, target, fstype) {
+        Ok(()) => 0,
+        Err(error) => -error.errno(),
+    }
+}
+
+unsafe fn syscall_exec(path_addr: u64) -> i64 {
+    let path = user_str(path_addr);
+
+    match filesystem::lookup(path, true) {
+        Ok(node) if node.kind != Type::File => return -Errno::EACCES,
+        Ok(node) if !node.permits(&task::credentials(), Access::EXEC) => {
+            return -FsError::Access.errno()
+        }
+        Ok(_) => {}
+        Err(error) => return -error.errno(),
+    }
+
+    let mp_module = MULTIPROCESSING.get_mut();
 
     mp_module.execute(path);
 
@@ -157,7 +647,7 @@ unsafe fn syscall_exec(path_addr: u64) -> i64 {
 }
 
 unsafe fn syscall_blit(address: u64) -> i64 {
-    let fb = FRAMEBUFFER.as_mut().unwrap();
+    let mut fb = FRAMEBUFFER.lock();
     let slice = from_raw_parts_mut(address as *mut u32, fb.width * fb.height);
     fb.buffer.copy_from_slice(slice);
 
@@ -1,18 +1,33 @@
 use core::arch::asm;
 use core::str::from_utf8_unchecked;
 
+use core::sync::atomic::{AtomicBool, Ordering};
+
 use crate::arch::addressing::VirtAddr;
 use crate::arch::paging::PAGE_SIZE;
 use crate::arch::registers::Cr3;
-use crate::filesystem::VFS_Node;
+use crate::filesystem::{OpenFile, OpenFlags, VFS_Node};
 
 use crate::{
-    arch::{addressing::KERNEL_BASE, paging::PageAllocator},
+    arch::{addressing::KERNEL_BASE, interrupts, paging::PageAllocator, percpu, pic::Timer, tick},
     filesystem,
+    sync::{Global, IrqSpinMutex},
 };
+use alloc::collections::VecDeque;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec;
 use alloc::vec::Vec;
 
-pub static mut MULTIPROCESSING: Option<Multiprocessing> = None;
+/// Created before the first task starts. It is not locked because `execute` and
+/// `exit` switch to another task without returning
+pub static MULTIPROCESSING: Global<Multiprocessing> = Global::new();
+
+// Address space of a process, in pages from address 0
+pub const PROGRAM_PAGES: usize = 1;
+/// !! HEAP will start at 0x200000
+pub const HEAP_PAGES: usize = 4;
+pub const STACK_PAGES: usize = 1;
 
 // Hack! Rework this!
 static mut PROGRAM_NAME: [u8; 64] = [0; 64];
@@ -26,9 +41,183 @@ extern "C" {
 #[derive(Debug)]
 pub struct Task {
     pub id: u64,
+    /// Path of the executable
+    pub name: String,
     pub registers: Registers,
     pub page_allocator: PageAllocator,
-    pub open_fd: Vec<(*mut VFS_Node, u64)>,
+    pub open_fd: Vec<OpenFile>,
+    pub credentials: Credentials,
+}
+
+/// User and group identities of a task.
+/// Permission checks use the effective ids, the real ids tell who started it.
+/// A task which is not root can only switch its effective ids to the saved ones
+#[derive(Debug, Clone, Copy)]
+pub struct Credentials {
+    pub uid: u32,
+    pub euid: u32,
+    pub suid: u32,
+    pub gid: u32,
+    pub egid: u32,
+    pub sgid: u32,
+}
+
+impl Credentials {
+    pub const ROOT: Credentials = Credentials {
+        uid: 0,
+        euid: 0,
+        suid: 0,
+        gid: 0,
+        egid: 0,
+        sgid: 0,
+    };
+
+    pub fn is_root(&self) -> bool {
+        self.euid == 0
+    }
+
+    /// Credentials of a new program: the saved ids become the effective ones, so it can
+    /// not take back the privileges its parent dropped
+    pub fn for_exec(&self) -> Credentials {
+        Credentials {
+            suid: self.euid,
+            sgid: self.egid,
+            ..*self
+        }
+    }
+}
+
+/// Credentials of the running task, the kernel itself acts as root before
+/// the first task starts
+pub fn credentials() -> Credentials {
+    MULTIPROCESSING
+        .try_get()
+        .and_then(|mp| mp.tasks.get(mp.current_id as usize))
+        .map_or(Credentials::ROOT, |task| task.credentials)
+}
+
+/// Id of the running task, None before the first task starts
+pub fn current_id() -> Option<u64> {
+    MULTIPROCESSING
+        .try_get()
+        .and_then(|mp| mp.tasks.get(mp.current_id as usize))
+        .map(|task| task.id)
+}
+
+/// Blocks the running task until `woken` is set, or until the uptime passes
+/// `deadline` (in milliseconds) if there is one. Returns false on timeout.
+///
+/// Only the newest task runs, the others wait for their child to exit, so there
+/// is no task to switch to: the CPU halts until an interrupt handler wakes the task
+/// or the timer reaches the deadline. Interrupts must be enabled.
+pub fn block(woken: &AtomicBool, deadline: Option<u64>) -> bool {
+    debug_assert!(
+        interrupts::are_enabled(),
+        "blocking with interrupts disabled"
+    );
+
+    loop {
+        interrupts::disable();
+        if woken.load(Ordering::Acquire) {
+            interrupts::enable();
+            return true;
+        }
+        if deadline.is_some_and(|deadline| Timer::uptime() >= deadline) {
+            interrupts::enable();
+            return false;
+        }
+        tick::halt_until(deadline.map(|ms| ms * 1_000_000));
+    }
+}
+
+/// Tasks blocked until an event, woken in the order they started waiting.
+/// Waking is allowed from interrupt handlers
+pub struct WaitQueue {
+    waiters: IrqSpinMutex<VecDeque<Arc<AtomicBool>>>,
+}
+
+impl WaitQueue {
+    pub const fn new() -> Self {
+        WaitQueue {
+            waiters: IrqSpinMutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Adds the running task to the queue, it must then `block` on the returned flag.
+    /// Registering before releasing a lock guarding the event means a wake up
+    /// right after the release is not lost
+    pub fn enqueue(&self) -> Arc<AtomicBool> {
+        let woken = Arc::new(AtomicBool::new(false));
+        self.waiters.lock().push_back(woken.clone());
+        woken
+    }
+
+    /// Removes a waiter that stopped waiting on its own, e.g. after a timeout
+    pub fn dequeue(&self, woken: &Arc<AtomicBool>) {
+        self.waiters
+            .lock()
+            .retain(|waiter| !Arc::ptr_eq(waiter, woken));
+    }
+
+    /// Blocks while `condition` returns true, it is checked again after every wake up
+    pub fn wait_while(&self, condition: impl FnMut() -> bool) {
+        self.wait_while_timeout(condition, None);
+    }
+
+    /// Like `wait_while`, giving up after `timeout` milliseconds.
+    /// Returns false if the condition still held when the time ran out
+    pub fn wait_while_timeout(
+        &self,
+        mut condition: impl FnMut() -> bool,
+        timeout: Option<u64>,
+    ) -> bool {
+        let deadline = timeout.map(|ms| Timer::uptime() + ms);
+
+        loop {
+            let woken = {
+                // Checked with the queue locked so a wake up cannot come in between
+                let mut waiters = self.waiters.lock();
+                if !condition() {
+                    return true;
+                }
+                let woken = Arc::new(AtomicBool::new(false));
+                waiters.push_back(woken.clone());
+                woken
+            };
+
+            if !block(&woken, deadline) {
+                self.dequeue(&woken);
+                return !condition();
+            }
+        }
+    }
+
+    /// Wakes the task waiting the longest, returns false if there was none
+    pub fn wake_one(&self) -> bool {
+        match self.waiters.lock().pop_front() {
+            Some(waiter) => {
+                waiter.store(true, Ordering::Release);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Wakes every waiting task, returns how many there were
+    pub fn wake_all(&self) -> usize {
+        let mut waiters = self.waiters.lock();
+        let count = waiters.len();
+        for waiter in waiters.drain(..) {
+            waiter.store(true, Ordering::Release);
+        }
+        count
+    }
+}
+
+impl Default for WaitQueue {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[derive(Debug)]
@@ -67,13 +256,14 @@ impl Multiprocessing {
         // Create a page allocator for the process pages
         let page_allocator = PageAllocator::new_user(KERNEL_BASE);
         let stdin_out = filesystem::fopen("/dev/serial").unwrap() as *mut VFS_Node;
-        let mut open_fd = Vec::new();
-        open_fd.push((stdin_out, 0));
+        let open_fd = vec![OpenFile::new(stdin_out, OpenFlags::O_RDWR)];
         let mut task = Task {
             id: self.current_id,
+            name: program_name.to_string(),
             registers: Registers::new(),
             page_allocator,
             open_fd,
+            credentials: Credentials::ROOT,
         };
 
         // Read the executable from the file
@@ -83,15 +273,18 @@ impl Multiprocessing {
         executable.read(0, executable.size, &mut bytes);
 
         // Allocate pages for the process
-        let _program_mem = task.page_allocator.alloc_next_page(1).unwrap();
-        // !! HEAP will start at 0x200000
-        let _heap = task.page_allocator.alloc_next_page(4).unwrap();
-        let stack = task.page_allocator.alloc_next_page(1).unwrap();
+        let _program_mem = task.page_allocator.alloc_next_page(PROGRAM_PAGES).unwrap();
+        let _heap = task.page_allocator.alloc_next_page(HEAP_PAGES).unwrap();
+        let stack = task.page_allocator.alloc_next_page(STACK_PAGES).unwrap();
         let stack_end_addr = stack.start_address.0 + PAGE_SIZE - 8;
 
         // Switch to the process pages
         Cr3::write_raw(task.page_allocator.user_pages_addresses.unwrap()[0].1, 0);
         self.tasks.push(task);
+        percpu::current()
+            .run_queue
+            .lock()
+            .push_back(self.current_id);
 
         // Copy program
         core::ptr::copy_nonoverlapping(bytes.as_ptr(), 0 as *mut u8, bytes.len());
@@ -124,29 +317,35 @@ impl Multiprocessing {
         self.tasks[self.current_id as usize].registers.rsp = rsp;
         self.tasks[self.current_id as usize].registers.rbp = rbp;
         self.tasks[self.current_id as usize].registers.rip = rip;
+        // The new process runs as the same user
+        let credentials = self.tasks[self.current_id as usize].credentials.for_exec();
 
         self.current_id += 1;
 
         // Create a page allocator for the process pages
         let page_allocator = PageAllocator::new_user(KERNEL_BASE);
         let stdin_out = filesystem::fopen("/dev/serial").unwrap() as *mut VFS_Node;
-        let mut open_fd = Vec::new();
-        open_fd.push((stdin_out, 0));
+        let open_fd = vec![OpenFile::new(stdin_out, OpenFlags::O_RDWR)];
         let mut task = Task {
             id: self.current_id,
+            name: program_name.to_string(),
             registers: Registers::new(),
             page_allocator,
             open_fd,
+            credentials,
         };
 
         // Allocate pages for the process
-        let _program_mem = task.page_allocator.alloc_next_page(1).unwrap();
-        // !! HEAP will start at 0x200000
-        let _heap = task.page_allocator.alloc_next_page(4).unwrap();
-        let stack = task.page_allocator.alloc_next_page(1).unwrap();
+        let _program_mem = task.page_allocator.alloc_next_page(PROGRAM_PAGES).unwrap();
+        let _heap = task.page_allocator.alloc_next_page(HEAP_PAGES).unwrap();
+        let stack = task.page_allocator.alloc_next_page(STACK_PAGES).unwrap();
         let stack_end_addr = stack.start_address.0 + PAGE_SIZE - 8;
 
         self.tasks.push(task);
+        percpu::current()
+            .run_queue
+            .lock()
+            .push_back(self.current_id);
 
         // Switch to the process pages
         Cr3::write_raw(
@@ -174,10 +373,19 @@ impl Multiprocessing {
 
     pub unsafe fn exit(&mut self) {
         let mut task = self.tasks.pop().unwrap();
-        self.current_id -= 1;
 
-        for i in 0..6 {
-            task.page_allocator.free_vaddr(VirtAddr::new(i * 0x200000));
+        // The parent waiting for the task is the one below it in the run queue
+        let mut run_queue = percpu::current().run_queue.lock();
+        run_queue.pop_back();
+        self.current_id = *run_queue.back().unwrap();
+        drop(run_queue);
+
+        for file in task.open_fd.drain(..) {
+            file.close();
+        }
+
+        for i in 0..(PROGRAM_PAGES + HEAP_PAGES + STACK_PAGES) as u64 {
+            task.page_allocator.free_vaddr(VirtAddr::new(i * PAGE_SIZE));
         }
 
         // Switch to the process pages
@@ -0,0 +1,41 @@
+#ifndef _DIRENT_H
+#define _DIRENT_H
+
+#include <stdint.h>
+
+#define NAME_MAX 64
+
+/* d_type values */
+#define DT_UNKNOWN 0
+#define DT_REG 1
+#define DT_DIR 2
+#define DT_CHR 3
+#define DT_BLK 4
+#define DT_LNK 5
+
+/* Must match the kernel's `filesystem::UserDirEnt` */
+struct dirent
+{
+    uint64_t d_ino;
+    uint8_t d_type;
+    char d_name[NAME_MAX];
+};
+
+/* Number of entries fetched from the kernel at once */
+#define DIR_BUF_ENTRIES 8
+
+typedef struct
+{
+    int64_t fd;
+    int len; /* number of entries in buf */
+    int cur; /* next entry to return from buf */
+    struct dirent buf[DIR_BUF_ENTRIES];
+} DIR;
+
+int64_t getdents(int64_t fd, struct dirent *buf, uint64_t n);
+
+DIR *opendir(char *path);
+struct dirent *readdir(DIR *dir);
+int closedir(DIR *dir);
+
+#endif
@@ -39,5 +39,6 @@ extern int sys_nerr;
 #define EPIPE 32   /* Broken pipe */
 #define EDOM 33    /* Math argument out of domain of func */
 #define ERANGE 34  /* Math result not representable */
+#define ELOOP 40   /* Too many symbolic links encountered */
 
 #endif
\ No newline at end of file
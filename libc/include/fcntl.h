@@ -1,4 +1,18 @@
 #ifndef _FCNTL_H
 #define _FCNTL_H
 
-#endif
\ No newline at end of file
+#include <stdint.h>
+
+/* open() flags, must match the kernel's `filesystem::OpenFlags` */
+#define O_RDONLY 0
+#define O_WRONLY 1
+#define O_RDWR 2
+#define O_ACCMODE 3
+#define O_CREAT 0100
+#define O_EXCL 0200
+#define O_TRUNC 01000
+#define O_APPEND 02000
+
+int64_t open(char *path, int flags);
+
+#endif
@@ -0,0 +1,8 @@
+#ifndef _SYS_MOUNT_H
+#define _SYS_MOUNT_H
+
+/* Mounts the block device `source` (e.g. "/dev/hda1") on the directory `target`.
+   `fstype` is "fat", or NULL to detect the filesystem */
+int mount(const char *source, const char *target, const char *fstype);
+
+#endif
@@ -0,0 +1,13 @@
+#ifndef _SYS_REBOOT_H
+#define _SYS_REBOOT_H
+
+/* Commands of reboot(), same values as Linux */
+#define RB_AUTOBOOT 0x01234567    /* Restart the machine */
+#define RB_HALT_SYSTEM 0xcdef0123 /* Stop the CPU */
+#define RB_POWER_OFF 0x4321fedc   /* Power the machine off */
+
+/* Writes the cached disk blocks and restarts, halts or powers off the machine.
+   Only root can call it, it only returns on failure */
+int reboot(int cmd);
+
+#endif
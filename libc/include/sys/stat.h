@@ -0,0 +1,55 @@
+#ifndef _SYS_STAT_H
+#define _SYS_STAT_H
+
+#include <stdint.h>
+
+/* Must match the kernel's `filesystem::Stat` */
+struct stat
+{
+    uint64_t st_ino;
+    uint64_t st_type; /* one of the DT_* values in dirent.h */
+    uint64_t st_size;
+    uint64_t st_mode;
+    uint64_t st_nlink;
+    uint64_t st_uid;
+    uint64_t st_gid;
+};
+
+/* st_mode file type bits */
+#define S_IFMT 0170000
+#define S_IFREG 0100000
+#define S_IFDIR 0040000
+#define S_IFCHR 0020000
+#define S_IFBLK 0060000
+#define S_IFLNK 0120000
+
+/* st_mode permission bits */
+#define S_ISUID 04000
+#define S_ISGID 02000
+#define S_ISVTX 01000
+#define S_IRWXU 00700
+#define S_IRUSR 00400
+#define S_IWUSR 00200
+#define S_IXUSR 00100
+#define S_IRWXG 00070
+#define S_IRGRP 00040
+#define S_IWGRP 00020
+#define S_IXGRP 00010
+#define S_IRWXO 00007
+#define S_IROTH 00004
+#define S_IWOTH 00002
+#define S_IXOTH 00001
+
+#define S_ISREG(m) (((m) & S_IFMT) == S_IFREG)
+#define S_ISDIR(m) (((m) & S_IFMT) == S_IFDIR)
+#define S_ISCHR(m) (((m) & S_IFMT) == S_IFCHR)
+#define S_ISBLK(m) (((m) & S_IFMT) == S_IFBLK)
+#define S_ISLNK(m) (((m) & S_IFMT) == S_IFLNK)
+
+int stat(char *path, struct stat *buf);
+int fstat(int64_t fd, struct stat *buf);
+int lstat(char *path, struct stat *buf);
+int mkdir(char *path);
+int chmod(char *path, uint64_t mode);
+
+#endif
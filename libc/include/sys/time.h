@@ -0,0 +1,24 @@
+#ifndef _SYS_TIME_H
+#define _SYS_TIME_H
+
+#include <time.h>
+
+/* Must match the kernel's `Timeval` */
+struct timeval
+{
+    time_t tv_sec;
+    int64_t tv_usec;
+};
+
+/* Obsolete, only accepted for compatibility */
+struct timezone
+{
+    int tz_minuteswest;
+    int tz_dsttime;
+};
+
+int gettimeofday(struct timeval *tv, struct timezone *tz);
+/* Sets the system and CMOS clocks, only root can call it */
+int settimeofday(const struct timeval *tv, const struct timezone *tz);
+
+#endif
@@ -2,6 +2,7 @@
 #define _SYSCALL_H
 
 #include "stdint.h"
+#include "errno.h"
 
 #define DECL_SYSCALL0(fn) int64_t syscall_##fn();
 #define DECL_SYSCALL1(fn, p1) int64_t syscall_##fn(p1);
@@ -11,7 +12,7 @@
 
 DECL_SYSCALL3(read, uint64_t, uint64_t, const uint8_t *)
 DECL_SYSCALL3(write, uint64_t, uint64_t, const uint8_t *)
-DECL_SYSCALL1(open, const char *)
+DECL_SYSCALL2(open, const char *, uint64_t)
 DECL_SYSCALL1(close, uint64_t)
 DECL_SYSCALL1(sleep, uint64_t)
 DECL_SYSCALL0(exit)
@@ -20,6 +21,34 @@ DECL_SYSCALL0(uptime)
 DECL_SYSCALL1(exec, const char *)
 DECL_SYSCALL1(blit, uint64_t)
 DECL_SYSCALL3(fseek, uint64_t, uint64_t, uint64_t)
+DECL_SYSCALL2(stat, const char *, void *)
+DECL_SYSCALL2(fstat, uint64_t, void *)
+DECL_SYSCALL3(getdents, uint64_t, uint64_t, void *)
+DECL_SYSCALL0(sync)
+DECL_SYSCALL1(mkdir, const char *)
+DECL_SYSCALL1(unlink, const char *)
+DECL_SYSCALL1(rmdir, const char *)
+DECL_SYSCALL3(mount, const char *, const char *, const char *)
+DECL_SYSCALL2(symlink, const char *, const char *)
+DECL_SYSCALL3(readlink, const char *, void *, uint64_t)
+DECL_SYSCALL2(link, const char *, const char *)
+DECL_SYSCALL2(lstat, const char *, void *)
+DECL_SYSCALL2(chmod, const char *, uint64_t)
+DECL_SYSCALL3(chown, const char *, uint64_t, uint64_t)
+DECL_SYSCALL0(getuid)
+DECL_SYSCALL0(geteuid)
+DECL_SYSCALL0(getgid)
+DECL_SYSCALL0(getegid)
+DECL_SYSCALL1(setuid, uint64_t)
+DECL_SYSCALL1(seteuid, uint64_t)
+DECL_SYSCALL1(setgid, uint64_t)
+DECL_SYSCALL1(setegid, uint64_t)
+DECL_SYSCALL1(reboot, uint64_t)
+DECL_SYSCALL2(clock_gettime, uint64_t, void *)
+DECL_SYSCALL2(nanosleep, const void *, void *)
+DECL_SYSCALL1(time, void *)
+DECL_SYSCALL1(gettimeofday, void *)
+DECL_SYSCALL1(settimeofday, const void *)
 
 #define DEFN_SYSCALL0(fn, num)                         \
     int64_t syscall_##fn()                             \
@@ -105,4 +134,15 @@ DECL_SYSCALL3(fseek, uint64_t, uint64_t, uint64_t)
         _ret;                                                    \
     }
 
+/* Syscalls taking paths return -errno on failure, this sets errno and returns -1 */
+static inline int64_t syscall_result(int64_t ret)
+{
+    if (ret < 0)
+    {
+        errno = -ret;
+        return -1;
+    }
+    return ret;
+}
+
 #endif
\ No newline at end of file
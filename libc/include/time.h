@@ -0,0 +1,54 @@
+#ifndef _TIME_H
+#define _TIME_H
+
+#include <stdint.h>
+#include <stddef.h>
+
+typedef int64_t time_t;
+typedef int clockid_t;
+
+/* Must match the kernel's `Timespec` */
+struct timespec
+{
+    time_t tv_sec;
+    int64_t tv_nsec;
+};
+
+/* Broken-down time */
+struct tm
+{
+    int tm_sec;   /* 0-60 */
+    int tm_min;   /* 0-59 */
+    int tm_hour;  /* 0-23 */
+    int tm_mday;  /* 1-31 */
+    int tm_mon;   /* 0-11 */
+    int tm_year;  /* years since 1900 */
+    int tm_wday;  /* 0-6, Sunday is 0 */
+    int tm_yday;  /* 0-365 */
+    int tm_isdst; /* always 0, there is no daylight saving time */
+};
+
+/* Clocks of clock_gettime(), same values as Linux */
+#define CLOCK_REALTIME 0  /* Time since 1970-01-01 00:00:00 UTC */
+#define CLOCK_MONOTONIC 1 /* Time since boot, never goes backwards */
+
+int clock_gettime(clockid_t clock_id, struct timespec *tp);
+/* Sleeps for the time in req, rem (if not NULL) receives the time left */
+int nanosleep(const struct timespec *req, struct timespec *rem);
+
+/* Seconds since 1970, also stored in *tloc if it is not NULL */
+time_t time(time_t *tloc);
+
+/* There are no time zones, local time is UTC */
+struct tm *gmtime(const time_t *timep);
+struct tm *gmtime_r(const time_t *timep, struct tm *result);
+struct tm *localtime(const time_t *timep);
+struct tm *localtime_r(const time_t *timep, struct tm *result);
+/* Normalizes the fields of tm and returns its time */
+time_t mktime(struct tm *tm);
+
+/* Formats tm into s with the C and POSIX conversions. Returns the length written,
+   or 0 if it does not fit in max bytes */
+size_t strftime(char *s, size_t max, const char *format, const struct tm *tm);
+
+#endif
@@ -2,8 +2,11 @@
 #define _UNISTD_H
 
 #include <stdint.h>
+#include <fcntl.h>
+
+typedef uint32_t uid_t;
+typedef uint32_t gid_t;
 
-int64_t open(char *path);
 int64_t close(int64_t fd);
 int64_t write(int64_t fd, void *buf, uint64_t n);
 int64_t read(int64_t fd, void *buf, uint64_t n);
@@ -17,9 +20,25 @@ long fseek(int64_t fd, long offset, int whence);
 
 void exit(int status);
 int64_t uptime();
-void exec(char *path);
+int64_t exec(char *path);
 
 int64_t sleep(uint64_t n);
+int64_t sync();
+int64_t unlink(char *path);
+int64_t rmdir(char *path);
+int64_t symlink(char *target, char *linkpath);
+int64_t readlink(char *path, char *buf, uint64_t size);
+int64_t link(char *oldpath, char *newpath);
+int64_t chown(char *path, uid_t owner, gid_t group);
+
+uid_t getuid();
+uid_t geteuid();
+gid_t getgid();
+gid_t getegid();
+int64_t setuid(uid_t uid);
+int64_t seteuid(uid_t uid);
+int64_t setgid(gid_t gid);
+int64_t setegid(gid_t gid);
 
 /* standard file descriptors */
 #define STDIN_FILENO 0
@@ -0,0 +1,62 @@
+#include <dirent.h>
+#include <fcntl.h>
+#include <stdint.h>
+#include <stddef.h>
+#include <stdlib.h>
+#include <string.h>
+#include <syscall.h>
+#include <unistd.h>
+#include <sys/stat.h>
+
+int64_t getdents(int64_t fd, struct dirent *buf, uint64_t n)
+{
+    if (fd < 0)
+    {
+        return -1;
+    }
+    return syscall_getdents(fd, n, buf);
+}
+
+DIR *opendir(char *path)
+{
+    struct stat st;
+    if (stat(path, &st) < 0 || !S_ISDIR(st.st_mode))
+    {
+        return NULL;
+    }
+
+    DIR *dir = malloc(sizeof(*dir));
+    memset(dir, 0, sizeof(*dir));
+    dir->fd = open(path, O_RDONLY);
+    if (dir->fd < 0)
+    {
+        free(dir);
+        return NULL;
+    }
+
+    return dir;
+}
+
+struct dirent *readdir(DIR *dir)
+{
+    if (dir->cur >= dir->len)
+    {
+        int64_t ret = getdents(dir->fd, dir->buf, sizeof(dir->buf));
+        if (ret <= 0)
+        {
+            return NULL;
+        }
+        dir->len = ret / sizeof(struct dirent);
+        dir->cur = 0;
+    }
+
+    return &dir->buf[dir->cur++];
+}
+
+int closedir(DIR *dir)
+{
+    int ret = close(dir->fd);
+    free(dir);
+
+    return ret;
+}
@@ -1,5 +1,7 @@
 #include "errno.h"
 
+int errno;
+
 char *sys_errlist[] = {
     [0] = "Invalid error number",
     [EPERM] = "Operation not permitted",
@@ -36,6 +38,7 @@ char *sys_errlist[] = {
     [EPIPE] = "Broken pipe",
     [EDOM] = "Argument outside domain",
     [ERANGE] = "Result not representable",
+    [ELOOP] = "Too many symbolic links",
 };
 
 int sys_nerr = sizeof(sys_errlist) / sizeof(sys_errlist[0]);
\ No newline at end of file
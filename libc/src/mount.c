@@ -0,0 +1,7 @@
+#include <sys/mount.h>
+#include <syscall.h>
+
+int mount(const char *source, const char *target, const char *fstype)
+{
+    return syscall_mount(source, target, fstype);
+}
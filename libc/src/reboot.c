@@ -0,0 +1,7 @@
+#include <sys/reboot.h>
+#include <syscall.h>
+
+int reboot(int cmd)
+{
+    return syscall_result(syscall_reboot((uint32_t)cmd));
+}
@@ -0,0 +1,32 @@
+#include <sys/stat.h>
+#include <stdint.h>
+#include <syscall.h>
+
+int stat(char *path, struct stat *buf)
+{
+    return syscall_result(syscall_stat(path, buf));
+}
+
+int fstat(int64_t fd, struct stat *buf)
+{
+    if (fd < 0)
+    {
+        return -1;
+    }
+    return syscall_fstat(fd, buf);
+}
+
+int lstat(char *path, struct stat *buf)
+{
+    return syscall_result(syscall_lstat(path, buf));
+}
+
+int mkdir(char *path)
+{
+    return syscall_result(syscall_mkdir(path));
+}
+
+int chmod(char *path, uint64_t mode)
+{
+    return syscall_result(syscall_chmod(path, mode));
+}
@@ -8,19 +8,53 @@
 #include <stddef.h>
 #include <errno.h>
 #include <stdlib.h>
+#include <fcntl.h>
 
 static FILE _stdout = {0, -1, NULL, NULL, 0, 0, 0, 0, 0};
 FILE *stdin = &_stdout;
 FILE *stdout = &_stdout;
 FILE *stderr = &_stdout;
 
+/* Converts a fopen() mode string ("r", "w+", "ab", ...) to open() flags */
+static int mode_flags(char *mode)
+{
+    int flags;
+    switch (*mode)
+    {
+    case 'r':
+        flags = O_RDONLY;
+        break;
+    case 'w':
+        flags = O_WRONLY | O_CREAT | O_TRUNC;
+        break;
+    case 'a':
+        flags = O_WRONLY | O_CREAT | O_APPEND;
+        break;
+    default:
+        return -1;
+    }
+
+    if (strchr(mode, '+'))
+    {
+        flags = (flags & ~O_ACCMODE) | O_RDWR;
+    }
+
+    return flags;
+}
+
 FILE *fopen(char *path, char *mode)
 {
     FILE *fp;
+    int flags = mode_flags(mode);
+    if (flags < 0)
+    {
+        errno = EINVAL;
+        return NULL;
+    }
 
     fp = malloc(sizeof(*fp));
     memset(fp, 0, sizeof(*fp));
-    fp->fd = open(path);
+    fp->fd = open(path, flags);
     if (fp->fd < 0)
     {
         free(fp);
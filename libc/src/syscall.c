@@ -2,7 +2,7 @@
 
 DEFN_SYSCALL3(read, 0, uint64_t, uint64_t, const uint8_t *);
 DEFN_SYSCALL3(write, 1, uint64_t, uint64_t, const uint8_t *);
-DEFN_SYSCALL1(open, 2, const char *);
+DEFN_SYSCALL2(open, 2, const char *, uint64_t);
 DEFN_SYSCALL1(close, 3, uint64_t);
 DEFN_SYSCALL1(sleep, 4, uint64_t);
 DEFN_SYSCALL0(exit, 5);
@@ -10,4 +10,32 @@ DEFN_SYSCALL0(getpid, 6);
 DEFN_SYSCALL0(uptime, 7);
 DEFN_SYSCALL1(exec, 8, const char *);
 DEFN_SYSCALL1(blit, 9, uint64_t);
-DEFN_SYSCALL3(fseek, 10, uint64_t, uint64_t, uint64_t);
\ No newline at end of file
+DEFN_SYSCALL3(fseek, 10, uint64_t, uint64_t, uint64_t);
+DEFN_SYSCALL2(stat, 11, const char *, void *);
+DEFN_SYSCALL2(fstat, 12, uint64_t, void *);
+DEFN_SYSCALL3(getdents, 13, uint64_t, uint64_t, void *);
+DEFN_SYSCALL0(sync, 14);
+DEFN_SYSCALL1(mkdir, 15, const char *);
+DEFN_SYSCALL1(unlink, 16, const char *);
+DEFN_SYSCALL1(rmdir, 17, const char *);
+DEFN_SYSCALL3(mount, 18, const char *, const char *, const char *);
+DEFN_SYSCALL2(symlink, 19, const char *, const char *);
+DEFN_SYSCALL3(readlink, 20, const char *, void *, uint64_t);
+DEFN_SYSCALL2(link, 21, const char *, const char *);
+DEFN_SYSCALL2(lstat, 22, const char *, void *);
+DEFN_SYSCALL2(chmod, 23, const char *, uint64_t);
+DEFN_SYSCALL3(chown, 24, const char *, uint64_t, uint64_t);
+DEFN_SYSCALL0(getuid, 25);
+DEFN_SYSCALL0(geteuid, 26);
+DEFN_SYSCALL0(getgid, 27);
+DEFN_SYSCALL0(getegid, 28);
+DEFN_SYSCALL1(setuid, 29, uint64_t);
+DEFN_SYSCALL1(seteuid, 30, uint64_t);
+DEFN_SYSCALL1(setgid, 31, uint64_t);
+DEFN_SYSCALL1(setegid, 32, uint64_t);
+DEFN_SYSCALL1(reboot, 33, uint64_t);
+DEFN_SYSCALL2(clock_gettime, 34, uint64_t, void *);
+DEFN_SYSCALL2(nanosleep, 35, const void *, void *);
+DEFN_SYSCALL1(time, 36, void *);
+DEFN_SYSCALL1(gettimeofday, 37, void *);
+DEFN_SYSCALL1(settimeofday, 38, const void *);
\ No newline at end of file
@@ -0,0 +1,324 @@
+#include <time.h>
+#include <sys/time.h>
+#include <syscall.h>
+
+#define SECONDS_PER_DAY 86400
+
+static const char *day_names[] = {"Sunday", "Monday", "Tuesday", "Wednesday",
+                                  "Thursday", "Friday", "Saturday"};
+static const char *month_names[] = {"January", "February", "March", "April",
+                                    "May", "June", "July", "August",
+                                    "September", "October", "November", "December"};
+
+int clock_gettime(clockid_t clock_id, struct timespec *tp)
+{
+    return syscall_result(syscall_clock_gettime((uint64_t)clock_id, tp));
+}
+
+int nanosleep(const struct timespec *req, struct timespec *rem)
+{
+    return syscall_result(syscall_nanosleep(req, rem));
+}
+
+time_t time(time_t *tloc)
+{
+    return syscall_time(tloc);
+}
+
+int gettimeofday(struct timeval *tv, struct timezone *tz)
+{
+    if (tz != NULL)
+    {
+        tz->tz_minuteswest = 0;
+        tz->tz_dsttime = 0;
+    }
+    return syscall_result(syscall_gettimeofday(tv));
+}
+
+int settimeofday(const struct timeval *tv, const struct timezone *tz)
+{
+    return syscall_result(syscall_settimeofday(tv));
+}
+
+/* Days since 1970-01-01 of a date, years start in March so the leap day is the last one */
+static int64_t days_from_civil(int64_t year, int month, int day)
+{
+    year -= month <= 2;
+    int64_t era = (year >= 0 ? year : year - 399) / 400;
+    int64_t year_of_era = year - era * 400;
+    int64_t day_of_year = (153 * (month + (month > 2 ? -3 : 9)) + 2) / 5 + day - 1;
+    int64_t day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    return era * 146097 + day_of_era - 719468;
+}
+
+struct tm *gmtime_r(const time_t *timep, struct tm *result)
+{
+    int64_t days = *timep / SECONDS_PER_DAY;
+    int64_t seconds = *timep % SECONDS_PER_DAY;
+    if (seconds < 0)
+    {
+        seconds += SECONDS_PER_DAY;
+        days--;
+    }
+
+    result->tm_hour = seconds / 3600;
+    result->tm_min = seconds / 60 % 60;
+    result->tm_sec = seconds % 60;
+    /* 1970-01-01 was a Thursday */
+    result->tm_wday = ((days + 4) % 7 + 7) % 7;
+
+    days += 719468;
+    int64_t era = (days >= 0 ? days : days - 146096) / 146097;
+    int64_t day_of_era = days - era * 146097;
+    int64_t year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    int64_t day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    int64_t mp = (5 * day_of_year + 2) / 153;
+    int day = day_of_year - (153 * mp + 2) / 5 + 1;
+    int month = mp < 10 ? mp + 3 : mp - 9;
+    int64_t year = year_of_era + era * 400 + (month <= 2);
+
+    result->tm_year = year - 1900;
+    result->tm_mon = month - 1;
+    result->tm_mday = day;
+    result->tm_yday = days_from_civil(year, month, day) - days_from_civil(year, 1, 1);
+    result->tm_isdst = 0;
+    return result;
+}
+
+struct tm *gmtime(const time_t *timep)
+{
+    static struct tm tm;
+    return gmtime_r(timep, &tm);
+}
+
+struct tm *localtime_r(const time_t *timep, struct tm *result)
+{
+    return gmtime_r(timep, result);
+}
+
+struct tm *localtime(const time_t *timep)
+{
+    return gmtime(timep);
+}
+
+time_t mktime(struct tm *tm)
+{
+    /* Carry the months into the years, the other fields add up as seconds */
+    int64_t year = tm->tm_year + 1900 + tm->tm_mon / 12;
+    int month = tm->tm_mon % 12;
+    if (month < 0)
+    {
+        month += 12;
+        year--;
+    }
+
+    time_t t = days_from_civil(year, month + 1, 1) * SECONDS_PER_DAY +
+               (int64_t)(tm->tm_mday - 1) * SECONDS_PER_DAY + (int64_t)tm->tm_hour * 3600 +
+               (int64_t)tm->tm_min * 60 + tm->tm_sec;
+    gmtime_r(&t, tm);
+    return t;
+}
+
+/* Appends to the output of strftime, fails once max is reached */
+struct output
+{
+    char *s;
+    size_t len;
+    size_t max;
+};
+
+static int put_char(struct output *out, char c)
+{
+    if (out->len + 1 >= out->max)
+    {
+        return -1;
+    }
+    out->s[out->len++] = c;
+    return 0;
+}
+
+static int put_str(struct output *out, const char *s, int len)
+{
+    for (int i = 0; s[i] && (len < 0 || i < len); i++)
+    {
+        if (put_char(out, s[i]) < 0)
+        {
+            return -1;
+        }
+    }
+    return 0;
+}
+
+/* Decimal number with at least width digits, padded with pad */
+static int put_num(struct output *out, int64_t n, int width, char pad)
+{
+    char digits[24];
+    int i = 0;
+    int negative = n < 0;
+    uint64_t u = negative ? -(uint64_t)n : (uint64_t)n;
+    do
+    {
+        digits[i++] = '0' + u % 10;
+        u /= 10;
+    } while (u);
+
+    if (negative && put_char(out, '-') < 0)
+    {
+        return -1;
+    }
+    for (int w = i; w < width; w++)
+    {
+        if (put_char(out, pad) < 0)
+        {
+            return -1;
+        }
+    }
+    while (i--)
+    {
+        if (put_char(out, digits[i]) < 0)
+        {
+            return -1;
+        }
+    }
+    return 0;
+}
+
+static int format(struct output *out, const char *fmt, const struct tm *tm)
+{
+    for (; *fmt; fmt++)
+    {
+        if (*fmt != '%')
+        {
+            if (put_char(out, *fmt) < 0)
+                return -1;
+            continue;
+        }
+
+        int r;
+        int hour12 = tm->tm_hour % 12 == 0 ? 12 : tm->tm_hour % 12;
+        switch (*++fmt)
+        {
+        case 'a':
+            r = put_str(out, day_names[tm->tm_wday % 7], 3);
+            break;
+        case 'A':
+            r = put_str(out, day_names[tm->tm_wday % 7], -1);
+            break;
+        case 'b':
+        case 'h':
+            r = put_str(out, month_names[tm->tm_mon % 12], 3);
+            break;
+        case 'B':
+            r = put_str(out, month_names[tm->tm_mon % 12], -1);
+            break;
+        case 'c':
+            r = format(out, "%a %b %e %H:%M:%S %Y", tm);
+            break;
+        case 'C':
+            r = put_num(out, (tm->tm_year + 1900) / 100, 2, '0');
+            break;
+        case 'd':
+            r = put_num(out, tm->tm_mday, 2, '0');
+            break;
+        case 'D':
+            r = format(out, "%m/%d/%y", tm);
+            break;
+        case 'e':
+            r = put_num(out, tm->tm_mday, 2, ' ');
+            break;
+        case 'F':
+            r = format(out, "%Y-%m-%d", tm);
+            break;
+        case 'H':
+            r = put_num(out, tm->tm_hour, 2, '0');
+            break;
+        case 'I':
+            r = put_num(out, hour12, 2, '0');
+            break;
+        case 'j':
+            r = put_num(out, tm->tm_yday + 1, 3, '0');
+            break;
+        case 'm':
+            r = put_num(out, tm->tm_mon + 1, 2, '0');
+            break;
+        case 'M':
+            r = put_num(out, tm->tm_min, 2, '0');
+            break;
+        case 'n':
+            r = put_char(out, '\n');
+            break;
+        case 'p':
+            r = put_str(out, tm->tm_hour < 12 ? "AM" : "PM", -1);
+            break;
+        case 'r':
+            r = format(out, "%I:%M:%S %p", tm);
+            break;
+        case 'R':
+            r = format(out, "%H:%M", tm);
+            break;
+        case 's':
+        {
+            struct tm copy = *tm;
+            r = put_num(out, mktime(&copy), 1, '0');
+            break;
+        }
+        case 'S':
+            r = put_num(out, tm->tm_sec, 2, '0');
+            break;
+        case 't':
+            r = put_char(out, '\t');
+            break;
+        case 'T':
+            r = format(out, "%H:%M:%S", tm);
+            break;
+        case 'u':
+            r = put_num(out, tm->tm_wday == 0 ? 7 : tm->tm_wday, 1, '0');
+            break;
+        case 'w':
+            r = put_num(out, tm->tm_wday, 1, '0');
+            break;
+        case 'x':
+            r = format(out, "%m/%d/%y", tm);
+            break;
+        case 'X':
+            r = format(out, "%H:%M:%S", tm);
+            break;
+        case 'y':
+            r = put_num(out, (tm->tm_year + 1900) % 100, 2, '0');
+            break;
+        case 'Y':
+            r = put_num(out, tm->tm_year + 1900, 1, '0');
+            break;
+        case 'z':
+            r = put_str(out, "+0000", -1);
+            break;
+        case 'Z':
+            r = put_str(out, "UTC", -1);
+            break;
+        case '%':
+            r = put_char(out, '%');
+            break;
+        case '\0':
+            return 0;
+        default:
+            /* Unknown conversions are copied */
+            r = put_char(out, '%') < 0 ? -1 : put_char(out, *fmt);
+        }
+        if (r < 0)
+        {
+            return -1;
+        }
+    }
+    return 0;
+}
+
+size_t strftime(char *s, size_t max, const char *fmt, const struct tm *tm)
+{
+    struct output out = {.s = s, .len = 0, .max = max};
+    if (max == 0 || format(&out, fmt, tm) < 0)
+    {
+        return 0;
+    }
+    s[out.len] = 0;
+    return out.len;
+}
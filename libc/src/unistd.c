@@ -1,10 +1,11 @@
 #include <unistd.h>
 #include <stdint.h>
 #include <syscall.h>
+#include <fcntl.h>
 
-int64_t open(char *path)
+int64_t open(char *path, int flags)
 {
-    return syscall_open(path);
+    return syscall_result(syscall_open(path, flags));
 }
 
 int64_t close(int64_t fd)
@@ -49,9 +50,9 @@ int64_t uptime()
     return syscall_uptime();
 }
 
-void exec(char *path)
+int64_t exec(char *path)
 {
-    syscall_exec(path);
+    return syscall_result(syscall_exec(path));
 }
 
 long fseek(int64_t fd, long offset, int whence)
@@ -61,4 +62,80 @@ long fseek(int64_t fd, long offset, int whence)
         return -1;
     }
     return syscall_fseek(fd, offset, whence);
+}
+
+int64_t sync()
+{
+    return syscall_sync();
+}
+
+int64_t unlink(char *path)
+{
+    return syscall_result(syscall_unlink(path));
+}
+
+int64_t rmdir(char *path)
+{
+    return syscall_result(syscall_rmdir(path));
+}
+
+int64_t symlink(char *target, char *linkpath)
+{
+    return syscall_result(syscall_symlink(target, linkpath));
+}
+
+int64_t readlink(char *path, char *buf, uint64_t size)
+{
+    return syscall_result(syscall_readlink(path, buf, size));
+}
+
+int64_t link(char *oldpath, char *newpath)
+{
+    return syscall_result(syscall_link(oldpath, newpath));
+}
+
+/* An id of (uid_t)-1 or (gid_t)-1 is left unchanged */
+int64_t chown(char *path, uid_t owner, gid_t group)
+{
+    return syscall_result(syscall_chown(path, owner, group));
+}
+
+uid_t getuid()
+{
+    return syscall_getuid();
+}
+
+uid_t geteuid()
+{
+    return syscall_geteuid();
+}
+
+gid_t getgid()
+{
+    return syscall_getgid();
+}
+
+gid_t getegid()
+{
+    return syscall_getegid();
+}
+
+int64_t setuid(uid_t uid)
+{
+    return syscall_result(syscall_setuid(uid));
+}
+
+int64_t seteuid(uid_t uid)
+{
+    return syscall_result(syscall_seteuid(uid));
+}
+
+int64_t setgid(gid_t gid)
+{
+    return syscall_result(syscall_setgid(gid));
+}
+
+int64_t setegid(gid_t gid)
+{
+    return syscall_result(syscall_setegid(gid));
 }
\ No newline at end of file
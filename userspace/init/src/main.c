@@ -1,17 +1,43 @@
 #include <stdio.h>
+#include <stdlib.h>
 #include <ctype.h>
 #include <string.h>
 #include <unistd.h>
 #include <stdint.h>
+#include <stddef.h>
+#include <dirent.h>
+#include <sys/stat.h>
+#include <sys/mount.h>
+#include <sys/reboot.h>
+#include <sys/time.h>
+#include <time.h>
 
 #define LINE_MAX 64
 
+/* Unprivileged user the shell runs as, `su` switches to root and back */
+#define USER_UID 1000
+#define USER_GID 1000
+
 void help(char *);
 void ls(char *);
 void uname(char *);
 void uptime_cmd(char *);
 void run(char *);
 void echo(char *);
+void mkdir_cmd(char *);
+void rm(char *);
+void mount_cmd(char *);
+void sync_cmd(char *);
+void ln(char *);
+void id(char *);
+void su(char *);
+void chmod_cmd(char *);
+void chown_cmd(char *);
+void ps(char *);
+void dmesg(char *);
+void shutdown(char *);
+void reboot_cmd(char *);
+void date(char *);
 
 typedef struct command
 {
@@ -24,7 +50,21 @@ command_t commands[] = {{.name = "help", .exec = help},
                         {.name = "uname", .exec = uname},
                         {.name = "uptime", .exec = uptime_cmd},
                         {.name = "run", .exec = run},
-                        {.name = "echo", .exec = echo}};
+                        {.name = "echo", .exec = echo},
+                        {.name = "mkdir", .exec = mkdir_cmd},
+                        {.name = "rm", .exec = rm},
+                        {.name = "mount", .exec = mount_cmd},
+                        {.name = "sync", .exec = sync_cmd},
+                        {.name = "ln", .exec = ln},
+                        {.name = "id", .exec = id},
+                        {.name = "su", .exec = su},
+                        {.name = "chmod", .exec = chmod_cmd},
+                        {.name = "chown", .exec = chown_cmd},
+                        {.name = "ps", .exec = ps},
+                        {.name = "dmesg", .exec = dmesg},
+                        {.name = "shutdown", .exec = shutdown},
+                        {.name = "reboot", .exec = reboot_cmd},
+                        {.name = "date", .exec = date}};
 
 typedef enum command_index
 {
@@ -34,6 +74,20 @@ typedef enum command_index
     UPTIME,
     RUN,
     ECHO,
+    MKDIR,
+    RM,
+    MOUNT,
+    SYNC,
+    LN,
+    ID,
+    SU,
+    CHMOD,
+    CHOWN,
+    PS,
+    DMESG,
+    SHUTDOWN,
+    REBOOT,
+    DATE,
     _LAST
 } command_index;
 
@@ -44,9 +98,14 @@ void main()
 {
     puts("======= MercuryOS Shell =======\n\n");
 
+    // Only the effective ids are dropped, the saved ids stay root for `su`.
+    // Programs started from the shell get saved ids of 1000 and can not go back
+    setegid(USER_GID);
+    seteuid(USER_UID);
+
     while (1)
     {
-        printf("/# ");
+        printf(geteuid() == 0 ? "/# " : "/$ ");
         char argument[LINE_MAX] = {0};
         command_index ci = read_command(argument);
 
@@ -148,8 +207,69 @@ void help(char *_ignore)
     printf("    - uptime\n");
     printf("    - run [progam path]\n");
     printf("    - echo [string]\n");
+    printf("    - mkdir [path]\n");
+    printf("    - rm [path]\n");
+    printf("    - mount [device] [directory]\n");
+    printf("    - sync\n");
+    printf("    - ln [-s] [target] [link]\n");
+    printf("    - id\n");
+    printf("    - su (switch between root and user)\n");
+    printf("    - chmod [octal mode] [path]\n");
+    printf("    - chown [uid] [path]\n");
+    printf("    - ps\n");
+    printf("    - dmesg\n");
+    printf("    - shutdown (as root)\n");
+    printf("    - reboot (as root)\n");
+    printf("    - date [-s YYYY-MM-DD HH:MM:SS] (setting as root)\n");
+}
+void ls(char *path)
+{
+    if (*path == 0)
+    {
+        path = "/";
+    }
+
+    DIR *dir = opendir(path);
+    if (NULL == dir)
+    {
+        printf("ls: cannot open %s\n", path);
+        return;
+    }
+
+    struct dirent *entry;
+    while ((entry = readdir(dir)) != NULL)
+    {
+        if (entry->d_type == DT_DIR)
+        {
+            printf("%s/\n", entry->d_name);
+            continue;
+        }
+
+        char full_path[LINE_MAX + NAME_MAX + 1];
+        int len = strlen(path);
+        snprintf(full_path, sizeof(full_path), path[len - 1] == '/' ? "%s%s" : "%s/%s", path, entry->d_name);
+
+        if (entry->d_type == DT_LNK)
+        {
+            char target[LINE_MAX] = {0};
+            readlink(full_path, target, sizeof(target) - 1);
+            printf("%s -> %s\n", entry->d_name, target);
+            continue;
+        }
+
+        struct stat st;
+        if (stat(full_path, &st) == 0 && S_ISREG(st.st_mode))
+        {
+            printf("%s\t%d\n", entry->d_name, st.st_size);
+        }
+        else
+        {
+            printf("%s\n", entry->d_name);
+        }
+    }
+
+    closedir(dir);
 }
-void ls(char *path) {}
 void uname(char *_ignore)
 {
     puts("MercuryOS https://github.com/paunstefan/mercury_os");
@@ -162,9 +282,273 @@ void uptime_cmd(char *_ignore)
 }
 void run(char *path)
 {
-    exec(path);
+    if (exec(path) != 0)
+    {
+        perror("run");
+    }
 }
 void echo(char *string)
 {
     puts(string);
-}
\ No newline at end of file
+}
+void mkdir_cmd(char *path)
+{
+    if (mkdir(path) != 0)
+    {
+        printf("mkdir: cannot create %s\n", path);
+    }
+}
+void rm(char *path)
+{
+    // Empty directories are removed too
+    if (unlink(path) != 0 && rmdir(path) != 0)
+    {
+        printf("rm: cannot remove %s\n", path);
+    }
+}
+void mount_cmd(char *args)
+{
+    char *target = args;
+    while (*target && !isspace(*target))
+    {
+        target += 1;
+    }
+    if (*target == 0)
+    {
+        printf("usage: mount [device] [directory]\n");
+        return;
+    }
+    *target = 0;
+    target += 1;
+
+    if (mount(args, target, NULL) != 0)
+    {
+        printf("mount: cannot mount %s on %s\n", args, target);
+    }
+}
+void sync_cmd(char *_ignore)
+{
+    sync();
+}
+void ln(char *args)
+{
+    int symbolic = strncmp(args, "-s ", 3) == 0;
+    if (symbolic)
+    {
+        args += 3;
+    }
+
+    char *linkpath = args;
+    while (*linkpath && !isspace(*linkpath))
+    {
+        linkpath += 1;
+    }
+    if (*linkpath == 0)
+    {
+        printf("usage: ln [-s] [target] [link]\n");
+        return;
+    }
+    *linkpath = 0;
+    linkpath += 1;
+
+    int64_t ret = symbolic ? symlink(args, linkpath) : link(args, linkpath);
+    if (ret != 0)
+    {
+        perror("ln");
+    }
+}
+void id(char *_ignore)
+{
+    printf("uid=%d euid=%d gid=%d egid=%d\n", getuid(), geteuid(), getgid(), getegid());
+}
+void su(char *_ignore)
+{
+    int to_root = geteuid() != 0;
+    // Group first, changing it needs the root effective uid
+    if (to_root)
+    {
+        seteuid(0);
+        setegid(0);
+    }
+    else
+    {
+        setegid(USER_GID);
+        seteuid(USER_UID);
+    }
+}
+/* Splits "first second" in place, returns the second word or NULL */
+char *second_arg(char *args)
+{
+    while (*args && !isspace(*args))
+    {
+        args += 1;
+    }
+    if (*args == 0)
+    {
+        return NULL;
+    }
+    *args = 0;
+    return args + 1;
+}
+void chmod_cmd(char *args)
+{
+    char *path = second_arg(args);
+    if (path == NULL)
+    {
+        printf("usage: chmod [octal mode] [path]\n");
+        return;
+    }
+
+    if (chmod(path, strtoul(args, NULL, 8)) != 0)
+    {
+        perror("chmod");
+    }
+}
+void chown_cmd(char *args)
+{
+    char *path = second_arg(args);
+    if (path == NULL)
+    {
+        printf("usage: chown [uid] [path]\n");
+        return;
+    }
+
+    if (chown(path, atoi(args), (gid_t)-1) != 0)
+    {
+        perror("chown");
+    }
+}
+/* Copies the value of a "Key:\tvalue" line of a /proc status file, up to the next tab or newline */
+void status_field(char *status, char *key, char *value, int size)
+{
+    char *line = strstr(status, key);
+    char *src = line == NULL ? "?" : line + strlen(key) + 2;
+
+    int i = 0;
+    while (i < size - 1 && src[i] && src[i] != '\n' && src[i] != '\t')
+    {
+        value[i] = src[i];
+        i++;
+    }
+    value[i] = 0;
+}
+void ps(char *_ignore)
+{
+    DIR *dir = opendir("/proc");
+    if (NULL == dir)
+    {
+        printf("ps: cannot open /proc\n");
+        return;
+    }
+
+    printf("PID\tUID\tSTATE\tNAME\n");
+    struct dirent *entry;
+    while ((entry = readdir(dir)) != NULL)
+    {
+        if (!isdigit(entry->d_name[0]))
+        {
+            continue;
+        }
+
+        char path[LINE_MAX];
+        snprintf(path, sizeof(path), "/proc/%s/status", entry->d_name);
+        int64_t fd = open(path, O_RDONLY);
+        if (fd < 0)
+        {
+            continue;
+        }
+        char status[256] = {0};
+        int64_t n = read(fd, status, sizeof(status) - 1);
+        close(fd);
+        if (n <= 0)
+        {
+            continue;
+        }
+
+        char name[LINE_MAX], state[16], uid[16];
+        status_field(status, "Name", name, sizeof(name));
+        status_field(status, "State", state, sizeof(state));
+        status_field(status, "Uid", uid, sizeof(uid));
+        printf("%s\t%s\t%c\t%s\n", entry->d_name, uid, state[0], name);
+    }
+
+    closedir(dir);
+}
+void dmesg(char *_ignore)
+{
+    int64_t fd = open("/dev/kmsg", O_RDONLY);
+    if (fd < 0)
+    {
+        perror("dmesg");
+        return;
+    }
+
+    char buffer[512];
+    int64_t n;
+    while ((n = read(fd, buffer, sizeof(buffer))) > 0)
+    {
+        write(STDOUT_FILENO, buffer, n);
+    }
+    close(fd);
+}
+void shutdown(char *_ignore)
+{
+    reboot(RB_POWER_OFF);
+    perror("shutdown");
+}
+void reboot_cmd(char *_ignore)
+{
+    reboot(RB_AUTOBOOT);
+    perror("reboot");
+}
+/* Parses "YYYY-MM-DD HH:MM:SS", returns -1 if it is malformed */
+time_t parse_date(char *s)
+{
+    char separators[] = "-- ::";
+    long fields[6];
+    for (int i = 0; i < 6; i++)
+    {
+        char *end;
+        fields[i] = strtol(s, &end, 10);
+        if (end == s || (i < 5 && *end != separators[i]) || (i == 5 && *end != 0))
+        {
+            return -1;
+        }
+        s = end + 1;
+    }
+
+    struct tm tm = {.tm_year = fields[0] - 1900,
+                    .tm_mon = fields[1] - 1,
+                    .tm_mday = fields[2],
+                    .tm_hour = fields[3],
+                    .tm_min = fields[4],
+                    .tm_sec = fields[5]};
+    return mktime(&tm);
+}
+void date(char *args)
+{
+    if (strncmp(args, "-s", 2) == 0)
+    {
+        char *value = args + 2;
+        while (isspace(*value))
+        {
+            value++;
+        }
+        struct timeval tv = {.tv_sec = parse_date(value), .tv_usec = 0};
+        if (tv.tv_sec < 0)
+        {
+            printf("usage: date -s YYYY-MM-DD HH:MM:SS\n");
+            return;
+        }
+        if (settimeofday(&tv, NULL) != 0)
+        {
+            perror("date");
+            return;
+        }
+    }
+
+    time_t now = time(NULL);
+    char buffer[LINE_MAX];
+    strftime(buffer, sizeof(buffer), "%a %b %e %H:%M:%S %Z %Y", localtime(&now));
+    printf("%s\n", buffer);
+}